@@ -1,11 +1,24 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::Rng;
-use ruzstd::decoding::bit_reader_reverse::BitReaderReversed;
+use ruzstd::decoding::bit_reader_reverse::{BitReaderReversed, MIN_BITS_AFTER_REFILL};
 
 fn fibonacci(br: &mut BitReaderReversed, accesses: &[usize]) -> u64 {
     let mut sum = 0;
     for x in accesses {
-        sum += br.get_bits(*x).unwrap() as u64;
+        sum += br.get_bits(*x).unwrap();
+    }
+    let _ = black_box(br);
+    sum
+}
+
+// same accesses as fibonacci() but grouped so each group fits into one refill
+fn fibonacci_refill(br: &mut BitReaderReversed, groups: &[Vec<u8>]) -> u64 {
+    let mut sum = 0;
+    for group in groups {
+        br.refill();
+        for x in group {
+            sum += br.get_bits_unchecked(*x);
+        }
     }
     let _ = black_box(br);
     sum
@@ -26,12 +39,31 @@ fn criterion_benchmark(c: &mut Criterion) {
         access_vec.push(x);
     }
 
+    let mut groups: Vec<Vec<u8>> = vec![vec![]];
+    let mut group_bits = 0;
+    for x in &access_vec {
+        let x = *x as u8;
+        if group_bits + x > MIN_BITS_AFTER_REFILL {
+            groups.push(vec![]);
+            group_bits = 0;
+        }
+        group_bits += x;
+        groups.last_mut().unwrap().push(x);
+    }
+
     c.bench_function("fib 20", |b| {
         b.iter(|| {
             br.reset(&rand_vec);
             fibonacci(&mut br, &access_vec)
         })
     });
+
+    c.bench_function("fib 20 refill", |b| {
+        b.iter(|| {
+            br.reset(&rand_vec);
+            fibonacci_refill(&mut br, &groups)
+        })
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
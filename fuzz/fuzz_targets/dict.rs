@@ -0,0 +1,8 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate ruzstd;
+use ruzstd::decoding::dictionary::Dictionary;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Dictionary::decode_dict(data);
+});
@@ -0,0 +1,35 @@
+//! Embeds the decodecorpus files into the test, so the test itself does not need std::fs
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let corpus_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../decodecorpus_files")
+        .canonicalize()
+        .unwrap();
+    println!("cargo:rerun-if-changed={}", corpus_dir.display());
+
+    let mut files: Vec<_> = fs::read_dir(&corpus_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .collect();
+    files.sort();
+
+    let mut corpus = String::from("pub static CORPUS: &[(&str, &[u8], &[u8])] = &[\n");
+    for path in files {
+        let original = path.with_extension("");
+        corpus.push_str(&format!(
+            "    ({:?}, include_bytes!({:?}), include_bytes!({:?})),\n",
+            path.file_name().unwrap().to_str().unwrap(),
+            path.to_str().unwrap(),
+            original.to_str().unwrap()
+        ));
+    }
+    corpus.push_str("];\n");
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("corpus.rs");
+    fs::write(out, corpus).unwrap();
+}
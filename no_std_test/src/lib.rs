@@ -0,0 +1,74 @@
+//! Decodes the decodecorpus files with ruzstd built without std, only using ruzstd::io.
+//! This is its own crate so ruzstd is really built with `default-features = false, features = ["alloc"]`.
+//!
+//! Run with `cargo test` from this directory.
+
+#![no_std]
+
+#[cfg(test)]
+extern crate alloc;
+
+#[cfg(test)]
+mod corpus {
+    include!(concat!(env!("OUT_DIR"), "/corpus.rs"));
+}
+
+#[test]
+fn test_decode_corpus_no_std() {
+    use alloc::vec::Vec;
+    use ruzstd::io::{Cursor, Read};
+    use ruzstd::StreamingDecoder;
+
+    assert!(!corpus::CORPUS.is_empty());
+
+    for (name, compressed, original) in corpus::CORPUS {
+        let mut source = Cursor::new(*compressed);
+        let mut decoder = StreamingDecoder::new(&mut source).unwrap();
+
+        let mut result = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let read = decoder.read(&mut buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            result.extend_from_slice(&buf[..read]);
+        }
+
+        assert!(result[..] == original[..], "Decoded {} wrong", name);
+        if let Some(chksum) = decoder.decoder.get_checksum_from_data() {
+            assert_eq!(
+                decoder.decoder.get_calculated_checksum().unwrap(),
+                chksum,
+                "Checksum of {} did not match",
+                name
+            );
+        }
+    }
+}
+
+#[test]
+fn test_workspace_decoder_no_std() {
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use ruzstd::frame;
+    use ruzstd::workspace_decoder::{workspace_size, WorkspaceDecoder};
+
+    for (name, compressed, original) in corpus::CORPUS {
+        let (frame, _) = frame::read_frame_header(&mut &compressed[..]).unwrap();
+        let mut workspace = vec![0u8; workspace_size(&frame.header).unwrap()];
+        let mut decoder = WorkspaceDecoder::new(compressed, &mut workspace).unwrap();
+
+        let mut result = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let read = decoder.read(&mut buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            result.extend_from_slice(&buf[..read]);
+        }
+
+        assert!(result[..] == original[..], "Decoded {} wrong", name);
+    }
+}
@@ -0,0 +1,602 @@
+//! Prints how the frames in zstd files were encoded: each block with its literals and sequences, plus histograms over all of them.
+//! Useful to compare the output of different compressor settings.
+//!
+//! Usage: ruzstd-dump [--json] [--literals] [--no-sequences] [--dict DICT_FILE] FILE...
+extern crate ruzstd;
+use ruzstd::blocks::block::BlockType;
+use ruzstd::blocks::sequence_section::{do_offset_history, CompressionModes, ModeType};
+use ruzstd::decoding::block_observer::{BlockInfo, BlockObserver};
+use ruzstd::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex};
+
+struct Options {
+    json: bool,
+    literals: bool,
+    sequences: bool,
+    dict: Option<Vec<u8>>,
+    files: Vec<String>,
+}
+
+/// A sequence with its offset resolved against the repeat offsets
+struct SequenceRecord {
+    ll: u32,
+    ml: u32,
+    offset: u32,
+    repeat: RepeatKind,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RepeatKind {
+    New,
+    Rep1,
+    Rep2,
+    Rep3,
+    /// The first repeat offset minus one, only possible if the literal length is 0
+    Rep1Minus1,
+}
+
+const REPEAT_KINDS: [RepeatKind; 5] = [
+    RepeatKind::New,
+    RepeatKind::Rep1,
+    RepeatKind::Rep2,
+    RepeatKind::Rep3,
+    RepeatKind::Rep1Minus1,
+];
+
+impl RepeatKind {
+    fn from_offset_value(offset_value: u32, lit_len: u32) -> RepeatKind {
+        match (offset_value, lit_len > 0) {
+            (1, true) => RepeatKind::Rep1,
+            (2, true) => RepeatKind::Rep2,
+            (3, true) => RepeatKind::Rep3,
+            (1, false) => RepeatKind::Rep2,
+            (2, false) => RepeatKind::Rep3,
+            (3, false) => RepeatKind::Rep1Minus1,
+            _ => RepeatKind::New,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            RepeatKind::New => "new",
+            RepeatKind::Rep1 => "rep1",
+            RepeatKind::Rep2 => "rep2",
+            RepeatKind::Rep3 => "rep3",
+            RepeatKind::Rep1Minus1 => "rep1-1",
+        }
+    }
+}
+
+struct BlockRecord {
+    block_type: BlockType,
+    last_block: bool,
+    compressed_size: u32,
+    decompressed_size: u32,
+    /// Type, number of streams, regenerated size and huffman max_num_bits of the literals section
+    literals: Option<(String, Option<u8>, u32, Option<u8>)>,
+    literal_bytes: Vec<u8>,
+    num_sequences: u32,
+    modes: Option<CompressionModes>,
+    accuracy_logs: [Option<u8>; 3],
+    sequences: Vec<SequenceRecord>,
+}
+
+/// Collects the blocks of the current frame, they are taken out by dump_file() after each frame
+struct Collector {
+    blocks: Arc<Mutex<Vec<BlockRecord>>>,
+    literals: bool,
+}
+
+impl BlockObserver for Collector {
+    fn on_block(&mut self, block: &BlockInfo<'_>) {
+        let mut sequences = Vec::new();
+        if let Some(info) = &block.sequences {
+            let mut offset_hist = info.offset_history;
+            for seq in info.sequences.unwrap_or(&[]) {
+                let offset = do_offset_history(seq.of, seq.ll, &mut offset_hist);
+                sequences.push(SequenceRecord {
+                    ll: seq.ll,
+                    ml: seq.ml,
+                    offset,
+                    repeat: RepeatKind::from_offset_value(seq.of, seq.ll),
+                });
+            }
+        }
+
+        let literal_bytes = match (&block.sequences, self.literals) {
+            (Some(info), true) => info.literals.unwrap_or(&[]).to_vec(),
+            _ => Vec::new(),
+        };
+
+        self.blocks.lock().unwrap().push(BlockRecord {
+            block_type: block.block_type,
+            last_block: block.last_block,
+            compressed_size: block.compressed_size,
+            decompressed_size: block.decompressed_size,
+            literals: block.literals.map(|l| {
+                (
+                    format!("{}", l.section.ls_type),
+                    l.section.num_streams,
+                    l.section.regenerated_size,
+                    l.huffman_max_num_bits,
+                )
+            }),
+            literal_bytes,
+            num_sequences: block.sequences.as_ref().map_or(0, |s| s.num_sequences),
+            modes: block.sequences.as_ref().and_then(|s| s.modes),
+            accuracy_logs: match &block.sequences {
+                Some(s) => [s.ll_accuracy_log, s.of_accuracy_log, s.ml_accuracy_log],
+                None => [None; 3],
+            },
+            sequences,
+        });
+    }
+
+    fn wants_sequences(&self) -> bool {
+        true
+    }
+}
+
+/// Counts values in power of two buckets: bucket 0 holds 0, bucket n holds [2^(n-1), 2^n)
+struct Histogram {
+    buckets: [u64; 33],
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram { buckets: [0; 33] }
+    }
+
+    fn add(&mut self, value: u32) {
+        self.buckets[(32 - value.leading_zeros()) as usize] += 1;
+    }
+
+    fn bucket_name(idx: usize) -> String {
+        match idx {
+            0 => "0".to_owned(),
+            1 => "1".to_owned(),
+            _ => format!("{}-{}", 1u64 << (idx - 1), (1u64 << idx) - 1),
+        }
+    }
+
+    fn to_text(&self, name: &str) -> String {
+        let mut out = format!("  {}:\n", name);
+        for (idx, count) in self.buckets.iter().enumerate() {
+            if *count > 0 {
+                writeln!(out, "    {:>21}: {}", Self::bucket_name(idx), count).unwrap();
+            }
+        }
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let entries: Vec<String> = self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(idx, count)| format!("\"{}\":{}", Self::bucket_name(idx), count))
+            .collect();
+        format!("{{{}}}", entries.join(","))
+    }
+}
+
+struct Summary {
+    frames: u64,
+    blocks_by_type: [u64; 3],
+    sequences: u64,
+    literal_lengths: Histogram,
+    match_lengths: Histogram,
+    offsets: Histogram,
+    repeats: [u64; 5],
+}
+
+impl Summary {
+    fn new() -> Summary {
+        Summary {
+            frames: 0,
+            blocks_by_type: [0; 3],
+            sequences: 0,
+            literal_lengths: Histogram::new(),
+            match_lengths: Histogram::new(),
+            offsets: Histogram::new(),
+            repeats: [0; 5],
+        }
+    }
+
+    fn add(&mut self, block: &BlockRecord) {
+        let type_idx = match block.block_type {
+            BlockType::Raw => 0,
+            BlockType::RLE => 1,
+            _ => 2,
+        };
+        self.blocks_by_type[type_idx] += 1;
+        for seq in &block.sequences {
+            self.sequences += 1;
+            self.literal_lengths.add(seq.ll);
+            self.match_lengths.add(seq.ml);
+            self.offsets.add(seq.offset);
+            let repeat_idx = REPEAT_KINDS.iter().position(|k| *k == seq.repeat).unwrap();
+            self.repeats[repeat_idx] += 1;
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut out = String::from("summary:\n");
+        writeln!(
+            out,
+            "  frames: {}, blocks: {} raw, {} rle, {} compressed, sequences: {}",
+            self.frames,
+            self.blocks_by_type[0],
+            self.blocks_by_type[1],
+            self.blocks_by_type[2],
+            self.sequences
+        )
+        .unwrap();
+        out.push_str("  offsets by kind:");
+        for (kind, count) in REPEAT_KINDS.iter().zip(self.repeats.iter()) {
+            write!(out, " {}: {}", kind.name(), count).unwrap();
+        }
+        out.push('\n');
+        out.push_str(&self.literal_lengths.to_text("literal lengths"));
+        out.push_str(&self.match_lengths.to_text("match lengths"));
+        out.push_str(&self.offsets.to_text("offsets"));
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let repeats: Vec<String> = REPEAT_KINDS
+            .iter()
+            .zip(self.repeats.iter())
+            .map(|(kind, count)| format!("\"{}\":{}", kind.name(), count))
+            .collect();
+        format!(
+            "{{\"frames\":{},\"raw_blocks\":{},\"rle_blocks\":{},\"compressed_blocks\":{},\"sequences\":{},\"offset_kinds\":{{{}}},\"literal_lengths\":{},\"match_lengths\":{},\"offsets\":{}}}",
+            self.frames,
+            self.blocks_by_type[0],
+            self.blocks_by_type[1],
+            self.blocks_by_type[2],
+            self.sequences,
+            repeats.join(","),
+            self.literal_lengths.to_json(),
+            self.match_lengths.to_json(),
+            self.offsets.to_json(),
+        )
+    }
+}
+
+fn mode_name(mode: ModeType) -> &'static str {
+    match mode {
+        ModeType::Predefined => "Predefined",
+        ModeType::RLE => "RLE",
+        ModeType::FSECompressed => "FSECompressed",
+        ModeType::Repeat => "Repeat",
+    }
+}
+
+fn json_opt<T: std::fmt::Display>(value: Option<T>) -> String {
+    match value {
+        Some(v) => format!("{}", v),
+        None => "null".to_owned(),
+    }
+}
+
+fn json_bytes(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7E => out.push(b as char),
+            _ => write!(out, "\\u{:04x}", b).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn text_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x20..=0x7E => (b as char).to_string(),
+            _ => format!("\\x{:02x}", b),
+        })
+        .collect()
+}
+
+fn block_to_text(idx: usize, block: &BlockRecord, options: &Options) -> String {
+    let mut out = format!(
+        "  block {}: {}, {} -> {} bytes{}\n",
+        idx,
+        block.block_type,
+        block.compressed_size,
+        block.decompressed_size,
+        if block.last_block { ", last" } else { "" }
+    );
+    if let Some((ls_type, streams, size, max_bits)) = &block.literals {
+        write!(out, "    literals: {}, {} bytes", ls_type, size).unwrap();
+        if let Some(streams) = streams {
+            write!(out, ", {} streams", streams).unwrap();
+        }
+        if let Some(max_bits) = max_bits {
+            write!(out, ", huffman max_num_bits {}", max_bits).unwrap();
+        }
+        out.push('\n');
+        if options.literals {
+            writeln!(out, "      \"{}\"", text_bytes(&block.literal_bytes)).unwrap();
+        }
+    }
+    if block.literals.is_some() {
+        write!(out, "    sequences: {}", block.num_sequences).unwrap();
+        if let Some(modes) = block.modes {
+            let names = ["LL", "OF", "ML"];
+            let modes = [modes.ll_mode(), modes.of_mode(), modes.ml_mode()];
+            for ((name, mode), acc_log) in names
+                .iter()
+                .zip(modes.iter())
+                .zip(block.accuracy_logs.iter())
+            {
+                write!(out, ", {} {}", name, mode_name(*mode)).unwrap();
+                if let Some(acc_log) = acc_log {
+                    write!(out, " (accuracy log {})", acc_log).unwrap();
+                }
+            }
+        }
+        out.push('\n');
+    }
+    if options.sequences {
+        let mut literals_pos = 0;
+        for seq in &block.sequences {
+            write!(
+                out,
+                "      ll {:>6} ml {:>6} offset {:>8} {}",
+                seq.ll,
+                seq.ml,
+                seq.offset,
+                seq.repeat.name()
+            )
+            .unwrap();
+            if options.literals {
+                let end = std::cmp::min(literals_pos + seq.ll as usize, block.literal_bytes.len());
+                write!(
+                    out,
+                    " \"{}\"",
+                    text_bytes(&block.literal_bytes[literals_pos..end])
+                )
+                .unwrap();
+                literals_pos = end;
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn block_to_json(block: &BlockRecord, options: &Options) -> String {
+    let mut out = format!(
+        "{{\"type\":\"{}\",\"last\":{},\"compressed_size\":{},\"decompressed_size\":{}",
+        block.block_type, block.last_block, block.compressed_size, block.decompressed_size
+    );
+    if let Some((ls_type, streams, size, max_bits)) = &block.literals {
+        write!(
+            out,
+            ",\"literals\":{{\"type\":\"{}\",\"streams\":{},\"size\":{},\"huffman_max_num_bits\":{}",
+            ls_type,
+            json_opt(*streams),
+            size,
+            json_opt(*max_bits)
+        )
+        .unwrap();
+        if options.literals {
+            write!(out, ",\"bytes\":{}", json_bytes(&block.literal_bytes)).unwrap();
+        }
+        out.push('}');
+
+        write!(out, ",\"num_sequences\":{}", block.num_sequences).unwrap();
+        if let Some(modes) = block.modes {
+            write!(
+                out,
+                ",\"modes\":{{\"ll\":\"{}\",\"of\":\"{}\",\"ml\":\"{}\"}}",
+                mode_name(modes.ll_mode()),
+                mode_name(modes.of_mode()),
+                mode_name(modes.ml_mode())
+            )
+            .unwrap();
+        }
+        write!(
+            out,
+            ",\"accuracy_logs\":{{\"ll\":{},\"of\":{},\"ml\":{}}}",
+            json_opt(block.accuracy_logs[0]),
+            json_opt(block.accuracy_logs[1]),
+            json_opt(block.accuracy_logs[2])
+        )
+        .unwrap();
+    }
+    if options.sequences && block.literals.is_some() {
+        let sequences: Vec<String> = block
+            .sequences
+            .iter()
+            .map(|seq| {
+                format!(
+                    "{{\"ll\":{},\"ml\":{},\"offset\":{},\"kind\":\"{}\"}}",
+                    seq.ll,
+                    seq.ml,
+                    seq.offset,
+                    seq.repeat.name()
+                )
+            })
+            .collect();
+        write!(out, ",\"sequences\":[{}]", sequences.join(",")).unwrap();
+    }
+    out.push('}');
+    out
+}
+
+/// Skips a skippable frame if the source starts with one. Returns false if the source is empty
+fn skip_skippable_frames(source: &mut dyn BufRead) -> Result<bool, String> {
+    loop {
+        let buf = source.fill_buf().map_err(|e| e.to_string())?;
+        if buf.is_empty() {
+            return Ok(false);
+        }
+        if buf.len() < 4 {
+            return Ok(true);
+        }
+        let magic = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        if magic & 0xFFFF_FFF0 != 0x184D_2A50 {
+            return Ok(true);
+        }
+        let mut header = [0u8; 8];
+        source.read_exact(&mut header).map_err(|e| e.to_string())?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let skipped = std::io::copy(&mut source.take(u64::from(len)), &mut std::io::sink())
+            .map_err(|e| e.to_string())?;
+        if skipped != u64::from(len) {
+            return Err("Skippable frame is truncated".to_owned());
+        }
+    }
+}
+
+fn dump_file(path: &str, options: &Options, summary: &mut Summary) -> Result<String, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut source = BufReader::new(file);
+
+    let blocks = Arc::new(Mutex::new(Vec::new()));
+    let mut frame_dec = FrameDecoder::new();
+    if let Some(raw_dict) = &options.dict {
+        frame_dec.add_dict(raw_dict).map_err(|e| e.to_string())?;
+    }
+    frame_dec.set_block_observer(Box::new(Collector {
+        blocks: Arc::clone(&blocks),
+        literals: options.literals,
+    }));
+
+    let mut frames = Vec::new();
+    while skip_skippable_frames(&mut source)? {
+        frame_dec.reset(&mut source).map_err(|e| e.to_string())?;
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .map_err(|e| e.to_string())?;
+        frame_dec
+            .collect_to_writer(&mut std::io::sink())
+            .map_err(|e| e.to_string())?;
+
+        let frame_blocks = std::mem::take(&mut *blocks.lock().unwrap());
+        summary.frames += 1;
+        for block in &frame_blocks {
+            summary.add(block);
+        }
+
+        let content_size = frame_dec.content_size();
+        if options.json {
+            let blocks: Vec<String> = frame_blocks
+                .iter()
+                .map(|b| block_to_json(b, options))
+                .collect();
+            frames.push(format!(
+                "{{\"content_size\":{},\"blocks\":[{}]}}",
+                json_opt(content_size),
+                blocks.join(",")
+            ));
+        } else {
+            let mut out = format!(
+                "frame {}: content size {}\n",
+                frames.len(),
+                content_size.map_or("unknown".to_owned(), |size| size.to_string())
+            );
+            for (idx, block) in frame_blocks.iter().enumerate() {
+                out.push_str(&block_to_text(idx, block, options));
+            }
+            frames.push(out);
+        }
+    }
+
+    if options.json {
+        Ok(format!(
+            "{{\"file\":{},\"frames\":[{}]}}",
+            json_bytes(path.as_bytes()),
+            frames.join(",")
+        ))
+    } else {
+        Ok(format!("{}:\n{}", path, frames.concat()))
+    }
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        json: false,
+        literals: false,
+        sequences: true,
+        dict: None,
+        files: Vec::new(),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--literals" => options.literals = true,
+            "--no-sequences" => options.sequences = false,
+            "--dict" => {
+                let path = args.next().ok_or("--dict needs a file")?;
+                let mut raw = Vec::new();
+                File::open(&path)
+                    .and_then(|mut f| f.read_to_end(&mut raw))
+                    .map_err(|e| format!("Could not read dictionary {}: {}", path, e))?;
+                options.dict = Some(raw);
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown flag: {}", arg)),
+            _ => options.files.push(arg),
+        }
+    }
+    if options.files.is_empty() {
+        return Err("No files given".to_owned());
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Usage: ruzstd-dump [--json] [--literals] [--no-sequences] [--dict DICT_FILE] FILE...");
+            std::process::exit(1);
+        }
+    };
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    let mut summary = Summary::new();
+    let mut files = Vec::new();
+    for path in &options.files {
+        match dump_file(path, &options, &mut summary) {
+            Ok(out) => {
+                if options.json {
+                    files.push(out);
+                } else {
+                    stdout.write_all(out.as_bytes()).unwrap();
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to dump {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if options.json {
+        writeln!(
+            stdout,
+            "{{\"files\":[{}],\"summary\":{}}}",
+            files.join(","),
+            summary.to_json()
+        )
+        .unwrap();
+    } else {
+        stdout.write_all(summary.to_text().as_bytes()).unwrap();
+    }
+}
@@ -1,27 +1,26 @@
 extern crate ruzstd;
+use ruzstd::decoding::progress::Progress;
 use std::fs::File;
 use std::io::Read;
 use std::io::Write;
 
-struct StateTracker {
-    bytes_used: u64,
-    old_percentage: i8,
-}
-
 fn main() {
     let mut file_paths: Vec<_> = std::env::args().filter(|f| !f.starts_with('-')).collect();
     let flags: Vec<_> = std::env::args().filter(|f| f.starts_with('-')).collect();
     file_paths.remove(0);
 
     if !flags.contains(&"-d".to_owned()) {
+        eprintln!("This zstd implementation only supports decompression. Please add a \"-d\" flag");
         return;
     }
 
     if !flags.contains(&"-c".to_owned()) {
+        eprintln!("This zstd implementation only supports output on the stdout. Please add a \"-c\" flag and pipe the output into a file");
         return;
     }
 
     if flags.len() != 2 {
+        eprintln!(
             "No flags other than -d and -c are currently implemented. Flags used: {:?}",
             flags
         );
@@ -29,13 +28,19 @@ fn main() {
     }
 
     let mut frame_dec = ruzstd::FrameDecoder::new();
+    let mut old_percentage = None;
+    frame_dec.set_progress_hook(Box::new(move |progress: &Progress| {
+        if let Some(content_size) = progress.content_size.filter(|size| *size > 0) {
+            let percentage = (progress.bytes_decoded * 100) / content_size;
+            if old_percentage != Some((progress.frame_index, percentage)) {
+                eprint!("\r");
+                eprint!("{} % done", percentage);
+                old_percentage = Some((progress.frame_index, percentage));
+            }
+        }
+    }));
 
     for path in file_paths {
-        let mut tracker = StateTracker {
-            bytes_used: 0,
-            old_percentage: -1,
-        };
-
         let mut f = File::open(path).unwrap();
 
         frame_dec.reset(&mut f).unwrap();
@@ -52,15 +57,8 @@ fn main() {
                 let x = frame_dec.read(result.as_mut_slice()).unwrap();
 
                 result.resize(x, 0);
-                do_something(&result, &mut tracker);
+                do_something(&result);
                 result.resize(result.capacity(), 0);
-
-                let percentage = (tracker.bytes_used * 100) / frame_dec.content_size().unwrap();
-                if percentage as i8 != tracker.old_percentage {
-                    eprint!("\r");
-                    eprint!("{} % done", percentage);
-                    tracker.old_percentage = percentage as i8;
-                }
             }
         }
 
@@ -69,7 +67,7 @@ fn main() {
             let x = frame_dec.read(result.as_mut_slice()).unwrap();
 
             result.resize(x, 0);
-            do_something(&result, &mut tracker);
+            do_something(&result);
             result.resize(result.capacity(), 0);
         }
 
@@ -77,19 +75,21 @@ fn main() {
         match frame_dec.get_checksum_from_data() {
             Some(chksum) => {
                 if frame_dec.get_calculated_checksum().unwrap() != chksum {
+                    eprintln!(
                         "Checksum did not match! From data: {}, calculated while decoding: {}",
                         chksum,
                         frame_dec.get_calculated_checksum().unwrap()
                     );
                 } else {
+                    eprintln!("Checksums are ok!");
                 }
             }
+            None => eprintln!("Dont check checksum because none was provided"),
         }
     }
 }
 
-fn do_something(data: &[u8], s: &mut StateTracker) {
+fn do_something(data: &[u8]) {
     //Do something. Like writing it to a file or to stdout...
     std::io::stdout().write_all(data).unwrap();
-    s.bytes_used += data.len() as u64;
 }
@@ -1,4 +1,5 @@
 use core::fmt;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlockType {
     Raw,
     RLE,
@@ -17,6 +18,7 @@ impl fmt::Display for BlockType {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct BlockHeader {
     pub last_block: bool,
     pub block_type: BlockType,
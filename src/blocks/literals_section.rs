@@ -1,6 +1,8 @@
 use crate::decoding::bit_reader::BitReader;
+use crate::errors::LiteralsSectionParseError;
 use core::fmt;
 
+#[derive(Debug, Clone, Copy)]
 pub struct LiteralsSection {
     pub regenerated_size: u32,
     pub compressed_size: Option<u32>,
@@ -8,6 +10,7 @@ pub struct LiteralsSection {
     pub ls_type: LiteralsSectionType,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LiteralsSectionType {
     Raw,
     RLE,
@@ -42,7 +45,7 @@ impl LiteralsSection {
         }
     }
 
-    pub fn header_bytes_needed(&self, first_byte: u8) -> Result<u8, String> {
+    pub fn header_bytes_needed(&self, first_byte: u8) -> Result<u8, LiteralsSectionParseError> {
         let ls_type = Self::section_type(first_byte)?;
         let size_format = (first_byte >> 2) & 0x3;
         match ls_type {
@@ -92,7 +95,7 @@ impl LiteralsSection {
         }
     }
 
-    pub fn parse_from_header(&mut self, raw: &[u8]) -> Result<u8, String> {
+    pub fn parse_from_header(&mut self, raw: &[u8]) -> Result<u8, LiteralsSectionParseError> {
         let mut br = BitReader::new(raw);
         let t = br.get_bits(2)? as u8;
         self.ls_type = Self::section_type(t)?;
@@ -100,11 +103,10 @@ impl LiteralsSection {
 
         let byte_needed = self.header_bytes_needed(raw[0])?;
         if raw.len() < byte_needed as usize {
-            return Err(format!(
-                "Not enough byte to parse the literals section header. Have: {}, Want: {}",
-                raw.len(),
-                byte_needed
-            ));
+            return Err(LiteralsSectionParseError::NotEnoughBytes {
+                have: raw.len(),
+                need: byte_needed,
+            });
         }
 
         match self.ls_type {
@@ -140,7 +142,7 @@ impl LiteralsSection {
                     0 => {
                         self.num_streams = Some(1);
                     }
-                    1 | 2 | 3 => {
+                    1..=3 => {
                         self.num_streams = Some(4);
                     }
                     _ => panic!(
@@ -197,17 +199,14 @@ impl LiteralsSection {
         }
     }
 
-    fn section_type(raw: u8) -> Result<LiteralsSectionType, String> {
+    fn section_type(raw: u8) -> Result<LiteralsSectionType, LiteralsSectionParseError> {
         let t = raw & 0x3;
         match t {
             0 => Ok(LiteralsSectionType::Raw),
             1 => Ok(LiteralsSectionType::RLE),
             2 => Ok(LiteralsSectionType::Compressed),
             3 => Ok(LiteralsSectionType::Treeless),
-            _ => Err(format!(
-                "Illegal literalssectiontype. Is: {}, must be in: 0,1,2,3",
-                t
-            )),
+            _ => Err(LiteralsSectionParseError::IllegalLiteralSectionType { got: t }),
         }
     }
 }
@@ -1,10 +1,12 @@
+use crate::errors::SequencesHeaderParseError;
 use core::fmt;
+#[derive(Debug, Clone, Copy)]
 pub struct SequencesHeader {
     pub num_sequences: u32,
     pub modes: Option<CompressionModes>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Sequence {
     pub ll: u32,
     pub ml: u32,
@@ -17,8 +19,9 @@ impl fmt::Display for Sequence {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct CompressionModes(u8);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ModeType {
     Predefined,
     RLE,
@@ -64,13 +67,13 @@ impl SequencesHeader {
         }
     }
 
-    pub fn parse_from_header(&mut self, source: &[u8]) -> Result<u8, String> {
+    pub fn parse_from_header(&mut self, source: &[u8]) -> Result<u8, SequencesHeaderParseError> {
         let mut bytes_read = 0;
         if source.is_empty() {
-            return Err(format!(
-                "source must have at least {} bytes to parse header",
-                1
-            ));
+            return Err(SequencesHeaderParseError::NotEnoughBytes {
+                need_at_least: 1,
+                got: source.len(),
+            });
         }
 
         let source = match source[0] {
@@ -80,10 +83,10 @@ impl SequencesHeader {
             }
             1..=127 => {
                 if source.len() < 2 {
-                    return Err(format!(
-                        "source must have at least {} bytes to parse header",
-                        2
-                    ));
+                    return Err(SequencesHeaderParseError::NotEnoughBytes {
+                need_at_least: 2,
+                got: source.len(),
+            });
                 }
                 self.num_sequences = source[0] as u32;
                 bytes_read += 1;
@@ -91,10 +94,10 @@ impl SequencesHeader {
             }
             128..=254 => {
                 if source.len() < 3 {
-                    return Err(format!(
-                        "source must have at least {} bytes to parse header",
-                        3
-                    ));
+                    return Err(SequencesHeaderParseError::NotEnoughBytes {
+                need_at_least: 3,
+                got: source.len(),
+            });
                 }
                 self.num_sequences = ((source[0] as u32 - 128) << 8) + source[1] as u32;
                 bytes_read += 2;
@@ -102,10 +105,10 @@ impl SequencesHeader {
             }
             255 => {
                 if source.len() < 4 {
-                    return Err(format!(
-                        "source must have at least {} bytes to parse header",
-                        4
-                    ));
+                    return Err(SequencesHeaderParseError::NotEnoughBytes {
+                need_at_least: 4,
+                got: source.len(),
+            });
                 }
                 self.num_sequences = source[1] as u32 + ((source[2] as u32) << 8) + 0x7F00;
                 bytes_read += 3;
@@ -119,3 +122,140 @@ impl SequencesHeader {
         Ok(bytes_read)
     }
 }
+
+/// Returns the baseline and the number of extra bits for a literal length code
+pub fn lookup_ll_code(code: u8) -> (u32, u8) {
+    match code {
+        0..=15 => (code as u32, 0),
+        16 => (16, 1),
+        17 => (18, 1),
+        18 => (20, 1),
+        19 => (22, 1),
+        20 => (24, 2),
+        21 => (28, 2),
+        22 => (32, 3),
+        23 => (40, 3),
+        24 => (48, 4),
+        25 => (64, 6),
+        26 => (128, 7),
+        27 => (256, 8),
+        28 => (512, 9),
+        29 => (1024, 10),
+        30 => (2048, 11),
+        31 => (4096, 12),
+        32 => (8192, 13),
+        33 => (16384, 14),
+        34 => (32768, 15),
+        35 => (65536, 16),
+        _ => (0, 255),
+    }
+}
+
+/// Returns the baseline and the number of extra bits for a match length code
+pub fn lookup_ml_code(code: u8) -> (u32, u8) {
+    match code {
+        0..=31 => (code as u32 + 3, 0),
+        32 => (35, 1),
+        33 => (37, 1),
+        34 => (39, 1),
+        35 => (41, 1),
+        36 => (43, 2),
+        37 => (47, 2),
+        38 => (51, 3),
+        39 => (59, 3),
+        40 => (67, 4),
+        41 => (83, 4),
+        42 => (99, 5),
+        43 => (131, 7),
+        44 => (259, 8),
+        45 => (515, 9),
+        46 => (1027, 10),
+        47 => (2051, 11),
+        48 => (4099, 12),
+        49 => (8195, 13),
+        50 => (16387, 14),
+        51 => (32771, 15),
+        52 => (65539, 16),
+        _ => (0, 255),
+    }
+}
+
+pub const LL_MAX_LOG: u8 = 9;
+pub const ML_MAX_LOG: u8 = 9;
+pub const OF_MAX_LOG: u8 = 8;
+
+pub const LL_DEFAULT_ACC_LOG: u8 = 6;
+pub const LITERALS_LENGTH_DEFAULT_DISTRIBUTION: [i32; 36] = [
+    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1,
+    -1, -1, -1, -1,
+];
+
+pub const ML_DEFAULT_ACC_LOG: u8 = 6;
+pub const MATCH_LENGTH_DEFAULT_DISTRIBUTION: [i32; 53] = [
+    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+];
+
+pub const OF_DEFAULT_ACC_LOG: u8 = 5;
+pub const OFFSET_DEFAULT_DISTRIBUTION: [i32; 29] = [
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+];
+
+/// Resolves the offset_value of a sequence into the actual offset and updates the repeat offsets accordingly
+pub fn do_offset_history(offset_value: u32, lit_len: u32, scratch: &mut [u32; 3]) -> u32 {
+    let actual_offset = if lit_len > 0 {
+        match offset_value {
+            1..=3 => scratch[offset_value as usize - 1],
+            _ => {
+                //new offset
+                offset_value - 3
+            }
+        }
+    } else {
+        match offset_value {
+            1..=2 => scratch[offset_value as usize],
+            3 => scratch[0] - 1,
+            _ => {
+                //new offset
+                offset_value - 3
+            }
+        }
+    };
+
+    //update history
+    if lit_len > 0 {
+        match offset_value {
+            1 => {
+                //nothing
+            }
+            2 => {
+                scratch[1] = scratch[0];
+                scratch[0] = actual_offset;
+            }
+            _ => {
+                scratch[2] = scratch[1];
+                scratch[1] = scratch[0];
+                scratch[0] = actual_offset;
+            }
+        }
+    } else {
+        match offset_value {
+            1 => {
+                scratch[1] = scratch[0];
+                scratch[0] = actual_offset;
+            }
+            2 => {
+                scratch[2] = scratch[1];
+                scratch[1] = scratch[0];
+                scratch[0] = actual_offset;
+            }
+            _ => {
+                scratch[2] = scratch[1];
+                scratch[1] = scratch[0];
+                scratch[0] = actual_offset;
+            }
+        }
+    }
+
+    actual_offset
+}
@@ -1,14 +1,12 @@
-#[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
-#[cfg(feature = "alloc")]
-use alloc::string::String;
+use crate::errors::GetBitsError;
+
 pub struct BitReader<'s> {
     idx: usize, //index counts bits already read
     source: &'s [u8],
 }
 
 impl<'s> BitReader<'s> {
-    pub fn new(source: &'s [u8]) -> BitReader<'_> {
+    pub fn new(source: &'s [u8]) -> BitReader<'s> {
         BitReader { idx: 0, source }
     }
 
@@ -27,16 +25,18 @@ impl<'s> BitReader<'s> {
         self.idx -= n;
     }
 
-    pub fn get_bits(&mut self, n: usize) -> Result<u64, String> {
+    pub fn get_bits(&mut self, n: usize) -> Result<u64, GetBitsError> {
         if n > 64 {
-            return Err("Cant serve this request. The reader is limited to 64bit".to_owned());
+            return Err(GetBitsError::TooManyBits {
+                num_requested_bits: n,
+                limit: 64,
+            });
         }
         if self.bits_left() < n {
-            return Err(format!(
-                "Cant read n: {} bits. Bits left: {}",
-                n,
-                self.bits_left()
-            ));
+            return Err(GetBitsError::NotEnoughRemainingBits {
+                requested: n,
+                remaining: self.bits_left(),
+            });
         }
 
         let old_idx = self.idx;
@@ -1,24 +1,34 @@
+use crate::errors::GetBitsError;
 use byteorder::ByteOrder;
 use byteorder::LittleEndian;
-#[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
-#[cfg(feature = "alloc")]
-use alloc::string::String;
 
+/// Reads bits from the back of a bitstream towards the front, like the zstd bitstreams are written.
+///
+/// There are two ways to use this reader:
+/// 1. get_bits() is checked and refills as needed. This is what you want for anything that is not in a hot loop.
+/// 2. refill() followed by up to MIN_BITS_AFTER_REFILL bits worth of peek_bits()/consume()/get_bits_unchecked().
+///    These do not check anything, so the caller needs to make sure it does not read more bits than it refilled.
+///
+/// Reading past the start of the source is allowed and yields zero bits, bits_remaining() then becomes negative.
+/// The decoders use this to detect the end of the streams.
 pub struct BitReaderReversed<'s> {
-    idx: isize, //index counts bits already read
+    idx: isize, //index counts bits not yet loaded into the container. Goes negative when zeros get loaded past the start of the source
     source: &'s [u8],
 
+    /// The lowest bits_in_container bits are the next bits to read, the highest of those first. The bits above that are garbage
     bit_container: u64,
     bits_in_container: u8,
 }
 
+/// After a refill() at least this many bits can be read with the unchecked functions
+pub const MIN_BITS_AFTER_REFILL: u8 = 56;
+
 impl<'s> BitReaderReversed<'s> {
     pub fn bits_remaining(&self) -> isize {
         self.idx + self.bits_in_container as isize
     }
 
-    pub fn new(source: &'s [u8]) -> BitReaderReversed<'_> {
+    pub fn new(source: &'s [u8]) -> BitReaderReversed<'s> {
         BitReaderReversed {
             idx: source.len() as isize * 8,
             source,
@@ -27,96 +37,74 @@ impl<'s> BitReaderReversed<'s> {
         }
     }
 
-    fn refill_container(&mut self) {
-        let want_to_read = 64 - self.bits_in_container;
-        let can_read = if want_to_read as isize > self.idx {
-            self.idx
+    /// Fills the container so at least MIN_BITS_AFTER_REFILL bits can be read without checks.
+    /// This does not change bits_remaining()
+    #[inline(always)]
+    pub fn refill(&mut self) {
+        if self.bits_in_container >= MIN_BITS_AFTER_REFILL {
+            return;
+        }
+        // load as many whole bytes as fit into the container, this is between 1 and 7 bytes
+        let bytes = (63 - self.bits_in_container as usize) / 8;
+        let bits = bytes * 8;
+
+        if self.idx >= 64 {
+            // fast path: there are 8 bytes before the current position, read them all in one go and keep the ones we want
+            let end = self.idx as usize / 8;
+            let value = LittleEndian::read_u64(&self.source[end - 8..end]);
+            self.bit_container = (self.bit_container << bits) | (value >> (64 - bits));
         } else {
-            want_to_read as isize
-        };
-
-        match can_read {
-            64 => {
-                self.bit_container = LittleEndian::read_u64(&self.source[self.byte_idx() - 7..]);
-                self.bits_in_container += 64;
-                self.idx -= 64;
-            }
-            48..=63 => {
-                self.bit_container <<= 48;
-                self.bits_in_container += 48;
-                self.bit_container |= LittleEndian::read_u48(&self.source[self.byte_idx() - 5..]);
-                self.idx -= 48;
-            }
-            32..=47 => {
-                self.bit_container <<= 32;
-                self.bits_in_container += 32;
-                self.bit_container |=
-                    LittleEndian::read_u32(&self.source[self.byte_idx() - 3..]) as u64;
-                self.idx -= 32;
-            }
-            16..=31 => {
-                self.bit_container <<= 16;
-                self.bits_in_container += 16;
-                self.bit_container |=
-                    LittleEndian::read_u16(&self.source[self.byte_idx() - 1..]) as u64;
-                self.idx -= 16;
-            }
-            8..=15 => {
-                self.bit_container <<= 8;
-                self.bits_in_container += 8;
-                self.bit_container |= self.source[self.byte_idx()] as u64;
-                self.idx -= 8;
-            }
-            _ => panic!("For now panic"),
+            self.bit_container = refill_slow(self.source, self.idx, self.bit_container, bytes);
         }
+        self.bits_in_container += bits as u8;
+        self.idx -= bits as isize;
+    }
+
+    /// Returns the next n bits without consuming them. n must not be bigger than the bits refilled since the last consume()
+    #[inline(always)]
+    pub fn peek_bits(&self, n: u8) -> u64 {
+        debug_assert!(n <= self.bits_in_container);
+        let mask = (1u64 << n) - 1;
+        (self.bit_container >> (self.bits_in_container - n)) & mask
     }
 
-    fn byte_idx(&self) -> usize {
-        (self.idx as usize - 1) / 8
+    /// Throws away the next n bits. n must not be bigger than the bits refilled since the last consume()
+    #[inline(always)]
+    pub fn consume(&mut self, n: u8) {
+        debug_assert!(n <= self.bits_in_container);
+        self.bits_in_container -= n;
     }
 
-    pub fn get_bits(&mut self, n: usize) -> Result<u64, String> {
+    /// peek_bits() and consume() in one go. Same rules apply
+    #[inline(always)]
+    pub fn get_bits_unchecked(&mut self, n: u8) -> u64 {
+        let value = self.peek_bits(n);
+        self.consume(n);
+        value
+    }
+
+    pub fn get_bits(&mut self, n: usize) -> Result<u64, GetBitsError> {
         if n == 0 {
             return Ok(0);
         }
         if n > 64 {
-            return Err("Cant serve this request. The reader is limited to 64bit".to_owned());
-        }
-
-        let n = n as isize;
-
-        if self.bits_remaining() <= 0 {
-            self.idx -= n;
-            return Ok(0);
+            return Err(GetBitsError::TooManyBits {
+                num_requested_bits: n,
+                limit: 64,
+            });
         }
-
-        if self.bits_remaining() < n {
-            let emulated_read_shift = n - self.bits_remaining();
-            let v = self.get_bits(self.bits_remaining() as usize)?;
-            assert!(self.idx == 0);
-            let value = v << emulated_read_shift;
-            self.idx -= emulated_read_shift;
-            return Ok(value);
+        if n > MIN_BITS_AFTER_REFILL as usize {
+            // the first read bits are the higher ones
+            let high = self.get_bits(n - 32)?;
+            let low = self.get_bits(32)?;
+            return Ok((high << 32) | low);
         }
 
-        if (self.bits_in_container as isize) < n {
-            while (self.bits_in_container <= 56) && (self.bits_in_container as isize) < n {
-                self.refill_container();
-            }
-            if (self.bits_in_container as isize) < n {
-                return Err(format!("Cant fullfill read of {} bytes on reversed bitreader even after refill. Would need a bigger container", n));
-            }
+        let n = n as u8;
+        if self.bits_in_container < n {
+            self.refill();
         }
-
-        //if we reach this point there are enough bits in the container
-        let value = self.bit_container >> (self.bits_in_container as isize - n);
-        self.bits_in_container -= n as u8;
-        let value_masked = value & ((1 << n) - 1);
-
-
-        assert!(value_masked < (1 << n));
-
-        Ok(value_masked)
+        Ok(self.get_bits_unchecked(n))
     }
 
     pub fn reset(&mut self, new_source: &'s [u8]) {
@@ -126,3 +114,19 @@ impl<'s> BitReaderReversed<'s> {
         self.bits_in_container = 0;
     }
 }
+
+/// Loads the bytes one by one, padding with zeros past the start of the source.
+/// This is a free function so the reader itself does not need to live in memory in the hot loops
+#[cold]
+fn refill_slow(source: &[u8], mut idx: isize, mut bit_container: u64, bytes: usize) -> u64 {
+    for _ in 0..bytes {
+        let byte = if idx > 0 {
+            source[idx as usize / 8 - 1]
+        } else {
+            0
+        };
+        bit_container = (bit_container << 8) | byte as u64;
+        idx -= 8;
+    }
+    bit_container
+}
@@ -5,13 +5,13 @@ use super::super::blocks::literals_section::LiteralsSectionType;
 use super::super::blocks::sequence_section::SequencesHeader;
 use super::literals_section_decoder::decode_literals;
 use super::sequence_section_decoder::decode_sequences;
+use crate::decoding::block_observer::{BlockInfo, LiteralsInfo, SequencesInfo};
 use crate::decoding::scratch::DecoderScratch;
 use crate::decoding::sequence_execution::execute_sequences;
-use crate::io::Read;
-#[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
-#[cfg(feature = "alloc")]
-use alloc::string::String;
+use crate::errors::{BlockHeaderReadError, DecodeBlockContentError, DecompressBlockError};
+use crate::fse::FSETable;
+use crate::io::{BufRead, Read};
+use core::mem;
 
 pub struct BlockDecoder {
     header_buffer: [u8; 3],
@@ -21,7 +21,7 @@ pub struct BlockDecoder {
 enum DecoderState {
     ReadyToDecodeNextHeader,
     ReadyToDecodeNextBody,
-    #[allow(dead_code)]
+    /// The content of the last block has been consumed but could not be decoded
     Failed, //TODO put "self.internal_state = DecoderState::Failed;" everywhere a unresolveable error occurs
 }
 
@@ -32,7 +32,7 @@ pub fn new() -> BlockDecoder {
     }
 }
 
-const ABSOLUTE_MAXIMUM_BLOCK_SIZE: u32 = 128 * 1024;
+pub const ABSOLUTE_MAXIMUM_BLOCK_SIZE: u32 = 128 * 1024;
 
 impl BlockDecoder {
     pub fn decode_block_content(
@@ -40,74 +40,64 @@ impl BlockDecoder {
         header: &BlockHeader,
         workspace: &mut DecoderScratch, //reuse this as often as possible. Not only if the trees are reused but also reuse the allocations when building new trees
         source: &mut dyn Read,
-    ) -> Result<u64, String> {
+    ) -> Result<u64, DecodeBlockContentError> {
         match self.internal_state {
-            DecoderState::ReadyToDecodeNextBody => {/* Happy :) */},
-            DecoderState::Failed => return Err("Cant decode next block if failed along the way. Results will be nonsense".to_owned()),
-            DecoderState::ReadyToDecodeNextHeader => return Err("Cant decode next block body, while expecting to decode the header of the previous block. Results will be nonsense".to_owned()),
+            DecoderState::ReadyToDecodeNextBody => { /* Happy :) */ }
+            DecoderState::Failed => return Err(DecodeBlockContentError::DecoderStateIsFailed),
+            DecoderState::ReadyToDecodeNextHeader => {
+                return Err(DecodeBlockContentError::ExpectedHeaderOfPreviousBlock)
+            }
         }
 
         match header.block_type {
             BlockType::RLE => {
-                const BATCH_SIZE: usize = 512;
-                let mut buf = [0u8; BATCH_SIZE];
-                let full_reads = header.decompressed_size / BATCH_SIZE as u32;
-                let single_read_size = header.decompressed_size % BATCH_SIZE as u32;
-
-                match source.read_exact(&mut buf[0..1]) {
+                workspace
+                    .buffer
+                    .check_output_limit(header.decompressed_size as usize)?;
+                let mut byte = [0u8; 1];
+                match source.read_exact(&mut byte) {
                     Ok(_) => {
                         self.internal_state = DecoderState::ReadyToDecodeNextHeader;
                     }
-                    Err(_) => return Err("Error while reading the one RLE byte".to_owned()),
-                }
-
-                for i in 1..BATCH_SIZE {
-                    buf[i] = buf[0];
+                    Err(e) => {
+                        return Err(DecodeBlockContentError::ReadError {
+                            step: header.block_type,
+                            source: e,
+                        })
+                    }
                 }
 
-                for _ in 0..full_reads {
-                    workspace.buffer.push(&buf[..]);
-                }
-                let smaller = &mut buf[..single_read_size as usize];
-                workspace.buffer.push(smaller);
+                workspace
+                    .buffer
+                    .push_rle(byte[0], header.decompressed_size as usize);
+                notify_observer(workspace, header);
 
                 Ok(1)
             }
             BlockType::Raw => {
-                const BATCH_SIZE: usize = 128*1024;
-                let mut buf = [0u8; BATCH_SIZE];
-                let full_reads = header.decompressed_size / BATCH_SIZE as u32;
-                let single_read_size = header.decompressed_size % BATCH_SIZE as u32;
-
-                for _ in 0..full_reads {
-                    match source.read_exact(&mut buf[..]) {
-                        Ok(_) => {
-                            workspace.buffer.push(&buf[..]);
-                        }
-                        Err(_) => {
-                            return Err("Error while reading bytes of the raw block".to_owned())
-                        }
+                workspace
+                    .buffer
+                    .check_output_limit(header.decompressed_size as usize)?;
+                // read directly into the decodebuffer, so no extra buffer is needed for the block
+                match workspace
+                    .buffer
+                    .push_from_reader(source, header.decompressed_size as usize)
+                {
+                    Ok(_) => {}
+                    Err(e) => {
+                        return Err(DecodeBlockContentError::ReadError {
+                            step: header.block_type,
+                            source: e,
+                        })
                     }
                 }
-
-                let smaller = &mut buf[..single_read_size as usize];
-                match source.read_exact(smaller) {
-                    Ok(_) => {
-                        workspace.buffer.push(smaller);
-                    }
-                    Err(_) => {
-                       return Err("Error while reading bytes of the raw block".to_owned())
-                    }
-                }
-
+                notify_observer(workspace, header);
 
                 self.internal_state = DecoderState::ReadyToDecodeNextHeader;
                 Ok(header.decompressed_size as u64)
             }
 
-            BlockType::Reserved => {
-                Err("How did you even get this. The decoder should error out if it detects a reserved-type block".to_owned())
-            }
+            BlockType::Reserved => Err(DecodeBlockContentError::FoundReservedBlock),
 
             BlockType::Compressed => {
                 self.decompress_block(header, workspace, source)?;
@@ -119,22 +109,83 @@ impl BlockDecoder {
         }
     }
 
+    /// Like decode_block_content() but compressed blocks are decoded directly from the buffer of the source if it holds the whole block,
+    /// instead of copying them into the workspace first. Exactly the bytes of the block are consumed from the source.
+    pub fn decode_block_content_buffered<R: BufRead + ?Sized>(
+        &mut self,
+        header: &BlockHeader,
+        workspace: &mut DecoderScratch,
+        source: &mut R,
+    ) -> Result<u64, DecodeBlockContentError> {
+        if let (BlockType::Compressed, DecoderState::ReadyToDecodeNextBody) =
+            (&header.block_type, &self.internal_state)
+        {
+            let content_size = header.content_size as usize;
+            let buffered = match source.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) => {
+                    return Err(DecodeBlockContentError::ReadError {
+                        step: header.block_type,
+                        source: e,
+                    })
+                }
+            };
+            if buffered.len() >= content_size {
+                let result =
+                    self.decompress_block_content(header, workspace, &buffered[..content_size]);
+                // consume the block even if it is corrupt, so the decoder can skip it
+                source.consume(content_size);
+                if let Err(e) = result {
+                    self.internal_state = DecoderState::Failed;
+                    return Err(e.into());
+                }
+
+                self.internal_state = DecoderState::ReadyToDecodeNextHeader;
+                return Ok(header.content_size as u64);
+            }
+        }
+
+        // the block is not completely buffered, it has to be copied piece by piece anyways
+        let mut source = source;
+        self.decode_block_content(header, workspace, &mut source)
+    }
+
     fn decompress_block(
         &mut self,
         header: &BlockHeader,
         workspace: &mut DecoderScratch, //reuse this as often as possible. Not only if the trees are reused but also reuse the allocations when building new trees
         source: &mut dyn Read,
-    ) -> Result<(), String> {
-        workspace
-            .block_content_buffer
-            .resize(header.content_size as usize, 0);
-
-        match source.read_exact(workspace.block_content_buffer.as_mut_slice()) {
-            Ok(_) => { /* happy */ }
-            Err(_) => return Err("Error while reading the block content".to_owned()),
-        }
+    ) -> Result<(), DecodeBlockContentError> {
+        // take the buffer out of the workspace while the block is decoded from it, this keeps its allocation
+        let mut block_content = mem::take(&mut workspace.block_content_buffer);
+        block_content.resize(header.content_size as usize, 0);
+
+        let result = match source.read_exact(block_content.as_mut_slice()) {
+            Ok(_) => {
+                let result = self.decompress_block_content(header, workspace, &block_content);
+                if result.is_err() {
+                    self.internal_state = DecoderState::Failed;
+                }
+                result.map_err(DecodeBlockContentError::from)
+            }
+            Err(e) => Err(DecodeBlockContentError::ReadError {
+                step: header.block_type,
+                source: e,
+            }),
+        };
 
-        let raw = workspace.block_content_buffer.as_slice();
+        workspace.block_content_buffer = block_content;
+        result
+    }
+
+    fn decompress_block_content(
+        &mut self,
+        header: &BlockHeader,
+        workspace: &mut DecoderScratch,
+        raw: &[u8],
+    ) -> Result<(), DecompressBlockError> {
+        let buffer_len_before = workspace.buffer.len();
+        let offset_history_before = workspace.offset_hist;
 
         let mut section = LiteralsSection::new();
         let bytes_in_literals_header = section.parse_from_header(raw)?;
@@ -150,7 +201,10 @@ impl BlockDecoder {
         };
 
         if raw.len() < upper_limit_for_literals {
-            return Err(format!("Malformed section header. Says literals would be this long: {} but there are only {} bytes left", upper_limit_for_literals, raw.len()));
+            return Err(DecompressBlockError::MalformedSectionHeader {
+                expected_len: upper_limit_for_literals,
+                remaining_bytes: raw.len(),
+            });
         }
 
         let raw_literals = &raw[..upper_limit_for_literals];
@@ -184,6 +238,11 @@ impl BlockDecoder {
                 == header.content_size
         );
 
+        let wants_sequences = match &workspace.block_observer {
+            Some(observer) => observer.wants_sequences(),
+            None => false,
+        };
+
         if seq_section.num_sequences != 0 {
             decode_sequences(
                 &seq_section,
@@ -193,14 +252,87 @@ impl BlockDecoder {
             )?;
             execute_sequences(workspace)?;
         } else {
+            workspace
+                .buffer
+                .check_output_limit(workspace.literals_buffer.len())?;
             workspace.buffer.push(&workspace.literals_buffer);
             workspace.sequences.clear();
         }
 
+        if workspace.block_observer.is_some() {
+            let huffman_max_num_bits = match section.ls_type {
+                LiteralsSectionType::Compressed | LiteralsSectionType::Treeless => {
+                    Some(workspace.huf.table.max_num_bits)
+                }
+                LiteralsSectionType::Raw | LiteralsSectionType::RLE => None,
+            };
+            let literals = LiteralsInfo {
+                section,
+                huffman_max_num_bits,
+            };
+
+            let has_sequences = seq_section.num_sequences != 0;
+            let fse = &workspace.fse;
+            let accuracy_log = |rle: Option<u8>, table: &FSETable| {
+                if has_sequences && rle.is_none() {
+                    Some(table.accuracy_log)
+                } else {
+                    None
+                }
+            };
+            let sequences = SequencesInfo {
+                num_sequences: seq_section.num_sequences,
+                modes: seq_section.modes,
+                ll_accuracy_log: accuracy_log(fse.ll_rle, &fse.literal_lengths),
+                of_accuracy_log: accuracy_log(fse.of_rle, &fse.offsets),
+                ml_accuracy_log: accuracy_log(fse.ml_rle, &fse.match_lengths),
+                offset_history: offset_history_before,
+                sequences: if wants_sequences {
+                    Some(&workspace.sequences)
+                } else {
+                    None
+                },
+                literals: if wants_sequences {
+                    Some(&workspace.literals_buffer)
+                } else {
+                    None
+                },
+            };
+
+            let decompressed_size = (workspace.buffer.len() - buffer_len_before) as u32;
+            if let Some(observer) = &mut workspace.block_observer {
+                observer.on_block(&BlockInfo {
+                    block_type: header.block_type,
+                    last_block: header.last_block,
+                    compressed_size: header.content_size,
+                    decompressed_size,
+                    literals: Some(literals),
+                    sequences: Some(sequences),
+                });
+            }
+        }
+
         Ok(())
     }
 
-    pub fn read_block_header(&mut self, r: &mut dyn Read) -> Result<(BlockHeader, u8), String> {
+    /// Whether the last block could not be decoded although its content has been consumed from the source.
+    /// Decoding can continue with the next block header after skip_failed_block()
+    pub fn is_failed(&self) -> bool {
+        match self.internal_state {
+            DecoderState::Failed => true,
+            _ => false,
+        }
+    }
+
+    /// Gets ready to read the next block header after a block failed
+    pub fn skip_failed_block(&mut self) {
+        self.internal_state = DecoderState::ReadyToDecodeNextHeader;
+    }
+
+    pub fn read_block_header(
+        &mut self,
+        r: &mut dyn Read,
+    ) -> Result<(BlockHeader, u8), BlockHeaderReadError> {
         //match self.internal_state {
         //    DecoderState::ReadyToDecodeNextHeader => {/* Happy :) */},
         //    DecoderState::Failed => return Err(format!("Cant decode next block if failed along the way. Results will be nonsense")),
@@ -209,18 +341,12 @@ impl BlockDecoder {
 
         match r.read_exact(&mut self.header_buffer[0..3]) {
             Ok(_) => {}
-            Err(_) => return Err("Error while reading the block header".to_owned()),
+            Err(e) => return Err(BlockHeaderReadError::ReadError(e)),
         }
 
         let btype = match self.block_type() {
-            Ok(t) => match t {
-                BlockType::Reserved => return Err(
-                    "Reserved block occured. This is considered corruption by the documentation"
-                        .to_owned(),
-                ),
-                _ => t,
-            },
-            Err(m) => return Err(m),
+            BlockType::Reserved => return Err(BlockHeaderReadError::FoundReservedBlock),
+            t => t,
         };
 
         let block_size = self.block_content_size()?;
@@ -264,27 +390,23 @@ impl BlockDecoder {
         self.header_buffer[0] & 0x1 == 1
     }
 
-    fn block_type(&self) -> Result<BlockType, String> {
-        let t = (self.header_buffer[0] >> 1) & 0x3;
-        match t {
-            0 => Ok(BlockType::Raw),
-            1 => Ok(BlockType::RLE),
-            2 => Ok(BlockType::Compressed),
-            3 => Ok(BlockType::Reserved),
-            _ => Err(format!(
-                "Invalid Blocktype number. Is: {} Should be one of: 0,1,2,3 (3 is reserved though)",
-                t
-            )),
+    fn block_type(&self) -> BlockType {
+        // the two bits can not hold anything else
+        match (self.header_buffer[0] >> 1) & 0x3 {
+            0 => BlockType::Raw,
+            1 => BlockType::RLE,
+            2 => BlockType::Compressed,
+            _ => BlockType::Reserved,
         }
     }
 
-    fn block_content_size(&self) -> Result<u32, String> {
+    fn block_content_size(&self) -> Result<u32, BlockHeaderReadError> {
         let val = self.block_content_size_unchecked();
         if val > ABSOLUTE_MAXIMUM_BLOCK_SIZE {
-            Err(format!(
-                "Blocksize was bigger than the absolute maximum 128kb. Is: {}",
-                val
-            ))
+            Err(BlockHeaderReadError::BlockTooBig {
+                got: val,
+                max: ABSOLUTE_MAXIMUM_BLOCK_SIZE,
+            })
         } else {
             Ok(val)
         }
@@ -296,3 +418,17 @@ impl BlockDecoder {
             | ((self.header_buffer[2] as u32) << 13)
     }
 }
+
+/// Tells the observer about a raw or RLE block
+fn notify_observer(workspace: &mut DecoderScratch, header: &BlockHeader) {
+    if let Some(observer) = &mut workspace.block_observer {
+        observer.on_block(&BlockInfo {
+            block_type: header.block_type,
+            last_block: header.last_block,
+            compressed_size: header.content_size,
+            decompressed_size: header.decompressed_size,
+            literals: None,
+            sequences: None,
+        });
+    }
+}
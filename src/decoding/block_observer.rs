@@ -0,0 +1,54 @@
+use crate::blocks::block::BlockType;
+use crate::blocks::literals_section::LiteralsSection;
+use crate::blocks::sequence_section::{CompressionModes, Sequence};
+
+/// Gets told how each block of a frame was encoded, after the block has been decoded. Useful to debug encoders.
+///
+/// Set it with FrameDecoder::set_block_observer(). Without an observer the decoder does not collect any of this,
+/// so there is no cost if it is not used.
+pub trait BlockObserver {
+    fn on_block(&mut self, block: &BlockInfo<'_>);
+
+    /// If this returns true, on_block() also gets the decoded sequences and literals of compressed blocks
+    fn wants_sequences(&self) -> bool {
+        false
+    }
+}
+
+/// What the BlockDecoder found out about a block while decoding it
+#[derive(Debug)]
+pub struct BlockInfo<'a> {
+    pub block_type: BlockType,
+    pub last_block: bool,
+    /// Bytes the block content took in the source, without the 3 bytes of the block header
+    pub compressed_size: u32,
+    pub decompressed_size: u32,
+    /// Only set for compressed blocks
+    pub literals: Option<LiteralsInfo>,
+    /// Only set for compressed blocks
+    pub sequences: Option<SequencesInfo<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LiteralsInfo {
+    pub section: LiteralsSection,
+    /// The max_num_bits of the huffman table, if the literals were huffman coded
+    pub huffman_max_num_bits: Option<u8>,
+}
+
+#[derive(Debug)]
+pub struct SequencesInfo<'a> {
+    pub num_sequences: u32,
+    /// Not set if there are no sequences
+    pub modes: Option<CompressionModes>,
+    /// Accuracy logs of the FSE tables used for this block. None if there are no sequences or the table is in RLE mode
+    pub ll_accuracy_log: Option<u8>,
+    pub of_accuracy_log: Option<u8>,
+    pub ml_accuracy_log: Option<u8>,
+    /// The repeat offsets before the sequences of this block were executed. Needed to resolve the offsets of the sequences with do_offset_history()
+    pub offset_history: [u32; 3],
+    /// Only set if the observer wants_sequences()
+    pub sequences: Option<&'a [Sequence]>,
+    /// The decoded literals of the block. Only set if the observer wants_sequences()
+    pub literals: Option<&'a [u8]>,
+}
@@ -1,18 +1,33 @@
+use crate::decoding::dictionary::Dictionary;
+use crate::decoding::match_copy::{copy_match, CopyStrategy, WILD_COPY_SLACK};
+use crate::decoding::xxhash64::Xxh64;
+use crate::errors::DecodebufferError;
 use crate::io::{Error, Read, Write};
 use core::hash::Hasher;
-use core::mem;
-use core::ptr;
-use twox_hash::XxHash64;
 #[cfg(feature = "alloc")]
-use alloc::string::String;
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
 pub struct Decodebuffer {
     pub buffer: Vec<u8>,
-    pub dict_content: Vec<u8>,
+    /// The dictionary used for the current frame. Only its content is needed here, it is shared instead of copied
+    pub dict: Option<Arc<Dictionary>>,
 
     pub window_size: usize,
+    /// The first handed_out bytes of the buffer have been drained already, but are kept because they are still in the window
+    handed_out: usize,
+    /// If set, the drain functions that retain the window hand out all bytes and keep the window for later blocks. Not changed by reset()
+    pub eager_output: bool,
+    /// Set while decoding is stopped at a corrupt block. All bytes are handed out like with eager_output,
+    /// the window is kept in case the block is skipped and decoding continues. Cleared by reset()
+    pub stopped_at_corrupt_block: bool,
     total_output_counter: u64,
-    pub hash: XxHash64,
+    pub hash: Xxh64,
+    copy_strategy: CopyStrategy,
+    /// Not changed by reset()
+    output_limit: Option<u64>,
+    output_limit_exceeded: bool,
 }
 
 impl Read for Decodebuffer {
@@ -29,9 +44,10 @@ impl Read for Decodebuffer {
             return Ok(0);
         }
 
-        self.hash.write(&self.buffer[0..amount]);
-        target[..amount].copy_from_slice(&self.buffer[..amount]);
-        self.buffer.drain(0..amount);
+        let start = self.handed_out;
+        self.hash.write(&self.buffer[start..start + amount]);
+        target[..amount].copy_from_slice(&self.buffer[start..start + amount]);
+        self.hand_out(amount);
 
         Ok(amount)
     }
@@ -41,20 +57,52 @@ impl Decodebuffer {
     pub fn new(window_size: usize) -> Decodebuffer {
         Decodebuffer {
             buffer: Vec::new(),
-            dict_content: Vec::new(),
+            dict: None,
             window_size,
+            handed_out: 0,
+            eager_output: false,
+            stopped_at_corrupt_block: false,
             total_output_counter: 0,
-            hash: XxHash64::with_seed(0),
+            hash: Xxh64::new(),
+            copy_strategy: CopyStrategy::detect(),
+            output_limit: None,
+            output_limit_exceeded: false,
+        }
+    }
+
+    /// Which copy loop repeat() uses. Detected when the buffer is created
+    pub fn copy_strategy(&self) -> CopyStrategy {
+        self.copy_strategy
+    }
+
+    /// Overrides the detected copy strategy, for example to compare them. Fails if the CPU does not support the strategy
+    pub fn set_copy_strategy(&mut self, strategy: CopyStrategy) -> Result<(), DecodebufferError> {
+        if !strategy.is_supported() {
+            return Err(DecodebufferError::UnsupportedCopyStrategy(strategy));
         }
+        self.copy_strategy = strategy;
+        Ok(())
     }
 
     pub fn reset(&mut self, window_size: usize) {
         self.window_size = window_size;
         self.buffer.clear();
         self.buffer.reserve(self.window_size);
-        self.dict_content.clear();
+        self.handed_out = 0;
+        self.stopped_at_corrupt_block = false;
+        self.dict = None;
         self.total_output_counter = 0;
-        self.hash = XxHash64::with_seed(0);
+        self.hash = Xxh64::new();
+    }
+
+    /// Sets the content and counters as they were when a snapshot of the decoder was taken
+    pub fn restore(&mut self, content: &[u8], handed_out: usize, total_output: u64, hash: Xxh64) {
+        debug_assert!(handed_out <= content.len());
+        self.buffer.clear();
+        self.buffer.extend_from_slice(content);
+        self.handed_out = handed_out;
+        self.total_output_counter = total_output;
+        self.hash = hash;
     }
 
     pub fn len(&self) -> usize {
@@ -65,84 +113,123 @@ impl Decodebuffer {
         self.buffer.is_empty()
     }
 
+    /// How many bytes at the start of the buffer have already been drained but are kept for the window
+    pub fn handed_out(&self) -> usize {
+        self.handed_out
+    }
+
+    /// How many bytes have been decoded into this buffer since the last reset, including the ones already drained
+    pub fn total_output(&self) -> u64 {
+        self.total_output_counter
+    }
+
+    /// Throws away all but the first len bytes that have not been drained yet
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.buffer.len() {
+            self.total_output_counter -= (self.buffer.len() - len) as u64;
+            self.buffer.truncate(len);
+        }
+    }
+
+    /// Sets the maximum total_output() for check_output_limit(). The push functions and repeat() do not check it themselves
+    pub fn set_output_limit(&mut self, limit: Option<u64>) {
+        self.output_limit = limit;
+        self.output_limit_exceeded = false;
+    }
+
+    /// Whether check_output_limit() failed since the limit was set
+    pub fn output_limit_exceeded(&self) -> bool {
+        self.output_limit_exceeded
+    }
+
+    /// Fails if amount more bytes would exceed the output limit. Call this before growing the buffer by amount bytes
+    pub fn check_output_limit(&mut self, amount: usize) -> Result<(), DecodebufferError> {
+        match self.output_limit {
+            Some(limit) if self.total_output_counter + amount as u64 > limit => {
+                self.output_limit_exceeded = true;
+                Err(DecodebufferError::OutputLimitExceeded { amount, limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn push(&mut self, data: &[u8]) {
         self.buffer.extend_from_slice(data);
         self.total_output_counter += data.len() as u64;
     }
 
-    pub fn repeat(&mut self, offset: usize, match_length: usize) -> Result<(), String> {
+    /// Appends amount copies of byte
+    pub fn push_rle(&mut self, byte: u8, amount: usize) {
+        let new_len = self.buffer.len() + amount;
+        self.buffer.resize(new_len, byte);
+        self.total_output_counter += amount as u64;
+    }
+
+    /// Reads amount bytes from source directly into the buffer. If reading fails the buffer stays as it was
+    pub fn push_from_reader(&mut self, source: &mut dyn Read, amount: usize) -> Result<(), Error> {
+        let old_len = self.buffer.len();
+        self.buffer.resize(old_len + amount, 0);
+        match source.read_exact(&mut self.buffer[old_len..]) {
+            Ok(()) => {
+                self.total_output_counter += amount as u64;
+                Ok(())
+            }
+            Err(e) => {
+                self.buffer.truncate(old_len);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn repeat(&mut self, offset: usize, match_length: usize) -> Result<(), DecodebufferError> {
+        if offset == 0 {
+            return Err(DecodebufferError::ZeroOffset);
+        }
         if offset > self.buffer.len() {
             if self.total_output_counter <= self.window_size as u64 {
                 // at least part of that repeat is from the dictionary content
                 let bytes_from_dict = offset - self.buffer.len();
-
-                if bytes_from_dict > self.dict_content.len() {
-                    return Err(format!(
-                        "Need {} bytes from the dictionary but it is only {} bytes long",
-                        bytes_from_dict,
-                        self.dict_content.len()
-                    ));
+                let dict_content: &[u8] = match &self.dict {
+                    Some(dict) => &dict.dict_content,
+                    None => &[],
+                };
+
+                if bytes_from_dict > dict_content.len() {
+                    return Err(DecodebufferError::NotEnoughBytesInDictionary {
+                        got: dict_content.len(),
+                        need: bytes_from_dict,
+                    });
                 }
 
                 if bytes_from_dict < match_length {
-                    let dict_slice =
-                        &self.dict_content[self.dict_content.len() - bytes_from_dict..];
+                    let dict_slice = &dict_content[dict_content.len() - bytes_from_dict..];
                     self.buffer.extend(dict_slice);
 
                     self.total_output_counter += bytes_from_dict as u64;
                     return self.repeat(self.buffer.len(), match_length - bytes_from_dict);
                 } else {
-                    let low = self.dict_content.len() - bytes_from_dict;
+                    let low = dict_content.len() - bytes_from_dict;
                     let high = low + match_length;
-                    let dict_slice = &self.dict_content[low..high];
+                    let dict_slice = &dict_content[low..high];
                     self.buffer.extend(dict_slice);
+
+                    self.total_output_counter += match_length as u64;
                 }
             } else {
-                return Err(format!(
-                    "offset: {} bigger than buffer: {}",
+                return Err(DecodebufferError::OffsetTooBig {
                     offset,
-                    self.buffer.len()
-                ));
+                    buf_len: self.buffer.len(),
+                });
             }
         } else {
-            let start_idx = self.buffer.len() - offset;
-            self.buffer.reserve(match_length);
-
-            if start_idx + match_length > self.buffer.len() {
-                //need to copy byte by byte. can be optimized more but for now lets leave it like this
-                //TODO batch whats possible
-                for x in 0..match_length {
-                    self.buffer.push(self.buffer[start_idx + x]);
-                }
-            } else {
-                /*
-                const BATCH_SIZE: usize = 32;
-                let full_copies = match_length / BATCH_SIZE;
-                let partial_copy_size = match_length % BATCH_SIZE;
-
-                let mut buf = [0u8; BATCH_SIZE];
-                for x in 0..full_copies {
-                    let idx = start_idx + x * BATCH_SIZE;
-                    let source = &self.buffer.as_slice()[idx..idx + BATCH_SIZE];
-                    buf[0..BATCH_SIZE].copy_from_slice(source);
-                    self.buffer.extend(&buf[0..BATCH_SIZE]);
-                }
-
-                let idx = start_idx + full_copies * BATCH_SIZE;
-                let source = &self.buffer.as_slice()[idx..idx + partial_copy_size];
-                buf[0..partial_copy_size].copy_from_slice(source);
-                self.buffer.extend(&buf[0..partial_copy_size]);
-                */
-
-                // using this unsafe block instead of the above increases performance by ca 5% when decoding the enwik9 dataset
-                self.buffer.reserve(match_length);
-                unsafe {
-                    self.buffer.set_len(self.buffer.len() + match_length);
-                    let slice = &mut self.buffer[start_idx..];
-                    let src = slice.as_mut_ptr();
-                    let dst = src.add(slice.len() - match_length);
-                    ptr::copy_nonoverlapping(src, dst, match_length);
-                }
+            // make room for the match and the slack the wide copies may write past it
+            self.buffer.reserve(match_length + WILD_COPY_SLACK);
+            unsafe {
+                let old_len = self.buffer.len();
+                let dst = self.buffer.as_mut_ptr().add(old_len);
+                // Safety: 0 < offset <= old_len, the space after old_len was reserved above and the strategy was checked to be supported
+                copy_match(self.copy_strategy, dst, offset, match_length);
+                self.buffer.set_len(old_len + match_length);
             }
             self.total_output_counter += match_length as u64;
         }
@@ -152,8 +239,16 @@ impl Decodebuffer {
 
     // Check if and how many bytes can currently be drawn from the buffer
     pub fn can_drain_to_window_size(&self) -> Option<usize> {
-        if self.buffer.len() > self.window_size {
-            Some(self.buffer.len() - self.window_size)
+        let can_drain = if self.eager_output || self.stopped_at_corrupt_block {
+            self.buffer.len() - self.handed_out
+        } else {
+            self.buffer
+                .len()
+                .saturating_sub(self.window_size)
+                .saturating_sub(self.handed_out)
+        };
+        if can_drain > 0 {
+            Some(can_drain)
         } else {
             None
         }
@@ -161,7 +256,18 @@ impl Decodebuffer {
 
     //How many bytes can be drained if the windowsize does not have to be maintained
     pub fn can_drain(&self) -> usize {
-        self.buffer.len()
+        self.buffer.len() - self.handed_out
+    }
+
+    /// Marks amount more bytes as drained and throws away the drained bytes that are no longer in the window
+    fn hand_out(&mut self, amount: usize) {
+        self.handed_out += amount;
+        let outside_window = self.buffer.len().saturating_sub(self.window_size);
+        let forget = core::cmp::min(self.handed_out, outside_window);
+        if forget > 0 {
+            self.buffer.drain(0..forget);
+            self.handed_out -= forget;
+        }
     }
 
     //drain as much as possible while retaining enough so that decoding si still possible with the requeired windowsize
@@ -171,8 +277,11 @@ impl Decodebuffer {
         match self.can_drain_to_window_size() {
             None => None,
             Some(can_drain) => {
-                self.hash.write(&self.buffer[0..can_drain]);
-                Some(self.buffer.drain(0..can_drain).collect())
+                let drained = &self.buffer[self.handed_out..self.handed_out + can_drain];
+                self.hash.write(drained);
+                let drained = drained.to_vec();
+                self.hand_out(can_drain);
+                Some(drained)
             }
         }
     }
@@ -181,48 +290,53 @@ impl Decodebuffer {
         match self.can_drain_to_window_size() {
             None => Ok(0),
             Some(can_drain) => {
-                self.hash.write(&self.buffer[0..can_drain]);
-                let mut buf = [0u8; 1]; //TODO batch to reasonable size
-                for x in self.buffer.drain(0..can_drain) {
-                    buf[0] = x;
-                    sink.write_all(&buf[..])?;
-                }
+                let drained = &self.buffer[self.handed_out..self.handed_out + can_drain];
+                self.hash.write(drained);
+                sink.write_all(drained)?;
+                self.hand_out(can_drain);
                 Ok(can_drain)
             }
         }
     }
 
-    //drain the buffer completely
+    //drain the buffer completely. The bytes are copied out so the buffer keeps its allocation for the next frame
     pub fn drain(&mut self) -> Vec<u8> {
-        self.hash.write(&self.buffer);
+        let drained = &self.buffer[self.handed_out..];
+        self.hash.write(drained);
+        let drained = drained.to_vec();
 
-        let new_buffer = Vec::with_capacity(self.buffer.capacity());
-        mem::replace(&mut self.buffer, new_buffer)
+        self.buffer.clear();
+        self.handed_out = 0;
+        drained
     }
 
     pub fn drain_to_writer(&mut self, sink: &mut dyn Write) -> Result<usize, Error> {
-        self.hash.write(&self.buffer);
-        sink.write_all(&self.buffer)?;
+        let drained = &self.buffer[self.handed_out..];
+        self.hash.write(drained);
+        sink.write_all(drained)?;
 
-        let len = self.buffer.len();
+        let len = drained.len();
         self.buffer.clear();
+        self.handed_out = 0;
         Ok(len)
     }
 
     pub fn read_all(&mut self, target: &mut [u8]) -> Result<usize, Error> {
-        let amount = if self.buffer.len() > target.len() {
+        let amount = if self.can_drain() > target.len() {
             target.len()
         } else {
-            self.buffer.len()
+            self.can_drain()
         };
 
         if amount == 0 {
             return Ok(0);
         }
 
-        self.hash.write(&self.buffer[0..amount]);
-        target[..amount].copy_from_slice(&self.buffer[..amount]);
-        self.buffer.drain(0..amount);
+        let start = self.handed_out;
+        self.hash.write(&self.buffer[start..start + amount]);
+        target[..amount].copy_from_slice(&self.buffer[start..start + amount]);
+        self.buffer.drain(0..start + amount);
+        self.handed_out = 0;
 
         Ok(amount)
     }
@@ -2,10 +2,13 @@ use core::convert::TryInto;
 
 use crate::decoding::scratch::FSEScratch;
 use crate::decoding::scratch::HuffmanScratch;
+use crate::errors::DictionaryDecodeError;
 #[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
 #[cfg(feature = "alloc")]
-use alloc::string::String;
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
 pub struct Dictionary {
     pub id: u32,
@@ -16,9 +19,24 @@ pub struct Dictionary {
 }
 
 impl Dictionary {
+    /// Creates a dictionary that only consists of content, like the "raw content" dictionaries and prefixes of the reference implementation.
+    /// It has no entropy tables and starts with the default repeat offsets. Frames compressed with such a dictionary
+    /// usually do not carry a dictionary id, in this case id 0 should be used.
+    pub fn from_raw_content(id: u32, content: &[u8]) -> Dictionary {
+        Dictionary {
+            id,
+            fse: FSEScratch::new(),
+            huf: HuffmanScratch::new(),
+            dict_content: content.to_vec(),
+            offset_hist: [1, 4, 8],
+        }
+    }
+
     /// parses the dictionary and set the tables
     /// it returns the dict_id for checking with the frame's dict_id
-    pub fn decode_dict(raw: &[u8]) -> Result<Dictionary, String> {
+    ///
+    /// Malformed dictionaries are rejected with an error describing what is wrong, this never panics
+    pub fn decode_dict(raw: &[u8]) -> Result<Dictionary, DictionaryDecodeError> {
         let mut new_dict = Dictionary {
             id: 0,
             fse: FSEScratch::new(),
@@ -27,9 +45,16 @@ impl Dictionary {
             offset_hist: [2, 4, 8],
         };
 
-        let magic_num = &raw[..4];
-        if magic_num != [0x37, 0xA4, 0x30, 0xEC] {
-            return Err("Bad magic_num at start of the dictionary".to_owned());
+        if raw.len() < 8 {
+            return Err(DictionaryDecodeError::DictionaryTooSmall {
+                have: raw.len(),
+                need: 8,
+            });
+        }
+
+        let magic_num: [u8; 4] = raw[..4].try_into().expect("optimized away");
+        if magic_num != MAGIC_NUM {
+            return Err(DictionaryDecodeError::BadMagicNum { got: magic_num });
         }
 
         let dict_id = raw[4..8].try_into().expect("optimized away");
@@ -38,43 +63,155 @@ impl Dictionary {
 
         let raw_tables = &raw[8..];
 
-        let huf_size = new_dict.huf.table.build_decoder(raw_tables)?;
-        let raw_tables = &raw_tables[huf_size as usize..];
+        let huf_size = new_dict
+            .huf
+            .table
+            .build_decoder(raw_tables)
+            .map_err(DictionaryDecodeError::FailedToReadHuffmanTable)?;
+        let raw_tables = skip_bytes(raw, raw_tables, huf_size as usize)?;
+
+        let of_size = new_dict
+            .fse
+            .offsets
+            .build_decoder(
+                raw_tables,
+                crate::decoding::sequence_section_decoder::OF_MAX_LOG,
+            )
+            .map_err(DictionaryDecodeError::FailedToReadOffsetTable)?;
+        let raw_tables = skip_bytes(raw, raw_tables, of_size)?;
+
+        let ml_size = new_dict
+            .fse
+            .match_lengths
+            .build_decoder(
+                raw_tables,
+                crate::decoding::sequence_section_decoder::ML_MAX_LOG,
+            )
+            .map_err(DictionaryDecodeError::FailedToReadMatchLengthTable)?;
+        let raw_tables = skip_bytes(raw, raw_tables, ml_size)?;
+
+        let ll_size = new_dict
+            .fse
+            .literal_lengths
+            .build_decoder(
+                raw_tables,
+                crate::decoding::sequence_section_decoder::LL_MAX_LOG,
+            )
+            .map_err(DictionaryDecodeError::FailedToReadLiteralLengthTable)?;
+        let raw_tables = skip_bytes(raw, raw_tables, ll_size)?;
+
+        if raw_tables.len() < 12 {
+            return Err(DictionaryDecodeError::DictionaryTooSmall {
+                have: raw.len(),
+                need: raw.len() - raw_tables.len() + 12,
+            });
+        }
+
+        let raw_content = &raw_tables[12..];
+
+        for idx in 0..3 {
+            let offset = raw_tables[idx * 4..idx * 4 + 4]
+                .try_into()
+                .expect("optimized away");
+            let offset = u32::from_le_bytes(offset);
+
+            if offset == 0 {
+                return Err(DictionaryDecodeError::ZeroRepeatOffset { index: idx });
+            }
+            if offset as usize > raw_content.len() {
+                return Err(DictionaryDecodeError::RepeatOffsetTooBig {
+                    index: idx,
+                    offset,
+                    content_size: raw_content.len(),
+                });
+            }
+            new_dict.offset_hist[idx] = offset;
+        }
+
+        new_dict.dict_content.extend(raw_content);
+
+        Ok(new_dict)
+    }
+}
+
+const MAGIC_NUM: [u8; 4] = [0x37, 0xA4, 0x30, 0xEC];
+
+/// Skips the bytes used by one of the tables, checking that the table builder did not report more bytes than there are
+fn skip_bytes<'a>(
+    raw: &[u8],
+    raw_tables: &'a [u8],
+    amount: usize,
+) -> Result<&'a [u8], DictionaryDecodeError> {
+    if amount > raw_tables.len() {
+        return Err(DictionaryDecodeError::DictionaryTooSmall {
+            have: raw.len(),
+            need: raw.len() - raw_tables.len() + amount,
+        });
+    }
+    Ok(&raw_tables[amount..])
+}
+
+/// A set of parsed dictionaries, indexed by their id.
+///
+/// The dictionaries are stored behind an Arc, so cloning a registry is cheap and all clones share the same
+/// dictionary content. This allows handing the same dictionaries to multiple FrameDecoders, also across threads.
+#[derive(Clone, Default)]
+pub struct DictionaryRegistry {
+    dicts: BTreeMap<u32, Arc<Dictionary>>,
+}
+
+impl DictionaryRegistry {
+    pub fn new() -> DictionaryRegistry {
+        DictionaryRegistry {
+            dicts: BTreeMap::new(),
+        }
+    }
 
-        let of_size = new_dict.fse.offsets.build_decoder(
-            raw_tables,
-            crate::decoding::sequence_section_decoder::OF_MAX_LOG,
-        )?;
-        let raw_tables = &raw_tables[of_size..];
+    /// Parses the raw dictionary and adds it to the registry. Returns the id of the dictionary
+    pub fn add_dict(&mut self, raw: &[u8]) -> Result<u32, DictionaryDecodeError> {
+        let dict = Dictionary::decode_dict(raw)?;
+        let id = dict.id;
+        self.insert(Arc::new(dict));
+        Ok(id)
+    }
+
+    /// Adds a raw content dictionary. Returns the dictionary that was previously registered with the same id, if any.
+    /// A dictionary with id 0 is used for all frames that do not specify a dictionary id
+    pub fn add_raw_content(&mut self, id: u32, content: &[u8]) -> Option<Arc<Dictionary>> {
+        self.insert(Arc::new(Dictionary::from_raw_content(id, content)))
+    }
 
-        let ml_size = new_dict.fse.match_lengths.build_decoder(
-            raw_tables,
-            crate::decoding::sequence_section_decoder::ML_MAX_LOG,
-        )?;
-        let raw_tables = &raw_tables[ml_size..];
+    /// Adds an already parsed dictionary. Returns the dictionary that was previously registered with the same id, if any
+    pub fn insert(&mut self, dict: Arc<Dictionary>) -> Option<Arc<Dictionary>> {
+        self.dicts.insert(dict.id, dict)
+    }
 
-        let ll_size = new_dict.fse.literal_lengths.build_decoder(
-            raw_tables,
-            crate::decoding::sequence_section_decoder::LL_MAX_LOG,
-        )?;
-        let raw_tables = &raw_tables[ll_size..];
+    pub fn get(&self, id: u32) -> Option<&Arc<Dictionary>> {
+        self.dicts.get(&id)
+    }
 
-        let offset1 = raw_tables[0..4].try_into().expect("optimized away");
-        let offset1 = u32::from_le_bytes(offset1);
+    pub fn remove(&mut self, id: u32) -> Option<Arc<Dictionary>> {
+        self.dicts.remove(&id)
+    }
 
-        let offset2 = raw_tables[4..8].try_into().expect("optimized away");
-        let offset2 = u32::from_le_bytes(offset2);
+    pub fn contains(&self, id: u32) -> bool {
+        self.dicts.contains_key(&id)
+    }
 
-        let offset3 = raw_tables[8..12].try_into().expect("optimized away");
-        let offset3 = u32::from_le_bytes(offset3);
+    /// Ids of all registered dictionaries in ascending order
+    pub fn ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.dicts.keys().copied()
+    }
 
-        new_dict.offset_hist[0] = offset1;
-        new_dict.offset_hist[1] = offset2;
-        new_dict.offset_hist[2] = offset3;
+    pub fn len(&self) -> usize {
+        self.dicts.len()
+    }
 
-        let raw_content = &raw_tables[12..];
-        new_dict.dict_content.extend(raw_content);
+    pub fn is_empty(&self) -> bool {
+        self.dicts.is_empty()
+    }
 
-        Ok(new_dict)
+    pub fn clear(&mut self) {
+        self.dicts.clear();
     }
 }
@@ -0,0 +1,56 @@
+use crate::decoding::block_decoder::ABSOLUTE_MAXIMUM_BLOCK_SIZE;
+
+/// Limits on how many bytes untrusted input may decode to, see FrameDecoder::set_output_limits().
+/// They are checked before the output of a block is allocated, so a decompression bomb fails before it uses up the memory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// Maximum number of bytes a single frame may decode to
+    pub max_frame_output: Option<u64>,
+    /// Maximum number of bytes all frames together may decode to, counted from the frame that is decoded when the limits are set
+    pub max_stream_output: Option<u64>,
+    /// Maximum number of bytes a frame may decode to per byte of bytes_read_from_source().
+    /// One block of output is allowed on top of that, so small frames of very repetitive data are not rejected
+    pub max_expansion_ratio: Option<u64>,
+}
+
+/// Which of the OutputLimits was exceeded, with the configured value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputLimit {
+    FrameOutput(u64),
+    StreamOutput(u64),
+    ExpansionRatio(u64),
+}
+
+impl OutputLimits {
+    /// How many bytes the current frame may decode to in total and the limit that allows the least, None if no limit is set.
+    /// stream_output is what the previous frames decoded to, frame_input how many bytes of the current frame will have been read after the next block
+    pub fn frame_output_limit(
+        &self,
+        stream_output: u64,
+        frame_input: u64,
+    ) -> Option<(u64, OutputLimit)> {
+        let mut result: Option<(u64, OutputLimit)> = None;
+        let mut consider = |max: u64, limit: OutputLimit| {
+            if result.map_or(true, |(smallest, _)| max < smallest) {
+                result = Some((max, limit));
+            }
+        };
+
+        if let Some(max) = self.max_frame_output {
+            consider(max, OutputLimit::FrameOutput(max));
+        }
+        if let Some(max) = self.max_stream_output {
+            consider(
+                max.saturating_sub(stream_output),
+                OutputLimit::StreamOutput(max),
+            );
+        }
+        if let Some(ratio) = self.max_expansion_ratio {
+            let max = ratio
+                .saturating_mul(frame_input)
+                .saturating_add(u64::from(ABSOLUTE_MAXIMUM_BLOCK_SIZE));
+            consider(max, OutputLimit::ExpansionRatio(ratio));
+        }
+        result
+    }
+}
@@ -1,30 +1,66 @@
 use super::super::blocks::literals_section::LiteralsSection;
 use super::super::blocks::literals_section::LiteralsSectionType;
 use super::bit_reader_reverse::BitReaderReversed;
-use super::scratch::HuffmanScratch;
-use crate::huff0::HuffmanDecoder;
 #[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
+use super::scratch::HuffmanScratch;
+use crate::errors::DecompressLiteralsError;
+use crate::huff0::{
+    DoubleEntry, Entry, HuffmanDecodeTables, HuffmanDecoder, HuffmanTableStorage, DOUBLE_TABLE_LOG,
+};
 #[cfg(feature = "alloc")]
-use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
 
+#[cfg(feature = "alloc")]
 pub fn decode_literals(
     section: &LiteralsSection,
     scratch: &mut HuffmanScratch,
     source: &[u8],
     target: &mut Vec<u8>,
-) -> Result<u32, String> {
+) -> Result<u32, DecompressLiteralsError> {
+    let target_start = target.len();
+    target.resize(target_start + section.regenerated_size as usize, 0);
+    let bytes_read = decode_literals_into(
+        section,
+        &mut scratch.table,
+        source,
+        &mut target[target_start..],
+    )?;
+    Ok(bytes_read)
+}
+
+/// Decodes the literals of the section into output, which needs to be exactly regenerated_size bytes long.
+/// source starts after the literals section header. Returns how many bytes of source were used.
+/// A huffman table that is read from source is stored in table, treeless sections reuse it
+pub fn decode_literals_into<T: HuffmanTableStorage>(
+    section: &LiteralsSection,
+    table: &mut T,
+    source: &[u8],
+    output: &mut [u8],
+) -> Result<u32, DecompressLiteralsError> {
+    debug_assert!(output.len() == section.regenerated_size as usize);
     match section.ls_type {
         LiteralsSectionType::Raw => {
-            target.extend(&source[0..section.regenerated_size as usize]);
+            if source.len() < output.len() {
+                return Err(DecompressLiteralsError::NotEnoughBytes {
+                    have: source.len(),
+                    need: output.len(),
+                });
+            }
+            output.copy_from_slice(&source[..output.len()]);
             Ok(section.regenerated_size)
         }
         LiteralsSectionType::RLE => {
-            target.resize(target.len() + section.regenerated_size as usize, source[0]);
+            if source.is_empty() {
+                return Err(DecompressLiteralsError::NotEnoughBytes { have: 0, need: 1 });
+            }
+            for x in output.iter_mut() {
+                *x = source[0];
+            }
             Ok(1)
         }
         LiteralsSectionType::Compressed | LiteralsSectionType::Treeless => {
-            let bytes_read = decompress_literals(section, scratch, source, target)?;
+            let bytes_read = decompress_literals(section, table, source, output)?;
 
             //return sum of used bytes
             Ok(bytes_read)
@@ -32,42 +68,46 @@ pub fn decode_literals(
     }
 }
 
-fn decompress_literals(
+fn decompress_literals<T: HuffmanTableStorage>(
     section: &LiteralsSection,
-    scratch: &mut HuffmanScratch,
+    table: &mut T,
     source: &[u8],
-    target: &mut Vec<u8>,
-) -> Result<u32, String> {
-    if section.compressed_size.is_none() {
-        return Err("compressed size was none even though it must be set to something for compressed literals".to_owned());
-    }
-    if section.num_streams.is_none() {
-        return Err("num_streams was none even though it must be set to something (1 or 4) for compressed literals".to_owned());
+    output: &mut [u8],
+) -> Result<u32, DecompressLiteralsError> {
+    let compressed_size = match section.compressed_size {
+        Some(size) => size as usize,
+        None => return Err(DecompressLiteralsError::MissingCompressedSize),
+    };
+    let num_streams = match section.num_streams {
+        Some(num_streams) => num_streams,
+        None => return Err(DecompressLiteralsError::MissingNumStreams),
+    };
+    if source.len() < compressed_size {
+        return Err(DecompressLiteralsError::NotEnoughBytes {
+            have: source.len(),
+            need: compressed_size,
+        });
     }
 
-    target.reserve(section.regenerated_size as usize);
-    let source = &source[0..section.compressed_size.unwrap() as usize];
+    let source = &source[0..compressed_size];
     let mut bytes_read = 0;
 
-    match section.ls_type {
-        LiteralsSectionType::Compressed => {
-            //read Huffman tree description
-            bytes_read += scratch.table.build_decoder(source)?;
-        }
-        LiteralsSectionType::Treeless => {
-            if scratch.table.max_num_bits == 0 {
-                return Err("Tried to reuse huffman table but it was never initialized".to_owned());
-            }
-        }
-        _ => { /* nothing to do, huffman tree has been provided by previous block */ }
+    if let LiteralsSectionType::Compressed = section.ls_type {
+        //read Huffman tree description
+        bytes_read += table.build_decoder(source)?;
     }
+    // treeless sections use the huffman tree provided by a previous block
+    let tables = match table.decode_tables(output.len()) {
+        Some(tables) => tables,
+        None => return Err(DecompressLiteralsError::UninitializedHuffmanTable),
+    };
 
     let source = &source[bytes_read as usize..];
 
-    if section.num_streams.unwrap() == 4 {
+    if num_streams == 4 {
         //build jumptable
         if source.len() < 6 {
-            return Err("Need 6 byte to decode jump header".to_owned());
+            return Err(DecompressLiteralsError::MissingBytesForJumpHeader { got: source.len() });
         }
         let jump1 = source[0] as usize + ((source[1] as usize) << 8);
         let jump2 = jump1 + source[2] as usize + ((source[3] as usize) << 8);
@@ -76,11 +116,10 @@ fn decompress_literals(
         let source = &source[6..];
 
         if source.len() < jump3 {
-            return Err(format!(
-                "Need at least {} byte to decode literals. Have: {}",
-                jump3,
-                source.len()
-            ));
+            return Err(DecompressLiteralsError::MissingBytesForLiterals {
+                got: source.len(),
+                needed: jump3,
+            });
         }
 
         //decode 4 streams
@@ -89,70 +128,238 @@ fn decompress_literals(
         let stream3 = &source[jump2..jump3];
         let stream4 = &source[jump3..];
 
-        for stream in &[stream1, stream2, stream3, stream4] {
-            let mut decoder = HuffmanDecoder::new(&scratch.table);
-            let mut br = BitReaderReversed::new(stream);
-            //skip the 0 padding at the end of the last byte of the bit stream and throw away the first 1 found
-            let mut skipped_bits = 0;
-            loop {
-                let val = br.get_bits(1)?;
-                skipped_bits += 1;
-                if val == 1 || skipped_bits > 8 {
-                    break;
+        decode_4_streams(tables, [stream1, stream2, stream3, stream4], output)?;
+
+        bytes_read += source.len() as u32;
+    } else {
+        //just decode the one stream
+        let mut br = init_stream(source)?;
+        let decoded = decode_stream(tables, &mut br, output, 0)?;
+        if decoded != output.len() {
+            return Err(DecompressLiteralsError::DecodedLiteralCountMismatch {
+                decoded,
+                expected: output.len(),
+            });
+        }
+        bytes_read += source.len() as u32;
+    }
+
+    Ok(bytes_read)
+}
+
+//skip the 0 padding at the end of the last byte of the bit stream and throw away the first 1 found
+fn init_stream(stream: &[u8]) -> Result<BitReaderReversed<'_>, DecompressLiteralsError> {
+    let mut br = BitReaderReversed::new(stream);
+    let mut skipped_bits = 0;
+    loop {
+        let val = br.get_bits(1)?;
+        skipped_bits += 1;
+        if val == 1 || skipped_bits > 8 {
+            break;
+        }
+    }
+    if skipped_bits > 8 {
+        //if more than 7 bits are 0, this is not the correct end of the bitstream. Either a bug or corrupted data
+        return Err(DecompressLiteralsError::ExtraPadding { skipped_bits });
+    }
+    Ok(br)
+}
+
+/// Decodes the four streams into their segments of the output like HUF_decompress4X in the reference implementation.
+/// The first three streams each decode (regenerated_size + 3) / 4 literals, the last one the rest.
+///
+/// All four streams are decoded in the same loop which lets the cpu work on all of them at the same time
+/// instead of waiting for the previous symbol of the same stream.
+fn decode_4_streams(
+    table: HuffmanDecodeTables<'_>,
+    streams: [&[u8]; 4],
+    output: &mut [u8],
+) -> Result<(), DecompressLiteralsError> {
+    let segment_size = (output.len() + 3) / 4;
+    if segment_size * 3 > output.len() {
+        return Err(
+            DecompressLiteralsError::RegeneratedSizeTooSmallForFourStreams { got: output.len() },
+        );
+    }
+
+    let (segment1, rest) = output.split_at_mut(segment_size);
+    let (segment2, rest) = rest.split_at_mut(segment_size);
+    let (segment3, segment4) = rest.split_at_mut(segment_size);
+
+    // the readers are only passed by value outside of the hot loop so the compiler can keep them in registers
+    let mut br1 = init_stream(streams[0])?;
+    let mut br2 = init_stream(streams[1])?;
+    let mut br3 = init_stream(streams[2])?;
+    let mut br4 = init_stream(streams[3])?;
+
+    let max_num_bits = table.max_num_bits;
+    let (mut pos1, mut pos2, mut pos3, mut pos4) = (0, 0, 0, 0);
+
+    // Same reasoning as in decode_stream. Each round decodes up to 8 symbols per stream so that needs to fit into the segments
+    let fast_path_possible = |br: &BitReaderReversed<'_>, position: usize, segment: &[u8]| {
+        br.bits_remaining() > 4 * max_num_bits as isize && position + 8 <= segment.len()
+    };
+    if !table.decode_double.is_empty() {
+        let decode = double_table(table);
+        let fast_path_possible = |br: &BitReaderReversed<'_>, position: usize, segment: &[u8]| {
+            br.bits_remaining() > 4 * DOUBLE_TABLE_LOG as isize && position + 8 <= segment.len()
+        };
+        while fast_path_possible(&br1, pos1, segment1)
+            && fast_path_possible(&br2, pos2, segment2)
+            && fast_path_possible(&br3, pos3, segment3)
+            && fast_path_possible(&br4, pos4, segment4)
+        {
+            br1.refill();
+            br2.refill();
+            br3.refill();
+            br4.refill();
+            // the loop condition made sure each segment has space for the at most 8 symbols of this round
+            unsafe {
+                for _ in 0..4 {
+                    pos1 += decode_double(decode, &mut br1, segment1, pos1);
+                    pos2 += decode_double(decode, &mut br2, segment2, pos2);
+                    pos3 += decode_double(decode, &mut br3, segment3, pos3);
+                    pos4 += decode_double(decode, &mut br4, segment4, pos4);
                 }
             }
-            if skipped_bits > 8 {
-                //if more than 7 bits are 0, this is not the correct end of the bitstream. Either a bug or corrupted data
-                return Err(format!("Padding at the end of the sequence_section was more than a byte long: {}. Probably cause by data corruption", skipped_bits));
+        }
+    } else {
+        let decode = table.decode;
+        while fast_path_possible(&br1, pos1, segment1)
+            && fast_path_possible(&br2, pos2, segment2)
+            && fast_path_possible(&br3, pos3, segment3)
+            && fast_path_possible(&br4, pos4, segment4)
+        {
+            br1.refill();
+            br2.refill();
+            br3.refill();
+            br4.refill();
+            for _ in 0..4 {
+                segment1[pos1] = decode_single(decode, &mut br1, max_num_bits);
+                segment2[pos2] = decode_single(decode, &mut br2, max_num_bits);
+                segment3[pos3] = decode_single(decode, &mut br3, max_num_bits);
+                segment4[pos4] = decode_single(decode, &mut br4, max_num_bits);
+                pos1 += 1;
+                pos2 += 1;
+                pos3 += 1;
+                pos4 += 1;
             }
-            decoder.init_state(&mut br)?;
+        }
+    }
 
-            while br.bits_remaining() > -(scratch.table.max_num_bits as isize) {
-                target.push(decoder.decode_symbol());
-                decoder.next_state(&mut br)?;
-            }
-            if br.bits_remaining() != -(scratch.table.max_num_bits as isize) {
-                return Err(format!(
-                    "Bitstream was read till: {}, should have been: {}",
-                    br.bits_remaining(),
-                    -(scratch.table.max_num_bits as isize)
-                ));
-            }
+    let mut streams = [
+        (br1, segment1, pos1),
+        (br2, segment2, pos2),
+        (br3, segment3, pos3),
+        (br4, segment4, pos4),
+    ];
+    for (br, segment, position) in streams.iter_mut() {
+        let decoded = decode_stream(table, br, segment, *position)?;
+        if decoded != segment.len() {
+            return Err(DecompressLiteralsError::DecodedLiteralCountMismatch {
+                decoded,
+                expected: segment.len(),
+            });
         }
+    }
 
-        bytes_read += source.len() as u32;
-    } else {
-        //just decode the one stream
-        assert!(section.num_streams.unwrap() == 1);
-        let mut decoder = HuffmanDecoder::new(&scratch.table);
-        let mut br = BitReaderReversed::new(source);
-        let mut skipped_bits = 0;
-        loop {
-            let val = br.get_bits(1)?;
-            skipped_bits += 1;
-            if val == 1 || skipped_bits > 8 {
-                break;
+    Ok(())
+}
+
+/// Decodes symbols into output starting at position until the bitstream is exhausted. Returns the position after the last symbol.
+/// The bitreader must be positioned right after the padding of the stream.
+fn decode_stream(
+    table: HuffmanDecodeTables<'_>,
+    br: &mut BitReaderReversed<'_>,
+    output: &mut [u8],
+    mut position: usize,
+) -> Result<usize, DecompressLiteralsError> {
+    let max_num_bits = table.max_num_bits;
+
+    // The fast path decodes directly from the next bits of the stream instead of keeping them in a HuffmanDecoder.
+    // As long as more than 4 * max_num_bits are left all of the next 4 codes are part of the stream.
+    // A refill provides enough bits for 4 codes of at most 11 bits. For double symbols both codes together
+    // use at most DOUBLE_TABLE_LOG bits so the same holds for them with that bound.
+    if !table.decode_double.is_empty() {
+        let decode = double_table(table);
+        while br.bits_remaining() > 4 * DOUBLE_TABLE_LOG as isize && position + 8 <= output.len() {
+            br.refill();
+            // the loop condition made sure there is space for the at most 8 symbols of this round
+            unsafe {
+                for _ in 0..4 {
+                    position += decode_double(decode, br, output, position);
+                }
             }
         }
-        if skipped_bits > 8 {
-            //if more than 7 bits are 0, this is not the correct end of the bitstream. Either a bug or corrupted data
-            return Err(format!("Padding at the end of the sequence_section was more than a byte long: {}. Probably cause by data corruption", skipped_bits));
-        }
-        decoder.init_state(&mut br)?;
-        while br.bits_remaining() > -(scratch.table.max_num_bits as isize) {
-            target.push(decoder.decode_symbol());
-            decoder.next_state(&mut br)?;
+    } else {
+        let decode = table.decode;
+        while br.bits_remaining() > 4 * max_num_bits as isize && position + 4 <= output.len() {
+            br.refill();
+            for _ in 0..4 {
+                output[position] = decode_single(decode, br, max_num_bits);
+                position += 1;
+            }
         }
-        bytes_read += source.len() as u32;
     }
 
-    if target.len() != section.regenerated_size as usize {
-        return Err(format!(
-            "Did not decode enough literals: {}, Should have been: {}",
-            target.len(),
-            section.regenerated_size
-        ));
+    // the rest is decoded symbol by symbol, this also checks that the stream ends where it should
+    let mut decoder = HuffmanDecoder::from_decode_table(table.decode, max_num_bits);
+    decoder.init_state(br)?;
+    let max_num_bits = max_num_bits as isize;
+    while br.bits_remaining() > -max_num_bits {
+        if position == output.len() {
+            return Err(DecompressLiteralsError::TooManyLiterals {
+                expected: output.len(),
+            });
+        }
+        br.refill();
+        output[position] = decoder.decode_symbol();
+        decoder.next_state_fast(br);
+        position += 1;
+    }
+    if br.bits_remaining() != -max_num_bits {
+        return Err(DecompressLiteralsError::BitstreamReadMismatch {
+            read_till: br.bits_remaining(),
+            expected: -max_num_bits,
+        });
     }
 
-    Ok(bytes_read)
+    Ok(position)
+}
+
+/// Decodes one symbol from the next max_num_bits bits. The bitreader needs to be refilled before
+#[inline(always)]
+fn decode_single(decode: &[Entry], br: &mut BitReaderReversed<'_>, max_num_bits: u8) -> u8 {
+    let entry = decode[br.peek_bits(max_num_bits) as usize];
+    br.consume(entry.num_bits);
+    entry.symbol
+}
+
+/// Decodes one or two symbols from the next DOUBLE_TABLE_LOG bits and writes them at position. Returns the amount of symbols.
+/// The bitreader needs to be refilled before.
+///
+/// # Safety
+/// output needs space for two symbols at position, even if only one is decoded.
+/// The fast loops check once per round that there is space for 8 symbols, which covers the 4 calls of one round
+#[inline(always)]
+unsafe fn decode_double(
+    decode: &[DoubleEntry; 1 << DOUBLE_TABLE_LOG],
+    br: &mut BitReaderReversed<'_>,
+    output: &mut [u8],
+    position: usize,
+) -> usize {
+    debug_assert!(position + 2 <= output.len());
+    let entry = decode[br.peek_bits(DOUBLE_TABLE_LOG) as usize];
+    br.consume(entry.num_bits);
+    output
+        .get_unchecked_mut(position..position + 2)
+        .copy_from_slice(&entry.symbols);
+    1 + entry.has_second_symbol as usize
+}
+
+fn double_table(table: HuffmanDecodeTables<'_>) -> &[DoubleEntry; 1 << DOUBLE_TABLE_LOG] {
+    table
+        .decode_double
+        .try_into()
+        .expect("double table has the wrong size")
 }
@@ -0,0 +1,139 @@
+//! Wide copies for the matches in Decodebuffer::repeat.
+//!
+//! All copies here may write up to WILD_COPY_SLACK bytes past the end of the match. The caller must have reserved that
+//! much space behind the match. The bytes written past the end are garbage and will be overwritten by the next push/repeat.
+//!
+//! The copies never read bytes that have not been written before, so nothing uninitialized is ever read, even though
+//! the slack space is.
+
+use core::ptr;
+
+/// How many bytes the copies may write past the end of the match
+pub const WILD_COPY_SLACK: usize = 32;
+
+/// Which copy loop is used for matches that do not overlap with their own output in a way that matters
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CopyStrategy {
+    /// 16 bytes per step with plain unaligned loads and stores. Works on every target
+    Portable,
+    /// 32 bytes per step with AVX2 loads and stores, if the offset allows it
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+}
+
+impl CopyStrategy {
+    /// The widest strategy the CPU supports. Without std features can only be detected at compile time
+    pub fn detect() -> CopyStrategy {
+        #[cfg(all(target_arch = "x86_64", feature = "std"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return CopyStrategy::Avx2;
+            }
+        }
+        #[cfg(all(target_arch = "x86_64", not(feature = "std"), target_feature = "avx2"))]
+        {
+            return CopyStrategy::Avx2;
+        }
+        #[allow(unreachable_code)]
+        CopyStrategy::Portable
+    }
+
+    /// Whether this strategy can be used on the current CPU
+    pub fn is_supported(self) -> bool {
+        match self {
+            CopyStrategy::Portable => true,
+            #[cfg(target_arch = "x86_64")]
+            CopyStrategy::Avx2 => CopyStrategy::detect() == CopyStrategy::Avx2,
+        }
+    }
+}
+
+/// Copies match_length bytes that start offset bytes before dst to dst, like a byte by byte copy would.
+/// This means for offset < match_length the copied bytes repeat with a period of offset.
+///
+/// # Safety
+/// * offset must be > 0 and dst - offset must be in the same allocation as dst
+/// * dst..dst + match_length + WILD_COPY_SLACK must be writable
+/// * strategy must be supported by the CPU
+#[inline(always)]
+pub unsafe fn copy_match(strategy: CopyStrategy, dst: *mut u8, offset: usize, match_length: usize) {
+    debug_assert!(offset > 0);
+    debug_assert!(strategy.is_supported());
+    let end = dst.add(match_length);
+    let mut dst = dst;
+    let mut offset = offset;
+
+    if offset < 16 {
+        match offset {
+            1 => {
+                ptr::write_bytes(dst, *dst.sub(1), match_length);
+                return;
+            }
+            2 | 4 | 8 => {
+                // the pattern fits evenly into 16 bytes, write the pattern instead of copying
+                let mut pattern = [0u8; 16];
+                for (idx, byte) in pattern.iter_mut().enumerate() {
+                    *byte = *dst.sub(offset).add(idx % offset);
+                }
+                while dst < end {
+                    ptr::copy_nonoverlapping(pattern.as_ptr(), dst, 16);
+                    dst = dst.add(16);
+                }
+                return;
+            }
+            _ => {
+                // Copy one period at a time. After each copy twice the distance is also a multiple of the period,
+                // so the distance can be doubled until it is big enough for the wide copies
+                while offset < 16 {
+                    ptr::copy_nonoverlapping(dst.sub(offset), dst, offset);
+                    dst = dst.add(offset);
+                    offset *= 2;
+                    if dst >= end {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    match strategy {
+        CopyStrategy::Portable => wild_copy_16(dst.sub(offset), dst, end),
+        #[cfg(target_arch = "x86_64")]
+        CopyStrategy::Avx2 => {
+            if offset >= 32 {
+                wild_copy_32_avx2(dst.sub(offset), dst, end)
+            } else {
+                wild_copy_16(dst.sub(offset), dst, end)
+            }
+        }
+    }
+}
+
+/// Copies in steps of 16 bytes until dst reaches end. src must be at least 16 bytes before dst
+#[inline(always)]
+unsafe fn wild_copy_16(mut src: *const u8, mut dst: *mut u8, end: *mut u8) {
+    loop {
+        ptr::copy_nonoverlapping(src, dst, 16);
+        src = src.add(16);
+        dst = dst.add(16);
+        if dst >= end {
+            break;
+        }
+    }
+}
+
+/// Copies in steps of 32 bytes until dst reaches end. src must be at least 32 bytes before dst
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn wild_copy_32_avx2(mut src: *const u8, mut dst: *mut u8, end: *mut u8) {
+    use core::arch::x86_64::{__m256i, _mm256_loadu_si256, _mm256_storeu_si256};
+    loop {
+        let chunk = _mm256_loadu_si256(src as *const __m256i);
+        _mm256_storeu_si256(dst as *mut __m256i, chunk);
+        src = src.add(32);
+        dst = dst.add(32);
+        if dst >= end {
+            break;
+        }
+    }
+}
@@ -1,10 +1,25 @@
 pub mod bit_reader;
 pub mod bit_reader_reverse;
+#[cfg(feature = "alloc")]
 pub mod block_decoder;
+#[cfg(feature = "alloc")]
+pub mod block_observer;
+#[cfg(feature = "alloc")]
 pub mod decodebuffer;
+#[cfg(feature = "alloc")]
 pub mod dictionary;
+#[cfg(feature = "alloc")]
+pub mod limits;
 pub mod literals_section_decoder;
+pub mod match_copy;
+#[cfg(feature = "alloc")]
+pub mod progress;
+#[cfg(feature = "alloc")]
 #[allow(dead_code)]
 pub mod scratch;
+#[cfg(feature = "alloc")]
 pub mod sequence_execution;
 pub mod sequence_section_decoder;
+#[cfg(feature = "alloc")]
+pub mod snapshot;
+pub mod xxhash64;
@@ -0,0 +1,42 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+
+/// Passed to the progress hook of a FrameDecoder after every decoded block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// How many frames this decoder has been reset for before the current one
+    pub frame_index: u64,
+    /// How many blocks of the current frame have been decoded
+    pub blocks_decoded: usize,
+    /// Bytes consumed from the source for the current frame, including the frame header
+    pub bytes_read: u64,
+    /// Bytes the current frame has decoded to so far, collected or not
+    pub bytes_decoded: u64,
+    /// The content size from the frame header, if it has one
+    pub content_size: Option<u64>,
+}
+
+/// Callback that is called with the Progress after every block, see FrameDecoder::set_progress_hook()
+pub type ProgressHook = dyn FnMut(&Progress) + Send;
+
+/// Makes decoding stop at the next block boundary with FrameDecoderError::Cancelled, after cancel() was called on it or any of its clones.
+/// The clones can be sent to other threads, e.g. the UI thread that has the cancel button
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
@@ -1,12 +1,17 @@
 use super::super::blocks::sequence_section::Sequence;
+use super::block_observer::BlockObserver;
 use super::decodebuffer::Decodebuffer;
+use super::sequence_section_decoder::SequenceTables;
 use crate::decoding::dictionary::Dictionary;
+use crate::errors::DictionaryDecodeError;
 use crate::fse::FSETable;
 use crate::huff0::HuffmanTable;
 #[cfg(feature = "alloc")]
-use alloc::vec::Vec;
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
 #[cfg(feature = "alloc")]
-use alloc::string::String;
+use alloc::vec::Vec;
 
 pub struct DecoderScratch {
     pub huf: HuffmanScratch,
@@ -17,6 +22,8 @@ pub struct DecoderScratch {
     pub literals_buffer: Vec<u8>,
     pub sequences: Vec<Sequence>,
     pub block_content_buffer: Vec<u8>,
+    /// Not changed by reset()
+    pub block_observer: Option<Box<dyn BlockObserver + Send>>,
 }
 
 impl DecoderScratch {
@@ -39,6 +46,7 @@ impl DecoderScratch {
             block_content_buffer: Vec::new(),
             literals_buffer: Vec::new(),
             sequences: Vec::new(),
+            block_observer: None,
         }
     }
 
@@ -49,7 +57,11 @@ impl DecoderScratch {
         self.block_content_buffer.clear();
 
         self.buffer.reset(window_size);
+        self.reset_tables();
+    }
 
+    /// Forgets the huffman and FSE tables, blocks that want to repeat them fail until new ones are read
+    pub fn reset_tables(&mut self) {
         self.fse.literal_lengths.reset();
         self.fse.match_lengths.reset();
         self.fse.offsets.reset();
@@ -60,24 +72,52 @@ impl DecoderScratch {
         self.huf.table.reset();
     }
 
-    pub fn use_dict(&mut self, dict: &Dictionary) {
-        self.fse = dict.fse.clone();
-        self.huf = dict.huf.clone();
+    /// Sets the tables and offsets from the dictionary. The dictionary content is shared, not copied.
+    /// The tables are copied into the existing ones, so switching dictionaries does not allocate once the tables are big enough
+    pub fn use_dict(&mut self, dict: &Arc<Dictionary>) {
+        self.fse.copy_from(&dict.fse);
+        self.huf.copy_from(&dict.huf);
         self.offset_hist = dict.offset_hist;
-        self.buffer.dict_content = dict.dict_content.clone();
+        self.buffer.dict = Some(Arc::clone(dict));
     }
 
     /// parses the dictionary and set the tables
     /// it returns the dict_id for checking with the frame's dict_id
-    pub fn load_dict(&mut self, raw: &[u8]) -> Result<u32, String> {
-        let dict = super::dictionary::Dictionary::decode_dict(raw)?;
-
-        self.huf = dict.huf.clone();
-        self.fse = dict.fse.clone();
-        self.offset_hist = dict.offset_hist;
-        self.buffer.dict_content = dict.dict_content.clone();
+    pub fn load_dict(&mut self, raw: &[u8]) -> Result<u32, DictionaryDecodeError> {
+        let dict = Arc::new(Dictionary::decode_dict(raw)?);
+        self.use_dict(&dict);
         Ok(dict.id)
     }
+
+    /// Address and capacity of all buffers owned by the scratch. If one of them changes, the buffer has been reallocated
+    pub fn buffers(&self) -> [(usize, usize); NUM_BUFFERS] {
+        let [decode, decode_double, weights] = self.huf.table.buffers();
+        let [of_decode, of_probs] = self.fse.offsets.buffers();
+        let [ll_decode, ll_probs] = self.fse.literal_lengths.buffers();
+        let [ml_decode, ml_probs] = self.fse.match_lengths.buffers();
+        [
+            buffer(&self.buffer.buffer),
+            buffer(&self.literals_buffer),
+            buffer(&self.sequences),
+            buffer(&self.block_content_buffer),
+            decode,
+            decode_double,
+            weights,
+            of_decode,
+            of_probs,
+            ll_decode,
+            ll_probs,
+            ml_decode,
+            ml_probs,
+        ]
+    }
+}
+
+/// How many buffers DecoderScratch::buffers() reports
+pub const NUM_BUFFERS: usize = 13;
+
+fn buffer<T>(v: &Vec<T>) -> (usize, usize) {
+    (v.as_ptr() as usize, v.capacity())
 }
 
 #[derive(Clone)]
@@ -91,6 +131,11 @@ impl HuffmanScratch {
             table: HuffmanTable::new(),
         }
     }
+
+    /// Like clone() but reuses the allocations of self
+    pub fn copy_from(&mut self, other: &HuffmanScratch) {
+        self.table.copy_from(&other.table);
+    }
 }
 
 impl Default for HuffmanScratch {
@@ -120,6 +165,28 @@ impl FSEScratch {
             ml_rle: None,
         }
     }
+
+    /// Like clone() but reuses the allocations of self
+    pub fn copy_from(&mut self, other: &FSEScratch) {
+        self.offsets.copy_from(&other.offsets);
+        self.of_rle = other.of_rle;
+        self.literal_lengths.copy_from(&other.literal_lengths);
+        self.ll_rle = other.ll_rle;
+        self.match_lengths.copy_from(&other.match_lengths);
+        self.ml_rle = other.ml_rle;
+    }
+
+    /// The tables and RLE symbols of the three sequence fields
+    pub fn tables(&mut self) -> SequenceTables<'_, FSETable> {
+        SequenceTables {
+            literal_lengths: &mut self.literal_lengths,
+            ll_rle: &mut self.ll_rle,
+            offsets: &mut self.offsets,
+            of_rle: &mut self.of_rle,
+            match_lengths: &mut self.match_lengths,
+            ml_rle: &mut self.ml_rle,
+        }
+    }
 }
 
 impl Default for FSEScratch {
@@ -1,10 +1,9 @@
+use super::super::blocks::sequence_section::do_offset_history;
+use super::decodebuffer::Decodebuffer;
 use super::scratch::DecoderScratch;
-#[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
-#[cfg(feature = "alloc")]
-use alloc::string::String;
+use crate::errors::ExecuteSequencesError;
 
-pub fn execute_sequences(scratch: &mut DecoderScratch) -> Result<(), String> {
+pub fn execute_sequences(scratch: &mut DecoderScratch) -> Result<(), ExecuteSequencesError> {
     let mut literals_copy_counter = 0;
     let old_buffer_size = scratch.buffer.len();
     let mut seq_sum = 0;
@@ -12,44 +11,78 @@ pub fn execute_sequences(scratch: &mut DecoderScratch) -> Result<(), String> {
     for idx in 0..scratch.sequences.len() {
         let seq = scratch.sequences[idx];
 
-        if seq.ll > 0 {
-            let high = literals_copy_counter + seq.ll as usize;
-            if high > scratch.literals_buffer.len() {
-                return Err(format!(
-                    "Sequence wants to copy up to byte {}. Bytes in literalsbuffer: {}",
-                    high,
-                    scratch.literals_buffer.len()
-                ));
-            }
-            let literals = &scratch.literals_buffer[literals_copy_counter..high];
-            literals_copy_counter += seq.ll as usize;
+        let actual_offset = do_offset_history(seq.of, seq.ll, &mut scratch.offset_hist);
+        execute_sequence(
+            &mut scratch.buffer,
+            &scratch.literals_buffer,
+            &mut literals_copy_counter,
+            seq.ll,
+            seq.ml,
+            actual_offset,
+        )?;
 
-            //for x in literals {
-            //}
+        seq_sum += seq.ml;
+        seq_sum += seq.ll;
+    }
 
-            scratch.buffer.push(literals);
-        }
+    push_rest_literals(
+        &mut scratch.buffer,
+        &scratch.literals_buffer,
+        literals_copy_counter,
+        old_buffer_size,
+        seq_sum,
+    )
+}
 
-        let actual_offset = do_offset_history(seq.of, seq.ll, &mut scratch.offset_hist);
-        if actual_offset == 0 {
-            return Err("Illegal offset: 0 found".to_owned());
-        }
-        if seq.ml > 0 {
-            scratch
-                .buffer
-                .repeat(actual_offset as usize, seq.ml as usize)?;
+/// Pushes the literals of one sequence and then repeats the match. Fails before the buffer grows if that would exceed the output limit
+fn execute_sequence(
+    buffer: &mut Decodebuffer,
+    literals_buffer: &[u8],
+    literals_copy_counter: &mut usize,
+    ll: u32,
+    ml: u32,
+    actual_offset: u32,
+) -> Result<(), ExecuteSequencesError> {
+    buffer.check_output_limit(ll as usize + ml as usize)?;
+    if ll > 0 {
+        let high = *literals_copy_counter + ll as usize;
+        if high > literals_buffer.len() {
+            return Err(ExecuteSequencesError::NotEnoughLiterals {
+                need: high,
+                have: literals_buffer.len(),
+            });
         }
+        let literals = &literals_buffer[*literals_copy_counter..high];
+        *literals_copy_counter += ll as usize;
 
-        seq_sum += seq.ml;
-        seq_sum += seq.ll;
+        buffer.push(literals);
+    }
+
+    if actual_offset == 0 {
+        return Err(ExecuteSequencesError::ZeroOffset);
     }
-    if literals_copy_counter < scratch.literals_buffer.len() {
-        let rest_literals = &scratch.literals_buffer[literals_copy_counter..];
-        scratch.buffer.push(rest_literals);
+    if ml > 0 {
+        buffer.repeat(actual_offset as usize, ml as usize)?;
+    }
+    Ok(())
+}
+
+/// Pushes the literals that were not used by any sequence and checks that the buffer grew by the expected amount
+fn push_rest_literals(
+    buffer: &mut Decodebuffer,
+    literals_buffer: &[u8],
+    literals_copy_counter: usize,
+    old_buffer_size: usize,
+    mut seq_sum: u32,
+) -> Result<(), ExecuteSequencesError> {
+    if literals_copy_counter < literals_buffer.len() {
+        let rest_literals = &literals_buffer[literals_copy_counter..];
+        buffer.check_output_limit(rest_literals.len())?;
+        buffer.push(rest_literals);
         seq_sum += rest_literals.len() as u32;
     }
 
-    let diff = scratch.buffer.len() - old_buffer_size;
+    let diff = buffer.len() - old_buffer_size;
     assert!(
         seq_sum as usize == diff,
         "Seq_sum: {} is different from the difference in buffersize: {}",
@@ -58,61 +91,3 @@ pub fn execute_sequences(scratch: &mut DecoderScratch) -> Result<(), String> {
     );
     Ok(())
 }
-
-fn do_offset_history(offset_value: u32, lit_len: u32, scratch: &mut [u32; 3]) -> u32 {
-    let actual_offset = if lit_len > 0 {
-        match offset_value {
-            1..=3 => scratch[offset_value as usize - 1],
-            _ => {
-                //new offset
-                offset_value - 3
-            }
-        }
-    } else {
-        match offset_value {
-            1..=2 => scratch[offset_value as usize],
-            3 => scratch[0] - 1,
-            _ => {
-                //new offset
-                offset_value - 3
-            }
-        }
-    };
-
-    //update history
-    if lit_len > 0 {
-        match offset_value {
-            1 => {
-                //nothing
-            }
-            2 => {
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-            _ => {
-                scratch[2] = scratch[1];
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-        }
-    } else {
-        match offset_value {
-            1 => {
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-            2 => {
-                scratch[2] = scratch[1];
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-            _ => {
-                scratch[2] = scratch[1];
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-        }
-    }
-
-    actual_offset
-}
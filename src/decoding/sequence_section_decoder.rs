@@ -1,32 +1,69 @@
 use super::super::blocks::sequence_section::ModeType;
 use super::super::blocks::sequence_section::Sequence;
 use super::super::blocks::sequence_section::SequencesHeader;
-use super::bit_reader_reverse::BitReaderReversed;
-use super::scratch::FSEScratch;
-use crate::fse::FSEDecoder;
+use super::super::blocks::sequence_section::{
+    lookup_ll_code, lookup_ml_code, LITERALS_LENGTH_DEFAULT_DISTRIBUTION, LL_DEFAULT_ACC_LOG,
+    MATCH_LENGTH_DEFAULT_DISTRIBUTION, ML_DEFAULT_ACC_LOG, OFFSET_DEFAULT_DISTRIBUTION,
+    OF_DEFAULT_ACC_LOG,
+};
+pub use super::super::blocks::sequence_section::{LL_MAX_LOG, ML_MAX_LOG, OF_MAX_LOG};
+use super::bit_reader_reverse::{BitReaderReversed, MIN_BITS_AFTER_REFILL};
 #[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
+use super::scratch::FSEScratch;
+use crate::errors::DecodeSequenceError;
+use crate::fse::FSETableStorage;
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
-#[cfg(feature = "alloc")]
-use alloc::string::String;
 
+/// The tables of the three sequence fields and their RLE symbols, borrowed from wherever they are stored.
+/// An RLE symbol that is set is used instead of the table
+pub struct SequenceTables<'a, T: FSETableStorage> {
+    pub literal_lengths: &'a mut T,
+    pub ll_rle: &'a mut Option<u8>,
+    pub offsets: &'a mut T,
+    pub of_rle: &'a mut Option<u8>,
+    pub match_lengths: &'a mut T,
+    pub ml_rle: &'a mut Option<u8>,
+}
+
+#[cfg(feature = "alloc")]
 pub fn decode_sequences(
     section: &SequencesHeader,
     source: &[u8],
     scratch: &mut FSEScratch,
     target: &mut Vec<Sequence>,
-) -> Result<(), String> {
-    let bytes_read = maybe_update_fse_tables(section, source, scratch)?;
+) -> Result<(), DecodeSequenceError> {
+    target.clear();
+    target.reserve(section.num_sequences as usize);
 
-    let bit_stream = &source[bytes_read..];
+    let mut tables = scratch.tables();
+    let bytes_read = update_sequence_tables(section, source, &mut tables)?;
+    decode_sequences_from_tables(section, &source[bytes_read..], &tables, |seq| {
+        target.push(seq);
+        Ok(())
+    })
+}
 
+/// Decodes the sequences from bit_stream, the part of the sequence section after the table descriptions.
+/// Each sequence is handed to handle_sequence as soon as it is decoded, in order.
+/// The errors of handle_sequence are passed on, so it can use the error type of the caller
+pub fn decode_sequences_from_tables<T, E, F>(
+    section: &SequencesHeader,
+    bit_stream: &[u8],
+    tables: &SequenceTables<'_, T>,
+    handle_sequence: F,
+) -> Result<(), E>
+where
+    T: FSETableStorage,
+    E: From<DecodeSequenceError>,
+    F: FnMut(Sequence) -> Result<(), E>,
+{
     let mut br = BitReaderReversed::new(bit_stream);
 
     //skip the 0 padding at the end of the last byte of the bit stream and throw away the first 1 found
     let mut skipped_bits = 0;
     loop {
-        let val = br.get_bits(1)?;
+        let val = br.get_bits(1).map_err(DecodeSequenceError::from)?;
         skipped_bits += 1;
         if val == 1 || skipped_bits > 8 {
             break;
@@ -34,53 +71,58 @@ pub fn decode_sequences(
     }
     if skipped_bits > 8 {
         //if more than 7 bits are 0, this is not the correct end of the bitstream. Either a bug or corrupted data
-        return Err(format!("Padding at the end of the sequence_section was more than a byte long: {}. Probably cause by data corruption", skipped_bits));
+        return Err(DecodeSequenceError::ExtraPadding { skipped_bits }.into());
     }
 
-    if scratch.ll_rle.is_some() || scratch.ml_rle.is_some() || scratch.of_rle.is_some() {
-        decode_sequences_with_rle(section, &mut br, scratch, target)
+    if tables.ll_rle.is_some() || tables.ml_rle.is_some() || tables.of_rle.is_some() {
+        decode_sequences_with_rle(section, &mut br, tables, handle_sequence)
     } else {
-        decode_sequences_without_rle(section, &mut br, scratch, target)
+        decode_sequences_without_rle(section, &mut br, tables, handle_sequence)
     }
 }
 
-fn decode_sequences_with_rle(
+fn decode_sequences_with_rle<T, E, F>(
     section: &SequencesHeader,
     br: &mut BitReaderReversed<'_>,
-    scratch: &mut FSEScratch,
-    target: &mut Vec<Sequence>,
-) -> Result<(), String> {
-    let mut ll_dec = FSEDecoder::new(&scratch.literal_lengths);
-    let mut ml_dec = FSEDecoder::new(&scratch.match_lengths);
-    let mut of_dec = FSEDecoder::new(&scratch.offsets);
-
-    if scratch.ll_rle.is_none() {
-        ll_dec.init_state(br)?;
+    tables: &SequenceTables<'_, T>,
+    mut handle_sequence: F,
+) -> Result<(), E>
+where
+    T: FSETableStorage,
+    E: From<DecodeSequenceError>,
+    F: FnMut(Sequence) -> Result<(), E>,
+{
+    let ll_rle = *tables.ll_rle;
+    let ml_rle = *tables.ml_rle;
+    let of_rle = *tables.of_rle;
+    let mut ll_dec = tables.literal_lengths.decoder();
+    let mut ml_dec = tables.match_lengths.decoder();
+    let mut of_dec = tables.offsets.decoder();
+
+    if ll_rle.is_none() {
+        ll_dec.init_state(br).map_err(DecodeSequenceError::from)?;
     }
-    if scratch.of_rle.is_none() {
-        of_dec.init_state(br)?;
+    if of_rle.is_none() {
+        of_dec.init_state(br).map_err(DecodeSequenceError::from)?;
     }
-    if scratch.ml_rle.is_none() {
-        ml_dec.init_state(br)?;
+    if ml_rle.is_none() {
+        ml_dec.init_state(br).map_err(DecodeSequenceError::from)?;
     }
 
-    target.clear();
-    target.reserve(section.num_sequences as usize);
-
-    for _seq_idx in 0..section.num_sequences {
+    for seq_idx in 0..section.num_sequences {
         //get the codes from either the RLE byte or from the decoder
-        let ll_code = if scratch.ll_rle.is_some() {
-            scratch.ll_rle.unwrap()
+        let ll_code = if let Some(rle) = ll_rle {
+            rle
         } else {
             ll_dec.decode_symbol()
         };
-        let ml_code = if scratch.ml_rle.is_some() {
-            scratch.ml_rle.unwrap()
+        let ml_code = if let Some(rle) = ml_rle {
+            rle
         } else {
             ml_dec.decode_symbol()
         };
-        let of_code = if scratch.of_rle.is_some() {
-            scratch.of_rle.unwrap()
+        let of_code = if let Some(rle) = of_rle {
+            rle
         } else {
             of_dec.decode_symbol()
         };
@@ -88,77 +130,85 @@ fn decode_sequences_with_rle(
         let (ll_value, ll_num_bits) = lookup_ll_code(ll_code);
         let (ml_value, ml_num_bits) = lookup_ml_code(ml_code);
 
-
         if of_code >= 32 {
-            return Err("Do not support offsets bigger than 1<<32".to_owned());
+            return Err(DecodeSequenceError::UnsupportedOffset {
+                offset_code: of_code,
+            }
+            .into());
         }
 
-        let offset = (br.get_bits(of_code as usize)? as u32) + (1u32 << of_code);
-        let ml_add = br.get_bits(ml_num_bits as usize)?;
-        let ll_add = br.get_bits(ll_num_bits as usize)?;
+        // of_code < 32 and ml_num_bits <= 16 so one refill is enough for these two
+        br.refill();
+        let offset = (br.get_bits_unchecked(of_code) as u32) + (1u32 << of_code);
+        let ml_add = br.get_bits_unchecked(ml_num_bits);
+        if of_code + ml_num_bits + ll_num_bits > MIN_BITS_AFTER_REFILL {
+            br.refill();
+        }
+        let ll_add = br.get_bits_unchecked(ll_num_bits);
 
         if offset == 0 {
-            return Err("Read an offset == 0. That is an illegal value for offsets".to_owned());
+            return Err(DecodeSequenceError::ZeroOffset.into());
         }
 
-        target.push(Sequence {
+        handle_sequence(Sequence {
             ll: ll_value + ll_add as u32,
             ml: ml_value + ml_add as u32,
             of: offset,
-        });
+        })?;
 
-        if target.len() < section.num_sequences as usize {
+        if seq_idx + 1 < section.num_sequences {
             //    "Bits left: {} ({} bytes)",
             //    br.bits_remaining(),
             //    br.bits_remaining() / 8,
             //);
-            if scratch.ll_rle.is_none() {
-                ll_dec.update_state(br)?;
+            // the accuracy logs are at most 9 + 9 + 8 bits, one refill is enough for all three updates
+            br.refill();
+            if ll_rle.is_none() {
+                ll_dec.update_state_fast(br);
             }
-            if scratch.ml_rle.is_none() {
-                ml_dec.update_state(br)?;
+            if ml_rle.is_none() {
+                ml_dec.update_state_fast(br);
             }
-            if scratch.of_rle.is_none() {
-                of_dec.update_state(br)?;
+            if of_rle.is_none() {
+                of_dec.update_state_fast(br);
             }
         }
 
         if br.bits_remaining() < 0 {
-            return Err(
-                "Bytestream did not contain enough bytes to decode num_sequences".to_owned(),
-            );
+            return Err(DecodeSequenceError::NotEnoughBytesForNumSequences.into());
         }
     }
 
     if br.bits_remaining() > 0 {
-        Err(format!(
-            "Did not use full bitstream. Bits left: {} ({} bytes)",
-            br.bits_remaining(),
-            br.bits_remaining() / 8,
-        ))
+        Err(DecodeSequenceError::ExtraBits {
+            bits_remaining: br.bits_remaining(),
+        }
+        .into())
     } else {
         Ok(())
     }
 }
 
-fn decode_sequences_without_rle(
+fn decode_sequences_without_rle<T, E, F>(
     section: &SequencesHeader,
     br: &mut BitReaderReversed<'_>,
-    scratch: &mut FSEScratch,
-    target: &mut Vec<Sequence>,
-) -> Result<(), String> {
-    let mut ll_dec = FSEDecoder::new(&scratch.literal_lengths);
-    let mut ml_dec = FSEDecoder::new(&scratch.match_lengths);
-    let mut of_dec = FSEDecoder::new(&scratch.offsets);
-
-    ll_dec.init_state(br)?;
-    of_dec.init_state(br)?;
-    ml_dec.init_state(br)?;
-
-    target.clear();
-    target.reserve(section.num_sequences as usize);
-
-    for _seq_idx in 0..section.num_sequences {
+    tables: &SequenceTables<'_, T>,
+    mut handle_sequence: F,
+) -> Result<(), E>
+where
+    T: FSETableStorage,
+    E: From<DecodeSequenceError>,
+    F: FnMut(Sequence) -> Result<(), E>,
+{
+    let mut ll_dec = tables.literal_lengths.decoder();
+    let mut ml_dec = tables.match_lengths.decoder();
+    let mut of_dec = tables.offsets.decoder();
+
+    ll_dec.init_state(br).map_err(DecodeSequenceError::from)?;
+    of_dec.init_state(br).map_err(DecodeSequenceError::from)?;
+    ml_dec.init_state(br).map_err(DecodeSequenceError::from)?;
+
+    for seq_idx in 0..section.num_sequences {
         let ll_code = ll_dec.decode_symbol();
         let ml_code = ml_dec.decode_symbol();
         let of_code = of_dec.decode_symbol();
@@ -167,223 +217,158 @@ fn decode_sequences_without_rle(
         let (ml_value, ml_num_bits) = lookup_ml_code(ml_code);
 
         if of_code >= 32 {
-            return Err("Do not support offsets bigger than 1<<32".to_owned());
+            return Err(DecodeSequenceError::UnsupportedOffset {
+                offset_code: of_code,
+            }
+            .into());
         }
 
-        let offset = (br.get_bits(of_code as usize)? as u32) + (1u32 << of_code);
-        let ml_add = br.get_bits(ml_num_bits as usize)?;
-        let ll_add = br.get_bits(ll_num_bits as usize)?;
+        // of_code < 32 and ml_num_bits <= 16 so one refill is enough for these two
+        br.refill();
+        let offset = (br.get_bits_unchecked(of_code) as u32) + (1u32 << of_code);
+        let ml_add = br.get_bits_unchecked(ml_num_bits);
+        if of_code + ml_num_bits + ll_num_bits > MIN_BITS_AFTER_REFILL {
+            br.refill();
+        }
+        let ll_add = br.get_bits_unchecked(ll_num_bits);
 
         if offset == 0 {
-            return Err("Read an offset == 0. That is an illegal value for offsets".to_owned());
+            return Err(DecodeSequenceError::ZeroOffset.into());
         }
 
-        target.push(Sequence {
+        handle_sequence(Sequence {
             ll: ll_value + ll_add as u32,
             ml: ml_value + ml_add as u32,
             of: offset,
-        });
+        })?;
 
-        if target.len() < section.num_sequences as usize {
+        if seq_idx + 1 < section.num_sequences {
             //    "Bits left: {} ({} bytes)",
             //    br.bits_remaining(),
             //    br.bits_remaining() / 8,
             //);
-            ll_dec.update_state(br)?;
-            ml_dec.update_state(br)?;
-            of_dec.update_state(br)?;
+            // the accuracy logs are at most 9 + 9 + 8 bits, one refill is enough for all three updates
+            br.refill();
+            ll_dec.update_state_fast(br);
+            ml_dec.update_state_fast(br);
+            of_dec.update_state_fast(br);
         }
 
         if br.bits_remaining() < 0 {
-            return Err(
-                "Bytestream did not contain enough bytes to decode num_sequences".to_owned(),
-            );
+            return Err(DecodeSequenceError::NotEnoughBytesForNumSequences.into());
         }
     }
 
     if br.bits_remaining() > 0 {
-        Err(format!(
-            "Did not use full bitstream. Bits left: {} ({} bytes)",
-            br.bits_remaining(),
-            br.bits_remaining() / 8,
-        ))
+        Err(DecodeSequenceError::ExtraBits {
+            bits_remaining: br.bits_remaining(),
+        }
+        .into())
     } else {
         Ok(())
     }
 }
 
-fn lookup_ll_code(code: u8) -> (u32, u8) {
-    match code {
-        0..=15 => (code as u32, 0),
-        16 => (16, 1),
-        17 => (18, 1),
-        18 => (20, 1),
-        19 => (22, 1),
-        20 => (24, 2),
-        21 => (28, 2),
-        22 => (32, 3),
-        23 => (40, 3),
-        24 => (48, 4),
-        25 => (64, 6),
-        26 => (128, 7),
-        27 => (256, 8),
-        28 => (512, 9),
-        29 => (1024, 10),
-        30 => (2048, 11),
-        31 => (4096, 12),
-        32 => (8192, 13),
-        33 => (16384, 14),
-        34 => (32768, 15),
-        35 => (65536, 16),
-        _ => (0, 255),
-    }
-}
-
-fn lookup_ml_code(code: u8) -> (u32, u8) {
-    match code {
-        0..=31 => (code as u32 + 3, 0),
-        32 => (35, 1),
-        33 => (37, 1),
-        34 => (39, 1),
-        35 => (41, 1),
-        36 => (43, 2),
-        37 => (47, 2),
-        38 => (51, 3),
-        39 => (59, 3),
-        40 => (67, 4),
-        41 => (83, 4),
-        42 => (99, 5),
-        43 => (131, 7),
-        44 => (259, 8),
-        45 => (515, 9),
-        46 => (1027, 10),
-        47 => (2051, 11),
-        48 => (4099, 12),
-        49 => (8195, 13),
-        50 => (16387, 14),
-        51 => (32771, 15),
-        52 => (65539, 16),
-        _ => (0, 255),
-    }
+/// What differs between the tables of the three sequence fields
+struct Field {
+    max_log: u8,
+    default_acc_log: u8,
+    default_distribution: &'static [i32],
+    missing_rle_byte: DecodeSequenceError,
 }
 
-pub const LL_MAX_LOG: u8 = 9;
-pub const ML_MAX_LOG: u8 = 9;
-pub const OF_MAX_LOG: u8 = 8;
-
-fn maybe_update_fse_tables(
+const LITERAL_LENGTHS: Field = Field {
+    max_log: LL_MAX_LOG,
+    default_acc_log: LL_DEFAULT_ACC_LOG,
+    default_distribution: &LITERALS_LENGTH_DEFAULT_DISTRIBUTION,
+    missing_rle_byte: DecodeSequenceError::MissingByteForRleLlTable,
+};
+
+const OFFSETS: Field = Field {
+    max_log: OF_MAX_LOG,
+    default_acc_log: OF_DEFAULT_ACC_LOG,
+    default_distribution: &OFFSET_DEFAULT_DISTRIBUTION,
+    missing_rle_byte: DecodeSequenceError::MissingByteForRleOfTable,
+};
+
+const MATCH_LENGTHS: Field = Field {
+    max_log: ML_MAX_LOG,
+    default_acc_log: ML_DEFAULT_ACC_LOG,
+    default_distribution: &MATCH_LENGTH_DEFAULT_DISTRIBUTION,
+    missing_rle_byte: DecodeSequenceError::MissingByteForRleMlTable,
+};
+
+/// Builds the tables the modes of the section ask for from the start of source. Returns how many bytes were read
+pub fn update_sequence_tables<T: FSETableStorage>(
     section: &SequencesHeader,
     source: &[u8],
-    scratch: &mut FSEScratch,
-) -> Result<usize, String> {
+    tables: &mut SequenceTables<'_, T>,
+) -> Result<usize, DecodeSequenceError> {
     let modes = match section.modes {
         Some(m) => m,
-        None => {
-            return Err("compression modes are none but they must be set to something".to_owned())
-        }
+        None => return Err(DecodeSequenceError::MissingCompressionMode),
     };
 
-    let mut bytes_read = 0;
-
-    match modes.ll_mode() {
-        ModeType::FSECompressed => {
-            let bytes = scratch.literal_lengths.build_decoder(source, LL_MAX_LOG)?;
-            bytes_read += bytes;
-            scratch.ll_rle = None;
-        }
-        ModeType::RLE => {
-            if source.is_empty() {
-                return Err("Need a byte to read for RLE ll table".to_owned());
-            }
-            bytes_read += 1;
-            scratch.ll_rle = Some(source[0]);
-        }
-        ModeType::Predefined => {
-            scratch.literal_lengths.build_from_probabilities(
-                LL_DEFAULT_ACC_LOG,
-                &Vec::from(&LITERALS_LENGTH_DEFAULT_DISTRIBUTION[..]),
-            )?;
-            scratch.ll_rle = None;
-        }
-        ModeType::Repeat => {
-            /* Nothing to do */
-        }
-    };
+    let mut bytes_read = update_table(
+        modes.ll_mode(),
+        source,
+        tables.literal_lengths,
+        tables.ll_rle,
+        &LITERAL_LENGTHS,
+    )?;
+    bytes_read += update_table(
+        modes.of_mode(),
+        &source[bytes_read..],
+        tables.offsets,
+        tables.of_rle,
+        &OFFSETS,
+    )?;
+    bytes_read += update_table(
+        modes.ml_mode(),
+        &source[bytes_read..],
+        tables.match_lengths,
+        tables.ml_rle,
+        &MATCH_LENGTHS,
+    )?;
 
-    let of_source = &source[bytes_read..];
+    Ok(bytes_read)
+}
 
-    match modes.of_mode() {
+fn update_table<T: FSETableStorage>(
+    mode: ModeType,
+    source: &[u8],
+    table: &mut T,
+    rle: &mut Option<u8>,
+    field: &Field,
+) -> Result<usize, DecodeSequenceError> {
+    match mode {
         ModeType::FSECompressed => {
-            let bytes = scratch.offsets.build_decoder(of_source, OF_MAX_LOG)?;
-            bytes_read += bytes;
-            scratch.of_rle = None;
+            let bytes_read = table.build_decoder(source, field.max_log)?;
+            *rle = None;
+            Ok(bytes_read)
         }
         ModeType::RLE => {
-            if of_source.is_empty() {
-                return Err("Need a byte to read for RLE of table".to_owned());
+            if source.is_empty() {
+                return Err(field.missing_rle_byte);
             }
-            bytes_read += 1;
-            scratch.of_rle = Some(of_source[0]);
+            *rle = Some(source[0]);
+            Ok(1)
         }
         ModeType::Predefined => {
-            scratch.offsets.build_from_probabilities(
-                OF_DEFAULT_ACC_LOG,
-                &Vec::from(&OFFSET_DEFAULT_DISTRIBUTION[..]),
-            )?;
-            scratch.of_rle = None;
+            table.build_from_probabilities(field.default_acc_log, field.default_distribution)?;
+            *rle = None;
+            Ok(0)
         }
         ModeType::Repeat => {
-            /* Nothing to do */
-        }
-    };
-
-    let ml_source = &source[bytes_read..];
-
-    match modes.ml_mode() {
-        ModeType::FSECompressed => {
-            let bytes = scratch.match_lengths.build_decoder(ml_source, ML_MAX_LOG)?;
-            bytes_read += bytes;
-            scratch.ml_rle = None;
-        }
-        ModeType::RLE => {
-            if ml_source.is_empty() {
-                return Err("Need a byte to read for RLE ml table".to_owned());
+            // the RLE symbol or the table of a previous block is used again
+            if rle.is_none() && table.accuracy_log() == 0 {
+                return Err(DecodeSequenceError::RepeatModeWithoutTable);
             }
-            bytes_read += 1;
-            scratch.ml_rle = Some(ml_source[0]);
+            Ok(0)
         }
-        ModeType::Predefined => {
-            scratch.match_lengths.build_from_probabilities(
-                ML_DEFAULT_ACC_LOG,
-                &Vec::from(&MATCH_LENGTH_DEFAULT_DISTRIBUTION[..]),
-            )?;
-            scratch.ml_rle = None;
-        }
-        ModeType::Repeat => {
-            /* Nothing to do */
-        }
-    };
-
-    Ok(bytes_read)
+    }
 }
 
-const LL_DEFAULT_ACC_LOG: u8 = 6;
-const LITERALS_LENGTH_DEFAULT_DISTRIBUTION: [i32; 36] = [
-    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1,
-    -1, -1, -1, -1,
-];
-
-const ML_DEFAULT_ACC_LOG: u8 = 6;
-const MATCH_LENGTH_DEFAULT_DISTRIBUTION: [i32; 53] = [
-    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
-];
-
-const OF_DEFAULT_ACC_LOG: u8 = 5;
-const OFFSET_DEFAULT_DISTRIBUTION: [i32; 29] = [
-    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
-];
-
 #[test]
 fn test_ll_default() {
     let mut table = crate::fse::FSETable::new();
@@ -0,0 +1,276 @@
+use crate::blocks::sequence_section::{LL_MAX_LOG, ML_MAX_LOG, OF_MAX_LOG};
+use crate::decoding::scratch::DecoderScratch;
+use crate::decoding::xxhash64::{Xxh64, STRIPE_SIZE};
+use crate::errors::SnapshotError;
+use crate::fse::{FSETable, MAX_SYMBOLS};
+use crate::huff0::MAX_WEIGHTS;
+use core::convert::TryInto;
+use core::hash::Hasher;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Every snapshot starts with these bytes, followed by the version
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"RZSN";
+/// Snapshots of other versions are rejected by restore()
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Builds a snapshot. All numbers are little endian, the snapshot ends with the XXH64 of everything before it
+pub struct SnapshotWriter {
+    out: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    pub fn new() -> SnapshotWriter {
+        let mut out = Vec::new();
+        out.extend_from_slice(&SNAPSHOT_MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        SnapshotWriter { out }
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.out.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.out.push(val as u8);
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.out.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64) {
+        self.out.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn option_u32(&mut self, val: Option<u32>) {
+        self.bool(val.is_some());
+        self.u32(val.unwrap_or(0));
+    }
+
+    /// Writes the length before the bytes
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.out.extend_from_slice(bytes);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let mut hash = Xxh64::new();
+        hash.write(&self.out);
+        let hash = hash.finish();
+        self.u64(hash);
+        self.out
+    }
+}
+
+impl Default for SnapshotWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads what SnapshotWriter wrote, after checking the magic, version and hash of the snapshot
+pub struct SnapshotReader<'s> {
+    source: &'s [u8],
+}
+
+impl<'s> SnapshotReader<'s> {
+    pub fn new(snapshot: &'s [u8]) -> Result<SnapshotReader<'s>, SnapshotError> {
+        if snapshot.len() < 16 || snapshot[..4] != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = u32::from_le_bytes(snapshot[4..8].try_into().expect("4 bytes"));
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion {
+                got: version,
+                supported: SNAPSHOT_VERSION,
+            });
+        }
+
+        let (content, hash) = snapshot.split_at(snapshot.len() - 8);
+        let mut calculated = Xxh64::new();
+        calculated.write(content);
+        if calculated.finish() != u64::from_le_bytes(hash.try_into().expect("8 bytes")) {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+        Ok(SnapshotReader {
+            source: &content[8..],
+        })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'s [u8], SnapshotError> {
+        if self.source.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (taken, rest) = self.source.split_at(len);
+        self.source = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::InvalidField { field: "flag" }),
+        }
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("8 bytes"),
+        ))
+    }
+
+    pub fn option_u32(&mut self) -> Result<Option<u32>, SnapshotError> {
+        let is_some = self.bool()?;
+        let val = self.u32()?;
+        Ok(if is_some { Some(val) } else { None })
+    }
+
+    pub fn bytes(&mut self) -> Result<&'s [u8], SnapshotError> {
+        let len = self.u64()?;
+        if len > self.source.len() as u64 {
+            return Err(SnapshotError::Truncated);
+        }
+        self.take(len as usize)
+    }
+
+    /// Checks that everything has been read
+    pub fn finish(self) -> Result<(), SnapshotError> {
+        if self.source.is_empty() {
+            Ok(())
+        } else {
+            Err(SnapshotError::InvalidField {
+                field: "trailing bytes",
+            })
+        }
+    }
+}
+
+/// Writes the tables, repeat offsets and the content of the decodebuffer that is still needed or not collected yet.
+/// The dictionary is not part of it, only which dictionary is used is saved with the frame state
+pub fn write_scratch(w: &mut SnapshotWriter, scratch: &DecoderScratch) {
+    for offset in &scratch.offset_hist {
+        w.u32(*offset);
+    }
+
+    let mut weights = [0; MAX_WEIGHTS + 1];
+    let num_weights = scratch.huf.table.weights(&mut weights);
+    w.bytes(&weights[..num_weights]);
+
+    write_fse_table(w, &scratch.fse.literal_lengths, scratch.fse.ll_rle);
+    write_fse_table(w, &scratch.fse.offsets, scratch.fse.of_rle);
+    write_fse_table(w, &scratch.fse.match_lengths, scratch.fse.ml_rle);
+
+    w.bytes(&scratch.buffer.buffer);
+    w.u64(scratch.buffer.handed_out() as u64);
+    w.u64(scratch.buffer.total_output());
+    let hash = &scratch.buffer.hash;
+    for acc in &hash.accumulators {
+        w.u64(*acc);
+    }
+    w.u64(hash.total_len);
+    w.bytes(&hash.buffer[..hash.buffered_len]);
+}
+
+/// Restores what write_scratch() wrote. The scratch needs to have been reset for the frame of the snapshot before
+pub fn read_scratch(
+    r: &mut SnapshotReader<'_>,
+    scratch: &mut DecoderScratch,
+) -> Result<(), SnapshotError> {
+    for offset in scratch.offset_hist.iter_mut() {
+        *offset = r.u32()?;
+    }
+
+    let weights = r.bytes()?;
+    if weights.len() > MAX_WEIGHTS {
+        return Err(SnapshotError::InvalidField {
+            field: "huffman weights",
+        });
+    }
+    if weights.is_empty() {
+        scratch.huf.table.reset();
+    } else {
+        scratch
+            .huf
+            .table
+            .build_from_weights(weights)
+            .map_err(SnapshotError::InvalidHuffmanTable)?;
+    }
+
+    scratch.fse.ll_rle = read_fse_table(r, &mut scratch.fse.literal_lengths, LL_MAX_LOG)?;
+    scratch.fse.of_rle = read_fse_table(r, &mut scratch.fse.offsets, OF_MAX_LOG)?;
+    scratch.fse.ml_rle = read_fse_table(r, &mut scratch.fse.match_lengths, ML_MAX_LOG)?;
+
+    let content = r.bytes()?;
+    let handed_out = r.u64()?;
+    if handed_out > content.len() as u64 {
+        return Err(SnapshotError::InvalidField {
+            field: "drained bytes",
+        });
+    }
+    let total_output = r.u64()?;
+    let mut hash = Xxh64::new();
+    for acc in hash.accumulators.iter_mut() {
+        *acc = r.u64()?;
+    }
+    hash.total_len = r.u64()?;
+    let buffered = r.bytes()?;
+    if buffered.len() >= STRIPE_SIZE {
+        return Err(SnapshotError::InvalidField {
+            field: "checksum state",
+        });
+    }
+    hash.buffer[..buffered.len()].copy_from_slice(buffered);
+    hash.buffered_len = buffered.len();
+    scratch
+        .buffer
+        .restore(content, handed_out as usize, total_output, hash);
+    Ok(())
+}
+
+fn write_fse_table(w: &mut SnapshotWriter, table: &FSETable, rle: Option<u8>) {
+    w.bool(rle.is_some());
+    w.u8(rle.unwrap_or(0));
+    w.u8(table.accuracy_log);
+    w.u32(table.symbol_probablilities.len() as u32);
+    for prob in &table.symbol_probablilities {
+        w.u32(*prob as u32);
+    }
+}
+
+fn read_fse_table(
+    r: &mut SnapshotReader<'_>,
+    table: &mut FSETable,
+    max_log: u8,
+) -> Result<Option<u8>, SnapshotError> {
+    let is_rle = r.bool()?;
+    let rle = r.u8()?;
+    let acc_log = r.u8()?;
+
+    let num_probs = r.u32()? as usize;
+    let mut probs = [0i32; MAX_SYMBOLS];
+    if num_probs > probs.len() {
+        return Err(SnapshotError::InvalidField {
+            field: "FSE probabilities",
+        });
+    }
+    for prob in probs[..num_probs].iter_mut() {
+        *prob = r.u32()? as i32;
+    }
+    table
+        .restore(acc_log, max_log, &probs[..num_probs])
+        .map_err(SnapshotError::InvalidFSETable)?;
+
+    Ok(if is_rle { Some(rle) } else { None })
+}
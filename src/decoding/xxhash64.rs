@@ -0,0 +1,143 @@
+use core::convert::TryInto;
+use core::hash::Hasher;
+
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+/// How many bytes are hashed at once
+pub const STRIPE_SIZE: usize = 32;
+
+/// XXH64 with seed 0, which zstd uses for the content checksum. Produces the same hashes as twox_hash::XxHash64,
+/// but its state is accessible so it can be saved in a snapshot of the decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xxh64 {
+    pub accumulators: [u64; 4],
+    pub total_len: u64,
+    /// The first buffered_len bytes have not been hashed yet because they do not fill a stripe
+    pub buffer: [u8; STRIPE_SIZE],
+    pub buffered_len: usize,
+}
+
+impl Default for Xxh64 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}
+
+fn merge_round(acc: u64, val: u64) -> u64 {
+    (acc ^ round(0, val))
+        .wrapping_mul(PRIME64_1)
+        .wrapping_add(PRIME64_4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"))
+}
+
+impl Xxh64 {
+    pub fn new() -> Xxh64 {
+        Xxh64 {
+            accumulators: [
+                PRIME64_1.wrapping_add(PRIME64_2),
+                PRIME64_2,
+                0,
+                0u64.wrapping_sub(PRIME64_1),
+            ],
+            total_len: 0,
+            buffer: [0; STRIPE_SIZE],
+            buffered_len: 0,
+        }
+    }
+
+    fn process_stripe(accumulators: &mut [u64; 4], stripe: &[u8]) {
+        for (idx, acc) in accumulators.iter_mut().enumerate() {
+            *acc = round(*acc, read_u64(&stripe[idx * 8..]));
+        }
+    }
+}
+
+impl Hasher for Xxh64 {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.total_len += bytes.len() as u64;
+
+        if self.buffered_len > 0 {
+            let take = core::cmp::min(STRIPE_SIZE - self.buffered_len, bytes.len());
+            self.buffer[self.buffered_len..self.buffered_len + take]
+                .copy_from_slice(&bytes[..take]);
+            self.buffered_len += take;
+            bytes = &bytes[take..];
+            if self.buffered_len < STRIPE_SIZE {
+                return;
+            }
+            let stripe = self.buffer;
+            Self::process_stripe(&mut self.accumulators, &stripe);
+            self.buffered_len = 0;
+        }
+
+        let mut stripes = bytes.chunks_exact(STRIPE_SIZE);
+        for stripe in &mut stripes {
+            Self::process_stripe(&mut self.accumulators, stripe);
+        }
+        let rest = stripes.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered_len = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let [v1, v2, v3, v4] = self.accumulators;
+        let mut hash = if self.total_len >= STRIPE_SIZE as u64 {
+            let mut hash = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            for v in &self.accumulators {
+                hash = merge_round(hash, *v);
+            }
+            hash
+        } else {
+            // nothing has been hashed yet, v3 still holds the seed
+            v3.wrapping_add(PRIME64_5)
+        };
+        hash = hash.wrapping_add(self.total_len);
+
+        let mut rest = &self.buffer[..self.buffered_len];
+        while rest.len() >= 8 {
+            hash ^= round(0, read_u64(rest));
+            hash = hash
+                .rotate_left(27)
+                .wrapping_mul(PRIME64_1)
+                .wrapping_add(PRIME64_4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            let val = u32::from_le_bytes(rest[..4].try_into().expect("4 bytes"));
+            hash ^= u64::from(val).wrapping_mul(PRIME64_1);
+            hash = hash
+                .rotate_left(23)
+                .wrapping_mul(PRIME64_2)
+                .wrapping_add(PRIME64_3);
+            rest = &rest[4..];
+        }
+        for byte in rest {
+            hash ^= u64::from(*byte).wrapping_mul(PRIME64_5);
+            hash = hash.rotate_left(11).wrapping_mul(PRIME64_1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME64_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME64_3);
+        hash ^= hash >> 32;
+        hash
+    }
+}
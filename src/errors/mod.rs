@@ -1,19 +1,34 @@
 #[cfg(feature = "alloc")]
-use alloc::string::String;
+use crate::blocks::block::BlockType;
+#[cfg(feature = "alloc")]
+use crate::decoding::limits::OutputLimit;
+#[cfg(feature = "alloc")]
+use crate::decoding::match_copy::CopyStrategy;
+#[cfg(feature = "alloc")]
+use crate::io::Error;
 
 use core::fmt;
+
+#[cfg(feature = "alloc")]
 #[derive(Debug)]
 pub enum FrameDecoderError {
-    FailedToReadBlockHeader(String),
-    FailedToReadBlockBody(String),
+    FailedToReadBlockHeader(BlockHeaderReadError),
+    FailedToReadBlockBody(DecodeBlockContentError),
     FailedToReadChecksum,
     NotYetInitialized,
-    FailedToInitialize(String),
+    FailedToInitialize(FrameHeaderError),
+    WindowSizeTooBig { requested: u64, max: u64 },
+    FailedToLoadDict(DictionaryDecodeError),
     FailedToDrainDecodebuffer,
     TargetTooSmall,
     DictNotProvided,
+    CorruptBlockNotSkipped,
+    CanNotSkipBlock,
+    Cancelled,
+    OutputLimitExceeded(OutputLimit),
 }
 
+#[cfg(feature = "alloc")]
 impl fmt::Display for FrameDecoderError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -30,6 +45,12 @@ impl fmt::Display for FrameDecoderError {
             FrameDecoderError::FailedToInitialize(m) => {
                 write!(f, "Decoder encountered error while initializing: {}", m)
             }
+            FrameDecoderError::WindowSizeTooBig { requested, max } => write!(
+                f,
+                "Dont support window_sizes (requested: {}) over: {}",
+                requested, max
+            ),
+            FrameDecoderError::FailedToLoadDict(m) => write!(f, "Failed to load dict: {}", m),
             FrameDecoderError::FailedToDrainDecodebuffer => write!(
                 f,
                 "Decoder encountered error while draining the decodebuffer"
@@ -42,6 +63,20 @@ impl fmt::Display for FrameDecoderError {
                 f,
                 "Frame header specified dictionary id that wasnt provided by add_dict() or reset_with_dict()"
             ),
+            FrameDecoderError::CorruptBlockNotSkipped => write!(
+                f,
+                "A block of this frame is corrupt. Skip it with skip_corrupt_block() or reset the decoder"
+            ),
+            FrameDecoderError::CanNotSkipBlock => write!(
+                f,
+                "There is no corrupt block or it can not be skipped because its end is unknown"
+            ),
+            FrameDecoderError::Cancelled => {
+                write!(f, "Decoding was cancelled by the cancellation token")
+            }
+            FrameDecoderError::OutputLimitExceeded(limit) => {
+                write!(f, "Decoding the next block would exceed the limit {:?}", limit)
+            }
         }
     }
 }
@@ -50,7 +85,1172 @@ impl fmt::Display for FrameDecoderError {
 #[cfg(feature = "std")]
 impl std::error::Error for FrameDecoderError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        // Generic error, underlying cause isn't tracked.
-        None
+        match self {
+            FrameDecoderError::FailedToReadBlockHeader(e) => Some(e),
+            FrameDecoderError::FailedToReadBlockBody(e) => Some(e),
+            FrameDecoderError::FailedToInitialize(e) => Some(e),
+            FrameDecoderError::FailedToLoadDict(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<FrameHeaderError> for FrameDecoderError {
+    fn from(err: FrameHeaderError) -> Self {
+        FrameDecoderError::FailedToInitialize(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub enum BlockHeaderReadError {
+    ReadError(Error),
+    FoundReservedBlock,
+    BlockTooBig { got: u32, max: u32 },
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for BlockHeaderReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockHeaderReadError::ReadError(e) => {
+                write!(f, "Error while reading the block header: {}", e)
+            }
+            BlockHeaderReadError::FoundReservedBlock => write!(
+                f,
+                "Reserved block occured. This is considered corruption by the documentation"
+            ),
+            BlockHeaderReadError::BlockTooBig { got, max } => write!(
+                f,
+                "Blocksize was bigger than the absolute maximum {} bytes. Is: {}",
+                max, got
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BlockHeaderReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BlockHeaderReadError::ReadError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub enum DecodeBlockContentError {
+    DecoderStateIsFailed,
+    ExpectedHeaderOfPreviousBlock,
+    FoundReservedBlock,
+    ReadError { step: BlockType, source: Error },
+    DecodebufferError(DecodebufferError),
+    DecompressBlockError(DecompressBlockError),
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for DecodeBlockContentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeBlockContentError::DecoderStateIsFailed => write!(
+                f,
+                "Cant decode next block if failed along the way. Results will be nonsense"
+            ),
+            DecodeBlockContentError::ExpectedHeaderOfPreviousBlock => write!(
+                f,
+                "Cant decode next block body, while expecting to decode the header of the previous block. Results will be nonsense"
+            ),
+            DecodeBlockContentError::FoundReservedBlock => write!(
+                f,
+                "How did you even get this. The decoder should error out if it detects a reserved-type block"
+            ),
+            DecodeBlockContentError::ReadError { step, source } => write!(
+                f,
+                "Error while reading the content of the {} block: {}",
+                step, source
+            ),
+            DecodeBlockContentError::DecodebufferError(e) => write!(f, "{}", e),
+            DecodeBlockContentError::DecompressBlockError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeBlockContentError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeBlockContentError::ReadError { source, .. } => Some(source),
+            DecodeBlockContentError::DecodebufferError(e) => Some(e),
+            DecodeBlockContentError::DecompressBlockError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<DecodebufferError> for DecodeBlockContentError {
+    fn from(err: DecodebufferError) -> Self {
+        DecodeBlockContentError::DecodebufferError(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<DecompressBlockError> for DecodeBlockContentError {
+    fn from(err: DecompressBlockError) -> Self {
+        DecodeBlockContentError::DecompressBlockError(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompressBlockError {
+    MalformedSectionHeader {
+        expected_len: usize,
+        remaining_bytes: usize,
+    },
+    FailedToReadLiteralsSection(LiteralsSectionParseError),
+    FailedToDecodeLiterals(DecompressLiteralsError),
+    FailedToReadSequencesHeader(SequencesHeaderParseError),
+    FailedToDecodeSequences(DecodeSequenceError),
+    FailedToExecuteSequences(ExecuteSequencesError),
+    DecodebufferError(DecodebufferError),
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for DecompressBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressBlockError::MalformedSectionHeader {
+                expected_len,
+                remaining_bytes,
+            } => write!(
+                f,
+                "Malformed section header. Says literals would be this long: {} but there are only {} bytes left",
+                expected_len, remaining_bytes
+            ),
+            DecompressBlockError::FailedToReadLiteralsSection(e) => {
+                write!(f, "Failed to parse literals section header: {}", e)
+            }
+            DecompressBlockError::FailedToDecodeLiterals(e) => {
+                write!(f, "Failed to decode literals: {}", e)
+            }
+            DecompressBlockError::FailedToReadSequencesHeader(e) => {
+                write!(f, "Failed to parse sequences header: {}", e)
+            }
+            DecompressBlockError::FailedToDecodeSequences(e) => {
+                write!(f, "Failed to decode sequences: {}", e)
+            }
+            DecompressBlockError::FailedToExecuteSequences(e) => {
+                write!(f, "Failed to execute sequences: {}", e)
+            }
+            DecompressBlockError::DecodebufferError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecompressBlockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecompressBlockError::FailedToReadLiteralsSection(e) => Some(e),
+            DecompressBlockError::FailedToDecodeLiterals(e) => Some(e),
+            DecompressBlockError::FailedToReadSequencesHeader(e) => Some(e),
+            DecompressBlockError::FailedToDecodeSequences(e) => Some(e),
+            DecompressBlockError::FailedToExecuteSequences(e) => Some(e),
+            DecompressBlockError::DecodebufferError(e) => Some(e),
+            DecompressBlockError::MalformedSectionHeader { .. } => None,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<LiteralsSectionParseError> for DecompressBlockError {
+    fn from(err: LiteralsSectionParseError) -> Self {
+        DecompressBlockError::FailedToReadLiteralsSection(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<DecompressLiteralsError> for DecompressBlockError {
+    fn from(err: DecompressLiteralsError) -> Self {
+        DecompressBlockError::FailedToDecodeLiterals(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<SequencesHeaderParseError> for DecompressBlockError {
+    fn from(err: SequencesHeaderParseError) -> Self {
+        DecompressBlockError::FailedToReadSequencesHeader(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<DecodeSequenceError> for DecompressBlockError {
+    fn from(err: DecodeSequenceError) -> Self {
+        DecompressBlockError::FailedToDecodeSequences(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<ExecuteSequencesError> for DecompressBlockError {
+    fn from(err: ExecuteSequencesError) -> Self {
+        DecompressBlockError::FailedToExecuteSequences(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<DecodebufferError> for DecompressBlockError {
+    fn from(err: DecodebufferError) -> Self {
+        DecompressBlockError::DecodebufferError(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecuteSequencesError {
+    DecodebufferError(DecodebufferError),
+    NotEnoughLiterals { need: usize, have: usize },
+    ZeroOffset,
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for ExecuteSequencesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecuteSequencesError::DecodebufferError(e) => write!(f, "{}", e),
+            ExecuteSequencesError::NotEnoughLiterals { need, have } => write!(
+                f,
+                "Sequence wants to copy up to byte {}. Bytes in literalsbuffer: {}",
+                need, have
+            ),
+            ExecuteSequencesError::ZeroOffset => write!(f, "Illegal offset: 0 found"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ExecuteSequencesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExecuteSequencesError::DecodebufferError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<DecodebufferError> for ExecuteSequencesError {
+    fn from(err: DecodebufferError) -> Self {
+        ExecuteSequencesError::DecodebufferError(err)
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodebufferError {
+    ZeroOffset,
+    NotEnoughBytesInDictionary { got: usize, need: usize },
+    OffsetTooBig { offset: usize, buf_len: usize },
+    OutputLimitExceeded { amount: usize, limit: u64 },
+    UnsupportedCopyStrategy(CopyStrategy),
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for DecodebufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodebufferError::ZeroOffset => write!(f, "Illegal offset: 0 found"),
+            DecodebufferError::NotEnoughBytesInDictionary { got, need } => write!(
+                f,
+                "Need {} bytes from the dictionary but it is only {} bytes long",
+                need, got
+            ),
+            DecodebufferError::OffsetTooBig { offset, buf_len } => {
+                write!(f, "offset: {} bigger than buffer: {}", offset, buf_len)
+            }
+            DecodebufferError::OutputLimitExceeded { amount, limit } => write!(
+                f,
+                "Decoding {} more bytes would exceed the output limit of {} bytes",
+                amount, limit
+            ),
+            DecodebufferError::UnsupportedCopyStrategy(strategy) => write!(
+                f,
+                "Copy strategy {:?} is not supported by this CPU",
+                strategy
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodebufferError {}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub enum FrameSizeError {
+    FailedToReadFrameHeader(FrameHeaderError),
+    FailedToReadBlockHeader(BlockHeaderReadError),
+    NotEnoughBytes { have: usize, need: usize },
+    ContentSizeOverflow,
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for FrameSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameSizeError::FailedToReadFrameHeader(m) => {
+                write!(f, "Failed to parse frame header: {}", m)
+            }
+            FrameSizeError::FailedToReadBlockHeader(m) => {
+                write!(f, "Failed to parse block header: {}", m)
+            }
+            FrameSizeError::NotEnoughBytes { have, need } => write!(
+                f,
+                "Source is truncated. Have: {} bytes, need at least: {} bytes",
+                have, need
+            ),
+            FrameSizeError::ContentSizeOverflow => {
+                write!(f, "Sum of the frame content sizes does not fit into a u64")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FrameSizeError {}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub enum DictionaryDecodeError {
+    DictionaryTooSmall {
+        have: usize,
+        need: usize,
+    },
+    BadMagicNum {
+        got: [u8; 4],
+    },
+    FailedToReadHuffmanTable(HuffmanTableError),
+    FailedToReadOffsetTable(FSETableError),
+    FailedToReadMatchLengthTable(FSETableError),
+    FailedToReadLiteralLengthTable(FSETableError),
+    ZeroRepeatOffset {
+        index: usize,
+    },
+    RepeatOffsetTooBig {
+        index: usize,
+        offset: u32,
+        content_size: usize,
+    },
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for DictionaryDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DictionaryDecodeError::DictionaryTooSmall { have, need } => write!(
+                f,
+                "Dictionary is too small. Have: {} bytes, need at least: {} bytes",
+                have, need
+            ),
+            DictionaryDecodeError::BadMagicNum { got } => write!(
+                f,
+                "Bad magic_num at start of the dictionary. Is: {:?}, Should be: {:?}",
+                got,
+                [0x37, 0xA4, 0x30, 0xEC]
+            ),
+            DictionaryDecodeError::FailedToReadHuffmanTable(m) => {
+                write!(
+                    f,
+                    "Failed to read the huffman table of the dictionary: {}",
+                    m
+                )
+            }
+            DictionaryDecodeError::FailedToReadOffsetTable(m) => {
+                write!(
+                    f,
+                    "Failed to read the offset table of the dictionary: {}",
+                    m
+                )
+            }
+            DictionaryDecodeError::FailedToReadMatchLengthTable(m) => write!(
+                f,
+                "Failed to read the match length table of the dictionary: {}",
+                m
+            ),
+            DictionaryDecodeError::FailedToReadLiteralLengthTable(m) => write!(
+                f,
+                "Failed to read the literal length table of the dictionary: {}",
+                m
+            ),
+            DictionaryDecodeError::ZeroRepeatOffset { index } => {
+                write!(f, "Repeat offset {} of the dictionary is zero", index)
+            }
+            DictionaryDecodeError::RepeatOffsetTooBig {
+                index,
+                offset,
+                content_size,
+            } => write!(
+                f,
+                "Repeat offset {} of the dictionary is: {} but the content is only {} bytes long",
+                index, offset, content_size
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DictionaryDecodeError {}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub enum SnapshotError {
+    NotYetInitialized,
+    CorruptBlock,
+    NotASnapshot,
+    UnsupportedVersion { got: u32, supported: u32 },
+    ChecksumMismatch,
+    Truncated,
+    InvalidField { field: &'static str },
+    FailedToRestoreFrameHeader(FrameDecoderError),
+    InvalidHuffmanTable(HuffmanTableError),
+    InvalidFSETable(FSETableError),
+    DictNotProvided { dict_id: u32 },
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::NotYetInitialized => {
+                write!(f, "Decoder must be initialized before taking a snapshot")
+            }
+            SnapshotError::CorruptBlock => write!(
+                f,
+                "Can not take a snapshot while a corrupt block has not been skipped"
+            ),
+            SnapshotError::NotASnapshot => write!(f, "Data is not a snapshot of a FrameDecoder"),
+            SnapshotError::UnsupportedVersion { got, supported } => write!(
+                f,
+                "Snapshot has version: {} but only version: {} is supported",
+                got, supported
+            ),
+            SnapshotError::ChecksumMismatch => {
+                write!(
+                    f,
+                    "Checksum of the snapshot does not match, it is corrupted"
+                )
+            }
+            SnapshotError::Truncated => write!(f, "Snapshot is truncated"),
+            SnapshotError::InvalidField { field } => {
+                write!(f, "Snapshot contains an invalid {}", field)
+            }
+            SnapshotError::FailedToRestoreFrameHeader(m) => {
+                write!(f, "Failed to restore the frame header: {}", m)
+            }
+            SnapshotError::InvalidHuffmanTable(e) => {
+                write!(f, "Failed to restore the huffman table: {}", e)
+            }
+            SnapshotError::InvalidFSETable(e) => {
+                write!(f, "Failed to restore a FSE table: {}", e)
+            }
+            SnapshotError::DictNotProvided { dict_id } => write!(
+                f,
+                "Snapshot uses dictionary {} which is not registered in the decoder",
+                dict_id
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SnapshotError {}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum ParallelDecodeError {
+    FailedToReadSource(std::io::Error),
+    FailedToFindFrame {
+        frame: usize,
+        err: FrameSizeError,
+    },
+    FailedToDecodeFrame {
+        frame: usize,
+        err: FrameDecoderError,
+    },
+    ChecksumMismatch {
+        frame: usize,
+        from_data: u32,
+        calculated: u32,
+    },
+    WorkerPanicked {
+        frame: usize,
+    },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for ParallelDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParallelDecodeError::FailedToReadSource(e) => {
+                write!(f, "Failed to read from the source: {}", e)
+            }
+            ParallelDecodeError::FailedToFindFrame { frame, err } => {
+                write!(f, "Failed to find the end of frame {}: {}", frame, err)
+            }
+            ParallelDecodeError::FailedToDecodeFrame { frame, err } => {
+                write!(f, "Failed to decode frame {}: {}", frame, err)
+            }
+            ParallelDecodeError::ChecksumMismatch {
+                frame,
+                from_data,
+                calculated,
+            } => write!(
+                f,
+                "Checksum of frame {} did not match. From data: {}, calculated while decoding: {}",
+                frame, from_data, calculated
+            ),
+            ParallelDecodeError::WorkerPanicked { frame } => {
+                write!(f, "Worker panicked while decoding frame {}", frame)
+            }
+        }
     }
 }
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParallelDecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParallelDecodeError::FailedToReadSource(e) => Some(e),
+            ParallelDecodeError::FailedToFindFrame { err, .. } => Some(err),
+            ParallelDecodeError::FailedToDecodeFrame { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// The errors below do not need an allocator, so they can be used by the workspace decoder without the alloc feature.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GetBitsError {
+    TooManyBits { num_requested_bits: usize, limit: u8 },
+    NotEnoughRemainingBits { requested: usize, remaining: usize },
+}
+
+impl fmt::Display for GetBitsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GetBitsError::TooManyBits {
+                num_requested_bits,
+                limit,
+            } => write!(
+                f,
+                "Cant serve this request. The reader is limited to {} bits, requested {} bits",
+                limit, num_requested_bits
+            ),
+            GetBitsError::NotEnoughRemainingBits {
+                requested,
+                remaining,
+            } => write!(
+                f,
+                "Cant read n: {} bits. Bits left: {}",
+                requested, remaining
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for GetBitsError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameHeaderError {
+    MagicNumberReadError,
+    FrameDescriptorReadError,
+    WindowDescriptorReadError,
+    DictionaryIdReadError,
+    FrameContentSizeReadError,
+    BadMagicNumber(u32),
+    ReservedFlagSet,
+    WindowTooBig { got: u64 },
+    WindowTooSmall { got: u64 },
+    FrameContentSizeNotPresent,
+}
+
+impl fmt::Display for FrameHeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameHeaderError::MagicNumberReadError => write!(f, "Error while reading magic number"),
+            FrameHeaderError::FrameDescriptorReadError => {
+                write!(f, "Error while reading frame descriptor")
+            }
+            FrameHeaderError::WindowDescriptorReadError => {
+                write!(f, "Error while reading window descriptor")
+            }
+            FrameHeaderError::DictionaryIdReadError => {
+                write!(f, "Error while reading dictionary id")
+            }
+            FrameHeaderError::FrameContentSizeReadError => {
+                write!(f, "Error while reading frame content size")
+            }
+            FrameHeaderError::BadMagicNumber(got) => write!(
+                f,
+                "magic_num wrong. Is: {}. Should be: {}",
+                got,
+                crate::frame::MAGIC_NUM
+            ),
+            FrameHeaderError::ReservedFlagSet => write!(f, "Reserved Flag set. Must be zero"),
+            FrameHeaderError::WindowTooBig { got } => write!(
+                f,
+                "window_size bigger than allowed maximum. Is: {}, Should be lower than: {}",
+                got,
+                crate::frame::MAX_WINDOW_SIZE
+            ),
+            FrameHeaderError::WindowTooSmall { got } => write!(
+                f,
+                "window_size smaller than allowed minimum. Is: {}, Should be greater than: {}",
+                got,
+                crate::frame::MIN_WINDOW_SIZE
+            ),
+            FrameHeaderError::FrameContentSizeNotPresent => {
+                write!(f, "The frame header does not contain a frame content size")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FrameHeaderError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FSETableError {
+    AccLogIsZero,
+    AccLogTooBig { got: u8, max: u8 },
+    GetBitsError(GetBitsError),
+    ProbabilityCounterMismatch { got: u32, expected_sum: u32 },
+    TooManySymbols { got: usize },
+}
+
+impl fmt::Display for FSETableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FSETableError::AccLogIsZero => write!(f, "Acclog must be at least 1"),
+            FSETableError::AccLogTooBig { got, max } => write!(
+                f,
+                "Found FSE acc_log: {} bigger than allowed maximum in this case: {}",
+                got, max
+            ),
+            FSETableError::GetBitsError(e) => write!(f, "{}", e),
+            FSETableError::ProbabilityCounterMismatch { got, expected_sum } => write!(
+                f,
+                "The counter: {} exceeded the expected sum: {}. This means an error or corrupted data",
+                got, expected_sum
+            ),
+            FSETableError::TooManySymbols { got } => write!(
+                f,
+                "There are too many symbols in this distribution: {}. Max: 256",
+                got
+            ),
+        }
+    }
+}
+
+impl From<GetBitsError> for FSETableError {
+    fn from(err: GetBitsError) -> Self {
+        FSETableError::GetBitsError(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FSETableError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FSEDecoderError {
+    GetBitsError(GetBitsError),
+    TableIsUninitialized,
+}
+
+impl fmt::Display for FSEDecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FSEDecoderError::GetBitsError(e) => write!(f, "{}", e),
+            FSEDecoderError::TableIsUninitialized => {
+                write!(f, "Tried to use an unitizialized table!")
+            }
+        }
+    }
+}
+
+impl From<GetBitsError> for FSEDecoderError {
+    fn from(err: GetBitsError) -> Self {
+        FSEDecoderError::GetBitsError(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FSEDecoderError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HuffmanTableError {
+    GetBitsError(GetBitsError),
+    FSEDecoderError(FSEDecoderError),
+    FSETableError(FSETableError),
+    SourceIsEmpty,
+    NotEnoughBytesForWeights { got_bytes: usize, expected_bytes: u8 },
+    FSETableUsedTooManyBytes { used: usize, available_bytes: u8 },
+    NotEnoughBytesToDecompressWeights { have: usize, need: usize },
+    ExtraPadding { skipped_bits: i32 },
+    TooManyWeights { got: usize },
+    MissingWeights,
+    LeftoverIsNotAPowerOf2 { got: u32 },
+    NotEnoughBytesInSource { got: usize, need: usize },
+    WeightBiggerThanMaxNumBits { got: u8 },
+    MaxBitsTooHigh { got: u8 },
+}
+
+impl fmt::Display for HuffmanTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HuffmanTableError::GetBitsError(e) => write!(f, "{}", e),
+            HuffmanTableError::FSEDecoderError(e) => write!(f, "{}", e),
+            HuffmanTableError::FSETableError(e) => write!(f, "{}", e),
+            HuffmanTableError::SourceIsEmpty => write!(f, "Source needs to have at least one byte"),
+            HuffmanTableError::NotEnoughBytesForWeights {
+                got_bytes,
+                expected_bytes,
+            } => write!(
+                f,
+                "Header says there should be {} bytes for the weights but there are only {} bytes in the stream",
+                expected_bytes, got_bytes
+            ),
+            HuffmanTableError::FSETableUsedTooManyBytes {
+                used,
+                available_bytes,
+            } => write!(
+                f,
+                "FSE table used more bytes: {} than were meant to be used for the whole stream of huffman weights ({})",
+                used, available_bytes
+            ),
+            HuffmanTableError::NotEnoughBytesToDecompressWeights { have, need } => write!(
+                f,
+                "Not enough bytes in stream to decompress weights. Is: {}, Should be: {}",
+                have, need
+            ),
+            HuffmanTableError::ExtraPadding { skipped_bits } => write!(
+                f,
+                "Padding at the end of the sequence_section was more than a byte long: {}. Probably cause by data corruption",
+                skipped_bits
+            ),
+            HuffmanTableError::TooManyWeights { got } => write!(
+                f,
+                "More than 255 weights decoded (got {} weights). Stream is probably corrupted",
+                got
+            ),
+            HuffmanTableError::MissingWeights => {
+                write!(f, "Cant build huffman table without any weights")
+            }
+            HuffmanTableError::LeftoverIsNotAPowerOf2 { got } => {
+                write!(f, "Leftover must be power of two but is: {}", got)
+            }
+            HuffmanTableError::NotEnoughBytesInSource { got, need } => write!(
+                f,
+                "Source needs to have at least {} bytes, got: {}",
+                need, got
+            ),
+            HuffmanTableError::WeightBiggerThanMaxNumBits { got } => write!(
+                f,
+                "Cant have weight: {} bigger than max_num_bits: {}",
+                got,
+                crate::huff0::MAX_MAX_NUM_BITS
+            ),
+            HuffmanTableError::MaxBitsTooHigh { got } => write!(
+                f,
+                "max_bits derived from weights is: {} should be lower than: {}",
+                got,
+                crate::huff0::MAX_MAX_NUM_BITS
+            ),
+        }
+    }
+}
+
+impl From<GetBitsError> for HuffmanTableError {
+    fn from(err: GetBitsError) -> Self {
+        HuffmanTableError::GetBitsError(err)
+    }
+}
+
+impl From<FSEDecoderError> for HuffmanTableError {
+    fn from(err: FSEDecoderError) -> Self {
+        HuffmanTableError::FSEDecoderError(err)
+    }
+}
+
+impl From<FSETableError> for HuffmanTableError {
+    fn from(err: FSETableError) -> Self {
+        HuffmanTableError::FSETableError(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HuffmanTableError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralsSectionParseError {
+    IllegalLiteralSectionType { got: u8 },
+    GetBitsError(GetBitsError),
+    NotEnoughBytes { have: usize, need: u8 },
+}
+
+impl fmt::Display for LiteralsSectionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiteralsSectionParseError::IllegalLiteralSectionType { got } => write!(
+                f,
+                "Illegal literalssectiontype. Is: {}, must be in: 0,1,2,3",
+                got
+            ),
+            LiteralsSectionParseError::GetBitsError(e) => write!(f, "{}", e),
+            LiteralsSectionParseError::NotEnoughBytes { have, need } => write!(
+                f,
+                "Not enough byte to parse the literals section header. Have: {}, Want: {}",
+                have, need
+            ),
+        }
+    }
+}
+
+impl From<GetBitsError> for LiteralsSectionParseError {
+    fn from(err: GetBitsError) -> Self {
+        LiteralsSectionParseError::GetBitsError(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LiteralsSectionParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequencesHeaderParseError {
+    NotEnoughBytes { need_at_least: u8, got: usize },
+}
+
+impl fmt::Display for SequencesHeaderParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequencesHeaderParseError::NotEnoughBytes { need_at_least, got } => write!(
+                f,
+                "source must have at least {} bytes to parse header, got: {}",
+                need_at_least, got
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SequencesHeaderParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompressLiteralsError {
+    MissingCompressedSize,
+    MissingNumStreams,
+    NotEnoughBytes { have: usize, need: usize },
+    UninitializedHuffmanTable,
+    HuffmanTableError(HuffmanTableError),
+    GetBitsError(GetBitsError),
+    MissingBytesForJumpHeader { got: usize },
+    MissingBytesForLiterals { got: usize, needed: usize },
+    RegeneratedSizeTooSmallForFourStreams { got: usize },
+    ExtraPadding { skipped_bits: i32 },
+    TooManyLiterals { expected: usize },
+    BitstreamReadMismatch { read_till: isize, expected: isize },
+    DecodedLiteralCountMismatch { decoded: usize, expected: usize },
+}
+
+impl fmt::Display for DecompressLiteralsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressLiteralsError::MissingCompressedSize => write!(
+                f,
+                "compressed size was none even though it must be set to something for compressed literals"
+            ),
+            DecompressLiteralsError::MissingNumStreams => write!(
+                f,
+                "num_streams was none even though it must be set to something (1 or 4) for compressed literals"
+            ),
+            DecompressLiteralsError::NotEnoughBytes { have, need } => write!(
+                f,
+                "Not enough bytes for the literals. Have: {}, need: {}",
+                have, need
+            ),
+            DecompressLiteralsError::UninitializedHuffmanTable => write!(
+                f,
+                "Tried to reuse huffman table but it was never initialized"
+            ),
+            DecompressLiteralsError::HuffmanTableError(e) => write!(f, "{}", e),
+            DecompressLiteralsError::GetBitsError(e) => write!(f, "{}", e),
+            DecompressLiteralsError::MissingBytesForJumpHeader { got } => {
+                write!(f, "Need 6 byte to decode jump header, got {} bytes", got)
+            }
+            DecompressLiteralsError::MissingBytesForLiterals { got, needed } => write!(
+                f,
+                "Need at least {} byte to decode literals. Have: {}",
+                needed, got
+            ),
+            DecompressLiteralsError::RegeneratedSizeTooSmallForFourStreams { got } => write!(
+                f,
+                "Regenerated size: {} is too small to be split into 4 streams",
+                got
+            ),
+            DecompressLiteralsError::ExtraPadding { skipped_bits } => write!(
+                f,
+                "Padding at the end of the sequence_section was more than a byte long: {}. Probably cause by data corruption",
+                skipped_bits
+            ),
+            DecompressLiteralsError::TooManyLiterals { expected } => write!(
+                f,
+                "Bitstream contains more literals than the {} that were expected",
+                expected
+            ),
+            DecompressLiteralsError::BitstreamReadMismatch {
+                read_till,
+                expected,
+            } => write!(
+                f,
+                "Bitstream was read till: {}, should have been: {}",
+                read_till, expected
+            ),
+            DecompressLiteralsError::DecodedLiteralCountMismatch { decoded, expected } => write!(
+                f,
+                "Did not decode enough literals: {}, Should have been: {}",
+                decoded, expected
+            ),
+        }
+    }
+}
+
+impl From<HuffmanTableError> for DecompressLiteralsError {
+    fn from(err: HuffmanTableError) -> Self {
+        DecompressLiteralsError::HuffmanTableError(err)
+    }
+}
+
+impl From<GetBitsError> for DecompressLiteralsError {
+    fn from(err: GetBitsError) -> Self {
+        DecompressLiteralsError::GetBitsError(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecompressLiteralsError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeSequenceError {
+    GetBitsError(GetBitsError),
+    FSEDecoderError(FSEDecoderError),
+    FSETableError(FSETableError),
+    MissingCompressionMode,
+    MissingByteForRleLlTable,
+    MissingByteForRleOfTable,
+    MissingByteForRleMlTable,
+    RepeatModeWithoutTable,
+    ExtraPadding { skipped_bits: i32 },
+    UnsupportedOffset { offset_code: u8 },
+    ZeroOffset,
+    NotEnoughBytesForNumSequences,
+    ExtraBits { bits_remaining: isize },
+}
+
+impl fmt::Display for DecodeSequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeSequenceError::GetBitsError(e) => write!(f, "{}", e),
+            DecodeSequenceError::FSEDecoderError(e) => write!(f, "{}", e),
+            DecodeSequenceError::FSETableError(e) => write!(f, "{}", e),
+            DecodeSequenceError::MissingCompressionMode => write!(
+                f,
+                "compression modes are none but they must be set to something"
+            ),
+            DecodeSequenceError::MissingByteForRleLlTable => {
+                write!(f, "Need a byte to read for RLE ll table")
+            }
+            DecodeSequenceError::MissingByteForRleOfTable => {
+                write!(f, "Need a byte to read for RLE of table")
+            }
+            DecodeSequenceError::MissingByteForRleMlTable => {
+                write!(f, "Need a byte to read for RLE ml table")
+            }
+            DecodeSequenceError::RepeatModeWithoutTable => write!(
+                f,
+                "Repeat mode was used but no table has been used before that could be repeated"
+            ),
+            DecodeSequenceError::ExtraPadding { skipped_bits } => write!(
+                f,
+                "Padding at the end of the sequence_section was more than a byte long: {}. Probably cause by data corruption",
+                skipped_bits
+            ),
+            DecodeSequenceError::UnsupportedOffset { offset_code } => write!(
+                f,
+                "Do not support offsets bigger than 1<<32, got offset code: {}",
+                offset_code
+            ),
+            DecodeSequenceError::ZeroOffset => write!(
+                f,
+                "Read an offset == 0. That is an illegal value for offsets"
+            ),
+            DecodeSequenceError::NotEnoughBytesForNumSequences => write!(
+                f,
+                "Bytestream did not contain enough bytes to decode num_sequences"
+            ),
+            DecodeSequenceError::ExtraBits { bits_remaining } => write!(
+                f,
+                "Did not use full bitstream. Bits left: {} ({} bytes)",
+                bits_remaining,
+                bits_remaining / 8
+            ),
+        }
+    }
+}
+
+impl From<GetBitsError> for DecodeSequenceError {
+    fn from(err: GetBitsError) -> Self {
+        DecodeSequenceError::GetBitsError(err)
+    }
+}
+
+impl From<FSEDecoderError> for DecodeSequenceError {
+    fn from(err: FSEDecoderError) -> Self {
+        DecodeSequenceError::FSEDecoderError(err)
+    }
+}
+
+impl From<FSETableError> for DecodeSequenceError {
+    fn from(err: FSETableError) -> Self {
+        DecodeSequenceError::FSETableError(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeSequenceError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkspaceDecoderError {
+    FailedToReadFrameHeader(FrameHeaderError),
+    DictNotSupported { dict_id: u32 },
+    WindowTooBig { window_size: u64 },
+    WorkspaceTooSmall { have: usize, need: usize },
+    NotEnoughBytes { have: usize, need: usize },
+    ReservedBlockType,
+    BlockTooBig { got: usize, max: usize },
+    FailedToReadLiteralsSection(LiteralsSectionParseError),
+    FailedToDecodeLiterals(DecompressLiteralsError),
+    FailedToReadSequencesHeader(SequencesHeaderParseError),
+    FailedToDecodeSequences(DecodeSequenceError),
+    NotEnoughLiterals { need: usize, have: usize },
+    ZeroOffset,
+    OffsetTooBig { offset: usize, max: usize },
+    ChecksumMismatch { from_data: u32, calculated: u32 },
+}
+
+impl fmt::Display for WorkspaceDecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkspaceDecoderError::FailedToReadFrameHeader(e) => {
+                write!(f, "Failed to parse frame header: {}", e)
+            }
+            WorkspaceDecoderError::DictNotSupported { dict_id } => write!(
+                f,
+                "Frame needs dictionary {}, but the workspace decoder does not support dictionaries",
+                dict_id
+            ),
+            WorkspaceDecoderError::WindowTooBig { window_size } => write!(
+                f,
+                "Window size {} is too big to be held in memory on this platform",
+                window_size
+            ),
+            WorkspaceDecoderError::WorkspaceTooSmall { have, need } => write!(
+                f,
+                "Workspace is too small for this frame. Have: {} bytes, need: {} bytes",
+                have, need
+            ),
+            WorkspaceDecoderError::NotEnoughBytes { have, need } => write!(
+                f,
+                "Source is truncated. Have: {} bytes, need at least: {} bytes",
+                have, need
+            ),
+            WorkspaceDecoderError::ReservedBlockType => write!(
+                f,
+                "Reserved block occured. This is considered corruption by the documentation"
+            ),
+            WorkspaceDecoderError::BlockTooBig { got, max } => write!(
+                f,
+                "Block is bigger than the maximum block size of this frame. Is: {}, Max: {}",
+                got, max
+            ),
+            WorkspaceDecoderError::FailedToReadLiteralsSection(e) => {
+                write!(f, "Failed to parse literals section header: {}", e)
+            }
+            WorkspaceDecoderError::FailedToDecodeLiterals(e) => {
+                write!(f, "Failed to decode literals: {}", e)
+            }
+            WorkspaceDecoderError::FailedToReadSequencesHeader(e) => {
+                write!(f, "Failed to parse sequences header: {}", e)
+            }
+            WorkspaceDecoderError::FailedToDecodeSequences(e) => {
+                write!(f, "Failed to decode sequences: {}", e)
+            }
+            WorkspaceDecoderError::NotEnoughLiterals { need, have } => write!(
+                f,
+                "Sequence wants to copy up to byte {}. Bytes in literalsbuffer: {}",
+                need, have
+            ),
+            WorkspaceDecoderError::ZeroOffset => write!(f, "Illegal offset: 0 found"),
+            WorkspaceDecoderError::OffsetTooBig { offset, max } => write!(
+                f,
+                "Offset: {} reaches further back than the decoded data or the window: {}",
+                offset, max
+            ),
+            WorkspaceDecoderError::ChecksumMismatch {
+                from_data,
+                calculated,
+            } => write!(
+                f,
+                "Checksum did not match. From data: {}, calculated while decoding: {}",
+                from_data, calculated
+            ),
+        }
+    }
+}
+
+impl From<FrameHeaderError> for WorkspaceDecoderError {
+    fn from(err: FrameHeaderError) -> Self {
+        WorkspaceDecoderError::FailedToReadFrameHeader(err)
+    }
+}
+
+impl From<LiteralsSectionParseError> for WorkspaceDecoderError {
+    fn from(err: LiteralsSectionParseError) -> Self {
+        WorkspaceDecoderError::FailedToReadLiteralsSection(err)
+    }
+}
+
+impl From<DecompressLiteralsError> for WorkspaceDecoderError {
+    fn from(err: DecompressLiteralsError) -> Self {
+        WorkspaceDecoderError::FailedToDecodeLiterals(err)
+    }
+}
+
+impl From<SequencesHeaderParseError> for WorkspaceDecoderError {
+    fn from(err: SequencesHeaderParseError) -> Self {
+        WorkspaceDecoderError::FailedToReadSequencesHeader(err)
+    }
+}
+
+impl From<DecodeSequenceError> for WorkspaceDecoderError {
+    fn from(err: DecodeSequenceError) -> Self {
+        WorkspaceDecoderError::FailedToDecodeSequences(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WorkspaceDecoderError {}
@@ -1,15 +1,20 @@
-use crate::io::Read;
-use core::convert::TryInto;
 #[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
+use crate::blocks::block::BlockType;
+#[cfg(feature = "alloc")]
+use crate::decoding::block_decoder;
+use crate::errors::FrameHeaderError;
 #[cfg(feature = "alloc")]
-use alloc::string::String;
+use crate::errors::FrameSizeError;
+use crate::io::Read;
+use core::convert::TryInto;
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
 pub const MAGIC_NUM: u32 = 0xFD2F_B528;
 pub const MIN_WINDOW_SIZE: u64 = 1024;
 pub const MAX_WINDOW_SIZE: u64 = (1 << 41) + 7 * (1 << 38);
+pub const SKIPPABLE_MAGIC_NUM_MIN: u32 = 0x184D_2A50;
+pub const SKIPPABLE_MAGIC_NUM_MAX: u32 = 0x184D_2A5F;
 
 pub struct Frame {
     magic_num: u32,
@@ -19,8 +24,10 @@ pub struct Frame {
 pub struct FrameHeader {
     pub descriptor: FrameDescriptor,
     window_descriptor: u8,
-    dict_id: Vec<u8>,
-    frame_content_size: Vec<u8>,
+    /// Only the first dictionary_id_bytes() bytes are used
+    dict_id: [u8; 4],
+    /// Only the first frame_content_size_bytes() bytes are used
+    frame_content_size: [u8; 8],
 }
 
 pub struct FrameDescriptor(u8);
@@ -47,45 +54,35 @@ impl FrameDescriptor {
     }
 
     // Deriving info from the flags
-    pub fn frame_content_size_bytes(&self) -> Result<u8, String> {
+    pub fn frame_content_size_bytes(&self) -> u8 {
         match self.frame_content_size_flag() {
             0 => {
                 if self.single_segment_flag() {
-                    Ok(1)
+                    1
                 } else {
-                    Ok(0)
+                    0
                 }
             }
-            1 => Ok(2),
-            2 => Ok(4),
-            3 => Ok(8),
-            _ => Err(format!(
-                "Invalid Frame_Content_Size_Flag Is: {} Should be one of: 0,1,2,3",
-                self.frame_content_size_flag()
-            )),
+            1 => 2,
+            2 => 4,
+            _ => 8,
         }
     }
 
-    pub fn dictionary_id_bytes(&self) -> Result<u8, String> {
+    pub fn dictionary_id_bytes(&self) -> u8 {
         match self.dict_id_flag() {
-            0 => Ok(0),
-            1 => Ok(1),
-            2 => Ok(2),
-            3 => Ok(4),
-            _ => Err(format!(
-                "Invalid Frame_Content_Size_Flag Is: {} Should be one of: 0,1,2,3",
-                self.frame_content_size_flag()
-            )),
+            0 => 0,
+            1 => 1,
+            2 => 2,
+            _ => 4,
         }
     }
 }
+
 impl FrameHeader {
-    pub fn window_size(&self) -> Result<u64, String> {
+    pub fn window_size(&self) -> Result<u64, FrameHeaderError> {
         if self.descriptor.single_segment_flag() {
-            match self.frame_content_size() {
-                Ok(x) => Ok(x),
-                Err(m) => Err(m),
-            }
+            self.frame_content_size()
         } else {
             let exp = self.window_descriptor >> 3;
             let mantissa = self.window_descriptor & 0x7;
@@ -100,197 +97,127 @@ impl FrameHeader {
                 if window_size < MAX_WINDOW_SIZE {
                     Ok(window_size)
                 } else {
-                    Err(format!(
-                        "window_size bigger than allowed maximum. Is: {}, Should be lower than: {}",
-                        window_size, MAX_WINDOW_SIZE
-                    ))
+                    Err(FrameHeaderError::WindowTooBig { got: window_size })
                 }
             } else {
-                Err(format!(
-                    "window_size smaller than allowed minimum. Is: {}, Should be greater than: {}",
-                    window_size, MIN_WINDOW_SIZE
-                ))
+                Err(FrameHeaderError::WindowTooSmall { got: window_size })
             }
         }
     }
 
-    pub fn dictiornary_id(&self) -> Result<Option<u32>, String> {
-        if self.descriptor.dict_id_flag() == 0 {
-            Ok(None)
+    pub fn dictiornary_id(&self) -> Option<u32> {
+        let bytes = self.descriptor.dictionary_id_bytes() as usize;
+        if bytes == 0 {
+            None
         } else {
-            match self.descriptor.dictionary_id_bytes() {
-                Err(m) => Err(m),
-                Ok(bytes) => {
-                    if self.dict_id.len() != bytes as usize {
-                        Err(format!(
-                            "Not enough bytes in dict_id. Is: {}, Should be: {}",
-                            self.dict_id.len(),
-                            bytes
-                        ))
-                    } else {
-                        let mut value: u32 = 0;
-                        let mut shift = 0;
-                        for x in &self.dict_id {
-                            value |= (*x as u32) << shift;
-                            shift += 8;
-                        }
-
-                        Ok(Some(value))
-                    }
-                }
+            let mut value: u32 = 0;
+            let mut shift = 0;
+            for x in &self.dict_id[..bytes] {
+                value |= (*x as u32) << shift;
+                shift += 8;
             }
+
+            Some(value)
         }
     }
 
-    pub fn frame_content_size(&self) -> Result<u64, String> {
+    pub fn frame_content_size(&self) -> Result<u64, FrameHeaderError> {
         match self.descriptor.frame_content_size_bytes() {
-            Err(m) => Err(m),
-            Ok(bytes) => match bytes {
-                0 => Err("Bytes was zero".to_owned()),
-                1 => {
-                    if self.frame_content_size.len() == 1 {
-                        Ok(u64::from(self.frame_content_size[0]))
-                    } else {
-                        Err(format!(
-                            "frame_content_size not long enough. Is: {}, Should be: {}",
-                            self.frame_content_size.len(),
-                            bytes
-                        ))
-                    }
-                }
-                2 => {
-                    if self.frame_content_size.len() == 2 {
-                        let val = (u64::from(self.frame_content_size[1]) << 8)
-                            + (u64::from(self.frame_content_size[0]));
-                        Ok(val + 256) //this weird offset is from the documentation. Only if bytes == 2
-                    } else {
-                        Err(format!(
-                            "frame_content_size not long enough. Is: {}, Should be: {}",
-                            self.frame_content_size.len(),
-                            bytes
-                        ))
-                    }
-                }
-                4 => {
-                    if self.frame_content_size.len() == 4 {
-                        let val = self.frame_content_size[..4]
-                            .try_into()
-                            .expect("optimized away");
-                        let val = u32::from_le_bytes(val);
-                        Ok(u64::from(val))
-                    } else {
-                        Err(format!(
-                            "frame_content_size not long enough. Is: {}, Should be: {}",
-                            self.frame_content_size.len(),
-                            bytes
-                        ))
-                    }
-                }
-                8 => {
-                    if self.frame_content_size.len() == 8 {
-                        let val = self.frame_content_size[..8]
-                            .try_into()
-                            .expect("optimized away");
-                        let val = u64::from_le_bytes(val);
-                        Ok(val)
-                    } else {
-                        Err(format!(
-                            "frame_content_size not long enough. Is: {}, Should be: {}",
-                            self.frame_content_size.len(),
-                            bytes
-                        ))
-                    }
-                }
-                _ => Err(format!(
-                    "Invalid amount of bytes'. Is: {}, Should be one of 1,2,4,8",
-                    self.frame_content_size.len()
-                )),
-            },
+            0 => Err(FrameHeaderError::FrameContentSizeNotPresent),
+            1 => Ok(u64::from(self.frame_content_size[0])),
+            2 => {
+                let val = (u64::from(self.frame_content_size[1]) << 8)
+                    + (u64::from(self.frame_content_size[0]));
+                Ok(val + 256) //this weird offset is from the documentation. Only if bytes == 2
+            }
+            4 => {
+                let val = self.frame_content_size[..4]
+                    .try_into()
+                    .expect("optimized away");
+                let val = u32::from_le_bytes(val);
+                Ok(u64::from(val))
+            }
+            _ => Ok(u64::from_le_bytes(self.frame_content_size)),
         }
     }
 }
 
 impl Frame {
-    pub fn check_valid(&self) -> Result<(), String> {
+    /// Writes the frame header the way read_frame_header() reads it, starting with the magic number
+    #[cfg(feature = "alloc")]
+    pub fn write_header(&self, out: &mut Vec<u8>) {
+        let header = &self.header;
+        out.extend_from_slice(&self.magic_num.to_le_bytes());
+        out.push(header.descriptor.0);
+        if !header.descriptor.single_segment_flag() {
+            out.push(header.window_descriptor);
+        }
+        out.extend_from_slice(&header.dict_id[..header.descriptor.dictionary_id_bytes() as usize]);
+        out.extend_from_slice(
+            &header.frame_content_size[..header.descriptor.frame_content_size_bytes() as usize],
+        );
+    }
+
+    pub fn check_valid(&self) -> Result<(), FrameHeaderError> {
         if self.magic_num != MAGIC_NUM {
-            Err(format!(
-                "magic_num wrong. Is: {}. Should be: {}",
-                self.magic_num, MAGIC_NUM
-            ))
+            Err(FrameHeaderError::BadMagicNumber(self.magic_num))
         } else if self.header.descriptor.reserved_flag() {
-            Err("Reserved Flag set. Must be zero".to_owned())
+            Err(FrameHeaderError::ReservedFlagSet)
         } else {
-            match self.header.dictiornary_id() {
-                Ok(_) => match self.header.window_size() {
-                    Ok(_) => {
-                        if self.header.descriptor.single_segment_flag() {
-                            match self.header.frame_content_size() {
-                                Ok(_) => Ok(()),
-                                Err(m) => Err(m),
-                            }
-                        } else {
-                            Ok(())
-                        }
-                    }
-                    Err(m) => Err(m),
-                },
-                Err(m) => Err(m),
-            }
+            // for single segment frames this checks the frame content size
+            self.header.window_size().map(|_| ())
         }
     }
 }
 
-pub fn read_frame_header(r: &mut dyn Read) -> Result<(Frame, u8), String> {
+pub fn read_frame_header(r: &mut dyn Read) -> Result<(Frame, u8), FrameHeaderError> {
     let mut buf = [0u8; 4];
     let magic_num: u32 = match r.read_exact(&mut buf) {
         Ok(_) => u32::from_le_bytes(buf),
-        Err(_) => return Err("Error while reading magic number".to_owned()),
+        Err(_) => return Err(FrameHeaderError::MagicNumberReadError),
     };
 
     let mut bytes_read = 4;
 
     let desc: FrameDescriptor = match r.read_exact(&mut buf[0..1]) {
         Ok(_) => FrameDescriptor(buf[0]),
-        Err(_) => return Err("Error while reading frame descriptor".to_owned()),
+        Err(_) => return Err(FrameHeaderError::FrameDescriptorReadError),
     };
 
     bytes_read += 1;
 
+    let dict_id_bytes = desc.dictionary_id_bytes() as usize;
+    let fcs_bytes = desc.frame_content_size_bytes() as usize;
+
     let mut frame_header = FrameHeader {
         descriptor: FrameDescriptor(desc.0),
-        dict_id: match desc.dictionary_id_bytes() {
-            Ok(bytes) => vec![0; bytes as usize],
-            Err(m) => return Err(m),
-        },
-        frame_content_size: match desc.frame_content_size_bytes() {
-            Ok(bytes) => vec![0; bytes as usize],
-            Err(m) => return Err(m),
-        },
+        dict_id: [0; 4],
+        frame_content_size: [0; 8],
         window_descriptor: 0,
     };
 
     if !desc.single_segment_flag() {
         match r.read_exact(&mut buf[0..1]) {
             Ok(_) => frame_header.window_descriptor = buf[0],
-            Err(_) => return Err("Error while reading window descriptor".to_owned()),
+            Err(_) => return Err(FrameHeaderError::WindowDescriptorReadError),
         }
         bytes_read += 1;
     }
 
-    if !frame_header.dict_id.is_empty() {
-        match r.read_exact(&mut frame_header.dict_id.as_mut_slice()) {
+    if dict_id_bytes != 0 {
+        match r.read_exact(&mut frame_header.dict_id[..dict_id_bytes]) {
             Ok(_) => {}
-            Err(_) => return Err("Error while reading dcitionary id".to_owned()),
+            Err(_) => return Err(FrameHeaderError::DictionaryIdReadError),
         }
-        bytes_read += frame_header.dict_id.len();
+        bytes_read += dict_id_bytes;
     }
 
-    if !frame_header.frame_content_size.is_empty() {
-        match r.read_exact(&mut frame_header.frame_content_size.as_mut_slice()) {
+    if fcs_bytes != 0 {
+        match r.read_exact(&mut frame_header.frame_content_size[..fcs_bytes]) {
             Ok(_) => {}
-            Err(_) => return Err("Error while reading frame content size".to_owned()),
+            Err(_) => return Err(FrameHeaderError::FrameContentSizeReadError),
         }
-        bytes_read += frame_header.frame_content_size.len();
+        bytes_read += fcs_bytes;
     }
 
     let frame: Frame = Frame {
@@ -300,3 +227,210 @@ pub fn read_frame_header(r: &mut dyn Read) -> Result<(Frame, u8), String> {
 
     Ok((frame, bytes_read as u8))
 }
+
+#[cfg(feature = "alloc")]
+/// What can be learned about a frame from its headers without decoding it
+struct FrameSizeInfo {
+    compressed_size: usize,
+    content_size: Option<u64>,
+    decompressed_bound: u64,
+}
+
+#[cfg(feature = "alloc")]
+fn is_skippable_magic_num(magic_num: u32) -> bool {
+    (SKIPPABLE_MAGIC_NUM_MIN..=SKIPPABLE_MAGIC_NUM_MAX).contains(&magic_num)
+}
+
+#[cfg(feature = "alloc")]
+/// Parses the frame header and walks the block headers of the first frame in src.
+/// Skippable frames are reported with a content size of zero.
+fn read_frame_size_info(src: &[u8]) -> Result<FrameSizeInfo, FrameSizeError> {
+    if src.len() < 4 {
+        return Err(FrameSizeError::NotEnoughBytes {
+            have: src.len(),
+            need: 4,
+        });
+    }
+    let magic_num = u32::from_le_bytes(src[..4].try_into().expect("optimized away"));
+
+    if is_skippable_magic_num(magic_num) {
+        if src.len() < 8 {
+            return Err(FrameSizeError::NotEnoughBytes {
+                have: src.len(),
+                need: 8,
+            });
+        }
+        let skip_size = u32::from_le_bytes(src[4..8].try_into().expect("optimized away"));
+        let compressed_size = 8 + skip_size as usize;
+        if src.len() < compressed_size {
+            return Err(FrameSizeError::NotEnoughBytes {
+                have: src.len(),
+                need: compressed_size,
+            });
+        }
+        return Ok(FrameSizeInfo {
+            compressed_size,
+            content_size: Some(0),
+            decompressed_bound: 0,
+        });
+    }
+
+    if src.len() < 5 {
+        return Err(FrameSizeError::NotEnoughBytes {
+            have: src.len(),
+            need: 5,
+        });
+    }
+    let descriptor = FrameDescriptor(src[4]);
+    let window_descriptor_bytes = if descriptor.single_segment_flag() {
+        0
+    } else {
+        1
+    };
+    let dict_id_bytes = descriptor.dictionary_id_bytes();
+    let fcs_bytes = descriptor.frame_content_size_bytes();
+    let header_size = 5 + window_descriptor_bytes + dict_id_bytes as usize + fcs_bytes as usize;
+    if src.len() < header_size {
+        return Err(FrameSizeError::NotEnoughBytes {
+            have: src.len(),
+            need: header_size,
+        });
+    }
+
+    let mut source = src;
+    let (frame, _) =
+        read_frame_header(&mut source).map_err(FrameSizeError::FailedToReadFrameHeader)?;
+    frame
+        .check_valid()
+        .map_err(FrameSizeError::FailedToReadFrameHeader)?;
+
+    let content_size = frame.header.frame_content_size().ok();
+    let window_size = frame
+        .header
+        .window_size()
+        .map_err(FrameSizeError::FailedToReadFrameHeader)?;
+    let block_size_max = u64::min(
+        window_size,
+        u64::from(block_decoder::ABSOLUTE_MAXIMUM_BLOCK_SIZE),
+    );
+
+    let mut block_dec = block_decoder::new();
+    let mut compressed_size = header_size;
+    let mut decompressed_bound: u64 = 0;
+    loop {
+        if source.len() < 3 {
+            return Err(FrameSizeError::NotEnoughBytes {
+                have: src.len(),
+                need: compressed_size + 3,
+            });
+        }
+        let (block_header, block_header_size) = block_dec
+            .read_block_header(&mut source)
+            .map_err(FrameSizeError::FailedToReadBlockHeader)?;
+        compressed_size += block_header_size as usize;
+
+        let block_content_size = block_header.content_size as usize;
+        if source.len() < block_content_size {
+            return Err(FrameSizeError::NotEnoughBytes {
+                have: src.len(),
+                need: compressed_size + block_content_size,
+            });
+        }
+        source = &source[block_content_size..];
+        compressed_size += block_content_size;
+
+        decompressed_bound += match block_header.block_type {
+            // the decompressed size of compressed blocks is only known after decoding them
+            BlockType::Compressed => block_size_max,
+            _ => u64::from(block_header.decompressed_size),
+        };
+
+        if block_header.last_block {
+            break;
+        }
+    }
+
+    if frame.header.descriptor.content_checksum_flag() {
+        compressed_size += 4;
+        if src.len() < compressed_size {
+            return Err(FrameSizeError::NotEnoughBytes {
+                have: src.len(),
+                need: compressed_size,
+            });
+        }
+    }
+
+    let decompressed_bound = match content_size {
+        Some(size) => size,
+        None => decompressed_bound,
+    };
+
+    Ok(FrameSizeInfo {
+        compressed_size,
+        content_size,
+        decompressed_bound,
+    })
+}
+
+#[cfg(feature = "alloc")]
+/// Returns the exact number of bytes the first frame in src occupies, including the frame header, all blocks and the checksum.
+/// Skippable frames are supported as well. Nothing is decoded, only the frame header and the block headers are parsed.
+///
+/// This can be used to split a buffer that contains multiple concatenated frames or to find the end of a frame in
+/// a larger container. If src ends before the frame does, FrameSizeError::NotEnoughBytes is returned.
+pub fn find_frame_compressed_size(src: &[u8]) -> Result<usize, FrameSizeError> {
+    read_frame_size_info(src).map(|info| info.compressed_size)
+}
+
+#[cfg(feature = "alloc")]
+/// Sums up the content sizes declared in the headers of all frames in src, skipping skippable frames.
+/// This is the equivalent of ZSTD_findDecompressedSize.
+///
+/// Returns None if at least one frame does not declare its content size. In that case decompress_bound()
+/// can be used to get an upper bound instead.
+pub fn find_decompressed_size(src: &[u8]) -> Result<Option<u64>, FrameSizeError> {
+    let mut src = src;
+    let mut total: u64 = 0;
+    let mut all_sizes_known = true;
+
+    while !src.is_empty() {
+        let info = read_frame_size_info(src)?;
+        match info.content_size {
+            Some(size) => {
+                total = total
+                    .checked_add(size)
+                    .ok_or(FrameSizeError::ContentSizeOverflow)?
+            }
+            None => all_sizes_known = false,
+        }
+        src = &src[info.compressed_size..];
+    }
+
+    if all_sizes_known {
+        Ok(Some(total))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Calculates an upper bound for the decompressed size of all frames in src, skipping skippable frames.
+/// This is the equivalent of ZSTD_decompressBound.
+///
+/// Frames that declare their content size contribute exactly that size. For all other frames the bound is
+/// derived from the block headers: raw and RLE blocks contribute their exact size, compressed blocks the maximum block size.
+/// This can be used to safely preallocate the target buffer before decoding.
+pub fn decompress_bound(src: &[u8]) -> Result<u64, FrameSizeError> {
+    let mut src = src;
+    let mut total: u64 = 0;
+
+    while !src.is_empty() {
+        let info = read_frame_size_info(src)?;
+        total = total
+            .checked_add(info.decompressed_bound)
+            .ok_or(FrameSizeError::ContentSizeOverflow)?;
+        src = &src[info.compressed_size..];
+    }
+
+    Ok(total)
+}
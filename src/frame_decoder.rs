@@ -1,18 +1,23 @@
 use super::frame;
+use crate::blocks::block::{BlockHeader, BlockType};
 use crate::decoding;
-use crate::decoding::dictionary::Dictionary;
-use crate::decoding::scratch::DecoderScratch;
-use crate::io::{Error, Read, Write};
+use crate::decoding::block_decoder::{BlockDecoder, ABSOLUTE_MAXIMUM_BLOCK_SIZE};
+use crate::decoding::block_observer::BlockObserver;
+use crate::decoding::dictionary::{Dictionary, DictionaryRegistry};
+use crate::decoding::limits::{OutputLimit, OutputLimits};
+use crate::decoding::progress::{CancellationToken, Progress, ProgressHook};
+use crate::decoding::scratch::{DecoderScratch, NUM_BUFFERS};
+use crate::decoding::snapshot::{self, SnapshotReader, SnapshotWriter};
+use crate::errors::{DecodeBlockContentError, DictionaryDecodeError, SnapshotError};
+use crate::io::{BufRead, Error, Read, Write};
 use core::convert::TryInto;
 use core::hash::Hasher;
 #[cfg(feature = "alloc")]
-use hashbrown::HashMap;
-#[cfg(feature = "std")]
-use std::collections::HashMap;
+use alloc::boxed::Box;
 #[cfg(feature = "alloc")]
-use alloc::vec::Vec;
+use alloc::sync::Arc;
 #[cfg(feature = "alloc")]
-use alloc::string::String;
+use alloc::vec::Vec;
 
 /// This implements a decoder for zstd frames. This decoder is able to decode frames only partially and gives control
 /// over how many bytes/blocks will be decoded at a time (so you dont have to decode a 10GB file into memory all at once).
@@ -62,29 +67,84 @@ use alloc::string::String;
 /// ```
 pub struct FrameDecoder {
     state: Option<FrameDecoderState>,
-    dicts: HashMap<u32, Dictionary>,
+    dicts: DictionaryRegistry,
+    dict_loader: Option<Box<DictLoader>>,
+    /// The copy of the prefix passed to reset_with_prefix(). Reused for the next prefix so it is not copied for every frame
+    prefix: Option<Arc<Dictionary>>,
+    eager_output: bool,
+    /// Only holds the observer until the decoder state has been created, after that it is moved into the DecoderScratch
+    block_observer: Option<Box<dyn BlockObserver + Send>>,
+    progress_hook: Option<Box<ProgressHook>>,
+    cancellation: Option<CancellationToken>,
+    output_limits: OutputLimits,
+    /// How many bytes the frames before the current one decoded to, since the output limits were set
+    stream_output: u64,
+    /// How many frames this decoder has been reset for
+    frame_counter: u64,
+    buffer_reallocations: u64,
+    /// Address and capacity of the buffers when the reallocations were last counted
+    last_buffers: [(usize, usize); NUM_BUFFERS],
 }
 
+/// Callback that is asked for a dictionary if a frame references a dictionary id that is not registered in the FrameDecoder.
+/// Dictionaries returned from it are added to the registry of the FrameDecoder so the callback is only asked once per id.
+pub type DictLoader = dyn FnMut(u32) -> Option<Arc<Dictionary>> + Send;
+
 struct FrameDecoderState {
     pub frame: frame::Frame,
     decoder_scratch: DecoderScratch,
+    block_decoder: BlockDecoder,
     frame_finished: bool,
     block_counter: usize,
     bytes_read_counter: u64,
     check_sum: Option<u32>,
     using_dict: Option<u32>,
+    /// The dictionary is a prefix passed to reset_with_prefix(), it is not in the registry
+    using_prefix: bool,
+    corrupt_block: Option<CorruptBlock>,
+}
+
+/// Where decoding a frame failed, see FrameDecoder::corrupt_block()
+#[derive(Debug, Clone, Copy)]
+pub struct CorruptBlock {
+    /// Index of the block in the frame
+    pub block_index: usize,
+    /// Offset of the block header from the start of the frame
+    pub compressed_offset: u64,
+    /// How many bytes the frame decoded to before this block. All of them can still be collected
+    pub decompressed_offset: u64,
+    /// None if the block header itself could not be read
+    pub header: Option<BlockHeader>,
+    /// How many bytes the block would have decoded to, if that is known. Only raw and RLE blocks declare their size,
+    /// for the last compressed block it follows from the frame content size
+    pub declared_size: Option<u64>,
+    /// Whether the whole block has been consumed from the source, so skip_corrupt_block() can continue after it
+    pub skippable: bool,
+    /// Set if the block is not corrupt but would have exceeded this limit, see FrameDecoder::set_output_limits()
+    pub exceeded_limit: Option<OutputLimit>,
 }
 
+/// How many blocks decode_blocks() decodes before it returns. It always decodes at least one block
+/// and stops after the last block of the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlockDecodingStrategy {
     All,
     UptoBlocks(usize),
+    /// Until the decodebuffer has grown by this many bytes. While the frame is not finished window_size of them
+    /// can not be collected yet, use UntilCollectable to get a certain amount of collectable bytes
     UptoBytes(usize),
+    /// Until can_collect() reports at least this many bytes
+    UntilCollectable(usize),
+    /// Until at least this many bytes have been read from the source. Blocks are never split, so up to one block more is read
+    UptoInputBytes(u64),
 }
 
 const MAX_WINDOW_SIZE: u64 = 1024 * 1024 * 100;
 
 impl FrameDecoderState {
-    pub fn new(source: &mut dyn Read) -> Result<FrameDecoderState, String> {
+    pub fn new(
+        source: &mut dyn Read,
+    ) -> Result<FrameDecoderState, crate::errors::FrameDecoderError> {
         let (frame, header_size) = frame::read_frame_header(source)?;
         let window_size = frame.header.window_size()?;
         frame.check_valid()?;
@@ -93,33 +153,220 @@ impl FrameDecoderState {
             frame_finished: false,
             block_counter: 0,
             decoder_scratch: DecoderScratch::new(window_size as usize),
+            block_decoder: decoding::block_decoder::new(),
             bytes_read_counter: u64::from(header_size),
             check_sum: None,
             using_dict: None,
+            using_prefix: false,
+            corrupt_block: None,
         })
     }
 
-    pub fn reset(&mut self, source: &mut dyn Read) -> Result<(), String> {
+    pub fn reset(&mut self, source: &mut dyn Read) -> Result<(), crate::errors::FrameDecoderError> {
         let (frame, header_size) = frame::read_frame_header(source)?;
         let window_size = frame.header.window_size()?;
         frame.check_valid()?;
 
         if window_size > MAX_WINDOW_SIZE {
-            return Err(format!(
-                "Dont support window_sizes (requested: {}) over: {}",
-                window_size, MAX_WINDOW_SIZE
-            ));
+            return Err(crate::errors::FrameDecoderError::WindowSizeTooBig {
+                requested: window_size,
+                max: MAX_WINDOW_SIZE,
+            });
         }
 
         self.frame = frame;
         self.frame_finished = false;
         self.block_counter = 0;
         self.decoder_scratch.reset(window_size as usize);
+        self.block_decoder = decoding::block_decoder::new();
         self.bytes_read_counter = u64::from(header_size);
         self.check_sum = None;
         self.using_dict = None;
+        self.using_prefix = false;
+        self.corrupt_block = None;
         Ok(())
     }
+
+    /// Remembers where the frame is corrupt and throws away what the failed block already decoded
+    fn block_failed(
+        &mut self,
+        block_start: u64,
+        buffer_len_before: usize,
+        header: Option<BlockHeader>,
+    ) {
+        self.decoder_scratch.buffer.truncate(buffer_len_before);
+        self.decoder_scratch.buffer.stopped_at_corrupt_block = true;
+        let decompressed_offset = self.decoder_scratch.buffer.total_output();
+        let declared_size = match header {
+            Some(h) if h.block_type != BlockType::Compressed => {
+                Some(u64::from(h.decompressed_size))
+            }
+            Some(h) if h.last_block => {
+                let max_block_size = core::cmp::min(
+                    self.decoder_scratch.buffer.window_size as u64,
+                    u64::from(ABSOLUTE_MAXIMUM_BLOCK_SIZE),
+                );
+                match self.frame.header.frame_content_size() {
+                    Ok(size) if size > decompressed_offset => {
+                        Some(core::cmp::min(size - decompressed_offset, max_block_size))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        self.corrupt_block = Some(CorruptBlock {
+            block_index: self.block_counter,
+            compressed_offset: block_start,
+            decompressed_offset,
+            header,
+            declared_size,
+            skippable: header.is_some() && self.block_decoder.is_failed(),
+            exceeded_limit: None,
+        });
+    }
+
+    /// Sets the output limit of the decodebuffer for the block with this header and returns which limit it is
+    fn limit_block_output(
+        &mut self,
+        limits: &OutputLimits,
+        stream_output: u64,
+        header: &BlockHeader,
+    ) -> Option<OutputLimit> {
+        let frame_input = self.bytes_read_counter + u64::from(header.content_size);
+        let limit = limits.frame_output_limit(stream_output, frame_input);
+        self.decoder_scratch
+            .buffer
+            .set_output_limit(limit.map(|(max, _)| max));
+        limit.map(|(_, which)| which)
+    }
+
+    /// The error for a block body that failed to decode after block_failed(), OutputLimitExceeded if it failed because of the limit
+    fn block_body_error(
+        &mut self,
+        limit: Option<OutputLimit>,
+        err: DecodeBlockContentError,
+    ) -> crate::errors::FrameDecoderError {
+        match limit {
+            Some(limit) if self.decoder_scratch.buffer.output_limit_exceeded() => {
+                if let Some(corrupt) = &mut self.corrupt_block {
+                    corrupt.exceeded_limit = Some(limit);
+                }
+                crate::errors::FrameDecoderError::OutputLimitExceeded(limit)
+            }
+            _ => crate::errors::FrameDecoderError::FailedToReadBlockBody(err),
+        }
+    }
+
+    /// Fails while a corrupt block has not been skipped, with the same error again if the block exceeded an output limit
+    fn check_no_corrupt_block(&self) -> Result<(), crate::errors::FrameDecoderError> {
+        match &self.corrupt_block {
+            None => Ok(()),
+            Some(CorruptBlock {
+                exceeded_limit: Some(limit),
+                ..
+            }) => Err(crate::errors::FrameDecoderError::OutputLimitExceeded(
+                *limit,
+            )),
+            Some(_) => Err(crate::errors::FrameDecoderError::CorruptBlockNotSkipped),
+        }
+    }
+
+    fn progress(&self, frame_index: u64) -> Progress {
+        Progress {
+            frame_index,
+            blocks_decoded: self.block_counter,
+            bytes_read: self.bytes_read_counter,
+            bytes_decoded: self.decoder_scratch.buffer.total_output(),
+            content_size: self.frame.header.frame_content_size().ok(),
+        }
+    }
+
+    /// Marks the frame as finished and reads the checksum after the last block, if the frame has one
+    fn finish_frame(
+        &mut self,
+        source: &mut dyn Read,
+    ) -> Result<(), crate::errors::FrameDecoderError> {
+        self.frame_finished = true;
+        if self.frame.header.descriptor.content_checksum_flag() {
+            let mut chksum = [0u8; 4];
+            match source.read_exact(&mut chksum) {
+                Err(_) => return Err(crate::errors::FrameDecoderError::FailedToReadChecksum),
+                Ok(()) => {
+                    self.bytes_read_counter += 4;
+                    let chksum = u32::from_le_bytes(chksum);
+                    self.check_sum = Some(chksum);
+                }
+            };
+        }
+        Ok(())
+    }
+
+    /// Makes sure the dictionary the frame header asks for is used, loading it via the dict_loader if necessary
+    fn select_dict(
+        &mut self,
+        dicts: &mut DictionaryRegistry,
+        dict_loader: &mut Option<Box<DictLoader>>,
+    ) -> Result<(), crate::errors::FrameDecoderError> {
+        match self.frame.header.dictiornary_id() {
+            Some(id) => {
+                match self.using_dict {
+                    Some(using_id) => {
+                        //happy. Prefixes override the dictionary of the frame
+                        debug_assert!(id == using_id || self.using_prefix);
+                    }
+                    None => {
+                        if !ensure_dict(dicts, dict_loader, id) {
+                            return Err(crate::errors::FrameDecoderError::DictNotProvided);
+                        }
+                        let dict = dicts.get(id).expect("checked above");
+                        self.decoder_scratch.use_dict(dict);
+                        self.using_dict = Some(id);
+                    }
+                }
+                Ok(())
+            }
+            None => {
+                // frames without dictionary id use the dictionary with id 0 if there is one
+                if self.using_dict.is_none() {
+                    if let Some(dict) = dicts.get(0) {
+                        self.decoder_scratch.use_dict(dict);
+                        self.using_dict = Some(0);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Makes sure the registry holds the dictionary with this id, asking the dict_loader for it if necessary
+fn ensure_dict(
+    dicts: &mut DictionaryRegistry,
+    dict_loader: &mut Option<Box<DictLoader>>,
+    id: u32,
+) -> bool {
+    if dicts.contains(id) {
+        return true;
+    }
+    let loaded = match dict_loader {
+        Some(loader) => loader(id),
+        None => None,
+    };
+    match loaded {
+        Some(dict) if dict.id == id => {
+            dicts.insert(dict);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn is_cancelled(token: &Option<CancellationToken>) -> bool {
+    match token {
+        Some(token) => token.is_cancelled(),
+        None => false,
+    }
 }
 
 impl Default for FrameDecoder {
@@ -135,7 +382,38 @@ impl FrameDecoder {
     pub fn new() -> FrameDecoder {
         FrameDecoder {
             state: None,
-            dicts: HashMap::new(),
+            dicts: DictionaryRegistry::new(),
+            dict_loader: None,
+            prefix: None,
+            eager_output: false,
+            block_observer: None,
+            progress_hook: None,
+            cancellation: None,
+            output_limits: OutputLimits::default(),
+            stream_output: 0,
+            frame_counter: 0,
+            buffer_reallocations: 0,
+            last_buffers: [(0, 0); NUM_BUFFERS],
+        }
+    }
+
+    /// Like new() but uses the provided dictionaries. The registry can be cloned cheaply
+    /// to share the same dictionaries between multiple decoders
+    pub fn with_dicts(dicts: DictionaryRegistry) -> FrameDecoder {
+        FrameDecoder {
+            state: None,
+            dicts,
+            dict_loader: None,
+            prefix: None,
+            eager_output: false,
+            block_observer: None,
+            progress_hook: None,
+            cancellation: None,
+            output_limits: OutputLimits::default(),
+            stream_output: 0,
+            frame_counter: 0,
+            buffer_reallocations: 0,
+            last_buffers: [(0, 0); NUM_BUFFERS],
         }
     }
 
@@ -145,11 +423,15 @@ impl FrameDecoder {
     /// Note that all bytes currently in the decodebuffer from any previous frame will be lost. Collect them with collect()/collect_to_writer()
     ///
     /// equivalent to reset()
-    pub fn init(&mut self, source: &mut dyn Read) -> Result<(), String> {
+    pub fn init(&mut self, source: &mut dyn Read) -> Result<(), crate::errors::FrameDecoderError> {
         self.reset(source)
     }
     /// Like init but provides the dict to use for the next frame
-    pub fn init_with_dict(&mut self, source: &mut dyn Read, dict: &[u8]) -> Result<(), String> {
+    pub fn init_with_dict(
+        &mut self,
+        source: &mut dyn Read,
+        dict: &[u8],
+    ) -> Result<(), crate::errors::FrameDecoderError> {
         self.reset_with_dict(source, dict)
     }
 
@@ -159,33 +441,190 @@ impl FrameDecoder {
     /// Note that all bytes currently in the decodebuffer from any previous frame will be lost. Collect them with collect()/collect_to_writer()
     ///
     /// equivalent to init()
-    pub fn reset(&mut self, source: &mut dyn Read) -> Result<(), String> {
+    pub fn reset(&mut self, source: &mut dyn Read) -> Result<(), crate::errors::FrameDecoderError> {
+        if let Some(s) = &self.state {
+            self.stream_output += s.decoder_scratch.buffer.total_output();
+        }
+        self.reset_state(source)?;
+        self.frame_counter += 1;
+        self.count_buffer_reallocations();
+        Ok(())
+    }
+
+    /// Reads the frame header and prepares the state for that frame, without counting it as the next frame of the stream
+    fn reset_state(
+        &mut self,
+        source: &mut dyn Read,
+    ) -> Result<(), crate::errors::FrameDecoderError> {
         match &mut self.state {
-            Some(s) => s.reset(source),
-            None => {
-                self.state = Some(FrameDecoderState::new(source)?);
-                Ok(())
+            Some(s) => s.reset(source)?,
+            None => self.state = Some(FrameDecoderState::new(source)?),
+        }
+        if let Some(state) = &mut self.state {
+            state.decoder_scratch.buffer.eager_output = self.eager_output;
+            if let Some(observer) = self.block_observer.take() {
+                state.decoder_scratch.block_observer = Some(observer);
             }
         }
+        Ok(())
     }
 
     /// Like reset but provides the dict to use for the next frame
-    pub fn reset_with_dict(&mut self, source: &mut dyn Read, dict: &[u8]) -> Result<(), String> {
+    pub fn reset_with_dict(
+        &mut self,
+        source: &mut dyn Read,
+        dict: &[u8],
+    ) -> Result<(), crate::errors::FrameDecoderError> {
         self.reset(source)?;
         if let Some(state) = &mut self.state {
-            let id = state.decoder_scratch.load_dict(dict)?;
+            let id = state
+                .decoder_scratch
+                .load_dict(dict)
+                .map_err(crate::errors::FrameDecoderError::FailedToLoadDict)?;
             state.using_dict = Some(id);
         };
         Ok(())
     }
 
+    /// Like reset but uses prefix as content that precedes the next frame, like ZSTD_DCtx_refPrefix.
+    /// The prefix is only used for this frame and takes precedence over any dictionary the frame header asks for
+    pub fn reset_with_prefix(
+        &mut self,
+        source: &mut dyn Read,
+        prefix: &[u8],
+    ) -> Result<(), crate::errors::FrameDecoderError> {
+        self.reset(source)?;
+        let dict = self.prefix_dict(prefix);
+        if let Some(state) = &mut self.state {
+            state.decoder_scratch.use_dict(&dict);
+            state.using_dict = Some(0);
+            state.using_prefix = true;
+        };
+        Ok(())
+    }
+
+    /// The prefix as dictionary. The copy of the previous prefix is used if it has the same content,
+    /// else its buffer is reused if no frame uses it anymore
+    fn prefix_dict(&mut self, prefix: &[u8]) -> Arc<Dictionary> {
+        match &mut self.prefix {
+            Some(dict) if dict.dict_content[..] == *prefix => {}
+            Some(dict) => match Arc::get_mut(dict) {
+                Some(dict) => {
+                    dict.dict_content.clear();
+                    dict.dict_content.extend_from_slice(prefix);
+                }
+                None => *dict = Arc::new(Dictionary::from_raw_content(0, prefix)),
+            },
+            None => self.prefix = Some(Arc::new(Dictionary::from_raw_content(0, prefix))),
+        }
+        Arc::clone(self.prefix.as_ref().expect("set above"))
+    }
+
     /// Add a dict to the FrameDecoder that can be used when needed. The FrameDecoder uses the appropriate one dynamically
-    pub fn add_dict(&mut self, raw_dict: &[u8]) -> Result<(), String> {
-        let dict = Dictionary::decode_dict(raw_dict)?;
-        self.dicts.insert(dict.id, dict);
+    pub fn add_dict(&mut self, raw_dict: &[u8]) -> Result<(), DictionaryDecodeError> {
+        self.dicts.add_dict(raw_dict)?;
         Ok(())
     }
 
+    /// Add content without entropy tables as dictionary. Content registered with id 0 is used for all frames without a dictionary id
+    pub fn add_raw_content_dict(&mut self, id: u32, content: &[u8]) {
+        self.dicts.add_raw_content(id, content);
+    }
+
+    /// Like add_dict but for an already parsed dictionary that may be shared with other decoders
+    pub fn add_shared_dict(&mut self, dict: Arc<Dictionary>) {
+        self.dicts.insert(dict);
+    }
+
+    /// Removes the dict with this id. Frames that are already being decoded with it are not affected
+    pub fn remove_dict(&mut self, id: u32) -> Option<Arc<Dictionary>> {
+        self.dicts.remove(id)
+    }
+
+    /// The dictionaries that can currently be used by this FrameDecoder
+    pub fn dicts(&self) -> &DictionaryRegistry {
+        &self.dicts
+    }
+
+    pub fn dicts_mut(&mut self) -> &mut DictionaryRegistry {
+        &mut self.dicts
+    }
+
+    /// Set a callback that is used to load dictionaries on demand, if a frame needs a dictionary that was not added before.
+    /// Without a callback decoding such a frame fails with crate::errors::FrameDecoderError::DictNotProvided
+    pub fn set_dict_loader(&mut self, loader: Box<DictLoader>) {
+        self.dict_loader = Some(loader);
+    }
+
+    /// If set, collect(), read() and can_collect() include all bytes of the decoded blocks instead of holding back window_size bytes
+    /// until the frame is finished. The window is still kept internally for the following blocks.
+    /// This is needed for interactive protocols where the sender flushes after each message and waits for an answer.
+    /// A StreamingDecoder then returns from read() as soon as a block decoded to anything. Takes effect immediately
+    ///
+    /// This only covers the decoding side of flushing, the crate has no encoder that could flush
+    pub fn set_eager_output(&mut self, eager: bool) {
+        self.eager_output = eager;
+        if let Some(state) = &mut self.state {
+            state.decoder_scratch.buffer.eager_output = eager;
+        }
+    }
+
+    pub fn eager_output(&self) -> bool {
+        self.eager_output
+    }
+
+    /// Set an observer that is told how each block was encoded after it has been decoded. Replaces the previous observer.
+    /// Takes effect immediately, also for the frame currently being decoded
+    pub fn set_block_observer(&mut self, observer: Box<dyn BlockObserver + Send>) {
+        match &mut self.state {
+            Some(state) => state.decoder_scratch.block_observer = Some(observer),
+            None => self.block_observer = Some(observer),
+        }
+    }
+
+    /// Removes the observer set with set_block_observer() and returns it
+    pub fn take_block_observer(&mut self) -> Option<Box<dyn BlockObserver + Send>> {
+        match &mut self.state {
+            Some(state) => state.decoder_scratch.block_observer.take(),
+            None => self.block_observer.take(),
+        }
+    }
+
+    /// Set a callback that is called after every block decoded by decode_blocks() or decode_from_to(), e.g. to show the progress in a UI.
+    /// Replaces the previous hook
+    pub fn set_progress_hook(&mut self, hook: Box<ProgressHook>) {
+        self.progress_hook = Some(hook);
+    }
+
+    /// Removes the hook set with set_progress_hook() and returns it
+    pub fn take_progress_hook(&mut self) -> Option<Box<ProgressHook>> {
+        self.progress_hook.take()
+    }
+
+    /// After the token has been cancelled, decode_blocks() and decode_from_to() stop before decoding the next block
+    /// and return crate::errors::FrameDecoderError::Cancelled. Decoding can continue after setting a new token or removing it
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Removes the token set with set_cancellation_token() and returns it
+    pub fn take_cancellation_token(&mut self) -> Option<CancellationToken> {
+        self.cancellation.take()
+    }
+
+    /// Limit how many bytes decode_blocks() and decode_from_to() decode, to protect against decompression bombs.
+    /// A block that would exceed a limit fails with crate::errors::FrameDecoderError::OutputLimitExceeded before its output is allocated,
+    /// and is reported by corrupt_block(). Until the block is skipped or the decoder is reset, decoding fails with the same error.
+    /// The stream limit counts from the current frame on
+    pub fn set_output_limits(&mut self, limits: OutputLimits) {
+        self.output_limits = limits;
+        self.stream_output = 0;
+    }
+
+    pub fn output_limits(&self) -> OutputLimits {
+        self.output_limits
+    }
+
     /// Returns how many bytes the frame contains after decompression
     pub fn content_size(&self) -> Option<u64> {
         let state = match &self.state {
@@ -193,10 +632,7 @@ impl FrameDecoder {
             Some(s) => s,
         };
 
-        match state.frame.header.frame_content_size() {
-            Err(_) => None,
-            Ok(x) => Some(x),
-        }
+        state.frame.header.frame_content_size().ok()
     }
 
     /// Returns the checksum that was read from the data. Only available after all bytes have been read. It is the last 4 bytes of a zstd-frame
@@ -262,74 +698,89 @@ impl FrameDecoder {
         &mut self,
         source: &mut dyn Read,
         strat: BlockDecodingStrategy,
+    ) -> Result<bool, crate::errors::FrameDecoderError> {
+        self.decode_blocks_with(source, strat, |block_dec, header, scratch, source| {
+            block_dec.decode_block_content(header, scratch, source)
+        })
+    }
+
+    /// Like decode_blocks() but compressed blocks are decoded directly from the buffer of the source instead of being copied first,
+    /// as long as the source buffers the whole block. This also works with slices, which buffer all their bytes.
+    ///
+    /// Only the bytes of the frame are consumed, after the last block the source is positioned right after the frame
+    pub fn decode_blocks_from_buf<R: BufRead + ?Sized>(
+        &mut self,
+        source: &mut R,
+        strat: BlockDecodingStrategy,
+    ) -> Result<bool, crate::errors::FrameDecoderError> {
+        self.decode_blocks_with(source, strat, |block_dec, header, scratch, source| {
+            block_dec.decode_block_content_buffered(header, scratch, source)
+        })
+    }
+
+    fn decode_blocks_with<R: Read + ?Sized>(
+        &mut self,
+        source: &mut R,
+        strat: BlockDecodingStrategy,
+        mut decode_block_content: impl FnMut(
+            &mut BlockDecoder,
+            &BlockHeader,
+            &mut DecoderScratch,
+            &mut R,
+        ) -> Result<u64, DecodeBlockContentError>,
     ) -> Result<bool, crate::errors::FrameDecoderError> {
         let state = match &mut self.state {
             None => return Err(crate::errors::FrameDecoderError::NotYetInitialized),
             Some(s) => s,
         };
 
-        match state.frame.header.dictiornary_id() {
-            Ok(Some(id)) => {
-                match state.using_dict {
-                    Some(using_id) => {
-                        //happy
-                        debug_assert!(id == using_id);
-                    }
-                    None => {
-                        let dict = match self.dicts.get(&id) {
-                            Some(dict) => dict,
-                            None => return Err(crate::errors::FrameDecoderError::DictNotProvided),
-                        };
-                        state.decoder_scratch.use_dict(dict);
-                        state.using_dict = Some(id);
-                    }
-                }
-            }
-            Ok(None) => {}
-            Err(e) => {
-                //should never happen we check this directly after decoding the frame header
-                return Err(crate::errors::FrameDecoderError::FailedToInitialize(e));
-            }
-        }
-
-        let mut block_dec = decoding::block_decoder::new();
+        state.check_no_corrupt_block()?;
+        state.select_dict(&mut self.dicts, &mut self.dict_loader)?;
 
         let buffer_size_before = state.decoder_scratch.buffer.len();
         let block_counter_before = state.block_counter;
+        let bytes_read_before = state.bytes_read_counter;
         loop {
-            let (block_header, block_header_size) = match block_dec.read_block_header(source) {
-                Ok(h) => h,
-                Err(m) => return Err(crate::errors::FrameDecoderError::FailedToReadBlockHeader(m)),
-            };
+            if is_cancelled(&self.cancellation) {
+                return Err(crate::errors::FrameDecoderError::Cancelled);
+            }
+            let block_start = state.bytes_read_counter;
+            let buffer_len_before_block = state.decoder_scratch.buffer.len();
+            let (block_header, block_header_size) =
+                match state.block_decoder.read_block_header(&mut &mut *source) {
+                    Ok(h) => h,
+                    Err(m) => {
+                        state.block_failed(block_start, buffer_len_before_block, None);
+                        return Err(crate::errors::FrameDecoderError::FailedToReadBlockHeader(m));
+                    }
+                };
             state.bytes_read_counter += u64::from(block_header_size);
+            let limit =
+                state.limit_block_output(&self.output_limits, self.stream_output, &block_header);
 
-            let bytes_read_in_block_body = match block_dec.decode_block_content(
+            let bytes_read_in_block_body = match decode_block_content(
+                &mut state.block_decoder,
                 &block_header,
                 &mut state.decoder_scratch,
                 source,
             ) {
                 Ok(h) => h,
-                Err(m) => return Err(crate::errors::FrameDecoderError::FailedToReadBlockBody(m)),
+                Err(m) => {
+                    state.block_failed(block_start, buffer_len_before_block, Some(block_header));
+                    return Err(state.block_body_error(limit, m));
+                }
             };
             state.bytes_read_counter += bytes_read_in_block_body;
 
             state.block_counter += 1;
 
             if block_header.last_block {
-                state.frame_finished = true;
-                if state.frame.header.descriptor.content_checksum_flag() {
-                    let mut chksum = [0u8; 4];
-                    match source.read_exact(&mut chksum) {
-                        Err(_) => {
-                            return Err(crate::errors::FrameDecoderError::FailedToReadChecksum)
-                        }
-                        Ok(()) => {
-                            state.bytes_read_counter += 4;
-                            let chksum = u32::from_le_bytes(chksum);
-                            state.check_sum = Some(chksum);
-                        }
-                    };
-                }
+                state.finish_frame(&mut &mut *source)?;
+            }
+            if let Some(hook) = &mut self.progress_hook {
+                hook(&state.progress(self.frame_counter - 1));
+            }
+            if state.frame_finished {
                 break;
             }
 
@@ -345,29 +796,54 @@ impl FrameDecoder {
                         break;
                     }
                 }
+                BlockDecodingStrategy::UntilCollectable(n) => {
+                    let collectable = state
+                        .decoder_scratch
+                        .buffer
+                        .can_drain_to_window_size()
+                        .unwrap_or(0);
+                    if collectable >= n {
+                        break;
+                    }
+                }
+                BlockDecodingStrategy::UptoInputBytes(n) => {
+                    if state.bytes_read_counter - bytes_read_before >= n {
+                        break;
+                    }
+                }
             }
         }
 
-        Ok(state.frame_finished)
+        let finished = state.frame_finished;
+        self.count_buffer_reallocations();
+        Ok(finished)
     }
 
     /// Collect bytes and retain window_size bytes while decoding is still going on.
-    /// After decoding of the frame (is_finished() == true) has finished it will collect all remaining bytes
+    /// After decoding of the frame (is_finished() == true) has finished it will collect all remaining bytes.
+    /// After it failed at a corrupt block all bytes are collected as well, but the window is kept in case the block is skipped
     pub fn collect(&mut self) -> Option<Vec<u8>> {
         let finished = self.is_finished();
         let state = match &mut self.state {
             None => return None,
             Some(s) => s,
         };
-        if finished {
+        let collected = if finished {
             Some(state.decoder_scratch.buffer.drain())
         } else {
             state.decoder_scratch.buffer.drain_to_window_size()
+        };
+        // the returned Vec is allocated for the caller, the buffer itself is kept
+        if collected.is_some() {
+            self.buffer_reallocations += 1;
         }
+        self.count_buffer_reallocations();
+        collected
     }
 
     /// Collect bytes and retain window_size bytes while decoding is still going on.
-    /// After decoding of the frame (is_finished() == true) has finished it will collect all remaining bytes
+    /// After decoding of the frame (is_finished() == true) has finished it will collect all remaining bytes.
+    /// After it failed at a corrupt block all bytes are collected as well, but the window is kept in case the block is skipped
     pub fn collect_to_writer(&mut self, w: &mut dyn Write) -> Result<usize, Error> {
         let finished = self.is_finished();
         let state = match &mut self.state {
@@ -382,8 +858,8 @@ impl FrameDecoder {
     }
 
     /// How many bytes can currently be collected from the decodebuffer, while decoding is going on this will be lower than the ectual decodbuffer size
-    /// because window_size bytes need to be retained for decoding.
-    /// After decoding of the frame (is_finished() == true) has finished it will report all remaining bytes
+    /// because window_size bytes need to be retained for decoding, unless set_eager_output() is used.
+    /// After decoding of the frame (is_finished() == true) has finished or failed at a corrupt block it will report all bytes that were not collected yet
     pub fn can_collect(&self) -> usize {
         let finished = self.is_finished();
         let state = match &self.state {
@@ -425,22 +901,18 @@ impl FrameDecoder {
         };
 
         if !self.is_finished() || self.state.is_none() {
-            let mut mt_source = &source[..];
+            let mut mt_source = source;
 
             if self.state.is_none() {
-                match self.init(&mut mt_source) {
-                    Ok(()) => {}
-                    Err(m) => return Err(crate::errors::FrameDecoderError::FailedToInitialize(m)),
-                }
+                self.init(&mut mt_source)?;
             }
 
             //pseudo block to scope "state" so we can borrow self again after the block
             {
-                let mut state = match &mut self.state {
+                let state = match &mut self.state {
                     Some(s) => s,
                     None => panic!("Bug in library"),
                 };
-                let mut block_dec = decoding::block_decoder::new();
 
                 if state.frame.header.descriptor.content_checksum_flag()
                     && state.frame_finished
@@ -456,46 +928,27 @@ impl FrameDecoder {
                     return Ok((4, 0));
                 }
 
-                match state.frame.header.dictiornary_id() {
-                    Ok(Some(id)) => {
-                        match state.using_dict {
-                            Some(using_id) => {
-                                //happy
-                                debug_assert!(id == using_id);
-                            }
-                            None => {
-                                let dict = match self.dicts.get(&id) {
-                                    Some(dict) => dict,
-                                    None => {
-                                        return Err(
-                                            crate::errors::FrameDecoderError::DictNotProvided,
-                                        )
-                                    }
-                                };
-                                state.decoder_scratch.use_dict(dict);
-                                state.using_dict = Some(id);
-                            }
-                        }
-                    }
-                    Ok(None) => {}
-                    Err(e) => {
-                        //should never happen we check this directly after decoding the frame header
-                        return Err(crate::errors::FrameDecoderError::FailedToInitialize(e));
-                    }
-                }
+                state.check_no_corrupt_block()?;
+                state.select_dict(&mut self.dicts, &mut self.dict_loader)?;
 
                 loop {
                     //check if there are enough bytes for the next header
                     if mt_source.len() < 3 {
                         break;
                     }
+                    if is_cancelled(&self.cancellation) {
+                        return Err(crate::errors::FrameDecoderError::Cancelled);
+                    }
+                    let block_start = state.bytes_read_counter;
+                    let buffer_len_before_block = state.decoder_scratch.buffer.len();
                     let (block_header, block_header_size) =
-                        match block_dec.read_block_header(&mut mt_source) {
+                        match state.block_decoder.read_block_header(&mut mt_source) {
                             Ok(h) => h,
                             Err(m) => {
+                                state.block_failed(block_start, buffer_len_before_block, None);
                                 return Err(
                                     crate::errors::FrameDecoderError::FailedToReadBlockHeader(m),
-                                )
+                                );
                             }
                         };
 
@@ -505,17 +958,28 @@ impl FrameDecoder {
                         break;
                     }
                     state.bytes_read_counter += u64::from(block_header_size);
-
-                    let bytes_read_in_block_body = match block_dec.decode_block_content(
+                    let limit = state.limit_block_output(
+                        &self.output_limits,
+                        self.stream_output,
                         &block_header,
-                        &mut state.decoder_scratch,
-                        &mut mt_source,
-                    ) {
-                        Ok(h) => h,
-                        Err(m) => {
-                            return Err(crate::errors::FrameDecoderError::FailedToReadBlockBody(m))
-                        }
-                    };
+                    );
+
+                    let bytes_read_in_block_body =
+                        match state.block_decoder.decode_block_content_buffered(
+                            &block_header,
+                            &mut state.decoder_scratch,
+                            &mut mt_source,
+                        ) {
+                            Ok(h) => h,
+                            Err(m) => {
+                                state.block_failed(
+                                    block_start,
+                                    buffer_len_before_block,
+                                    Some(block_header),
+                                );
+                                return Err(state.block_body_error(limit, m));
+                            }
+                        };
                     state.bytes_read_counter += bytes_read_in_block_body;
                     state.block_counter += 1;
 
@@ -530,6 +994,11 @@ impl FrameDecoder {
                                 state.check_sum = Some(chksum);
                             }
                         }
+                    }
+                    if let Some(hook) = &mut self.progress_hook {
+                        hook(&state.progress(self.frame_counter - 1));
+                    }
+                    if state.frame_finished {
                         break;
                     }
                 }
@@ -545,12 +1014,188 @@ impl FrameDecoder {
             None => panic!("Bug in library"),
         };
         let read_len = bytes_read_at_end - bytes_read_at_start;
+        self.count_buffer_reallocations();
         Ok((read_len as usize, result_len))
     }
+
+    /// Where decoding failed, if decode_blocks() or decode_from_to() returned an error while reading a block of the current frame.
+    /// Until it is skipped or the decoder is reset, no more blocks are decoded and all bytes decoded before the corrupt block can be collected.
+    ///
+    /// If the block can not be skipped, decoding can still continue with the next frame by calling reset() with the source positioned there
+    pub fn corrupt_block(&self) -> Option<&CorruptBlock> {
+        match &self.state {
+            None => None,
+            Some(s) => s.corrupt_block.as_ref(),
+        }
+    }
+
+    /// Skips the block reported by corrupt_block() so decoding can continue with the next block of the frame.
+    /// If the size of the block is declared, zeros of that size are emitted in its place. Returns how many zeros were emitted.
+    /// If it was the last block, the checksum of the frame is read from the source.
+    ///
+    /// Bytes decoded after the skipped block are wrong if they refer to its content, so the checksum will not match anymore.
+    /// The entropy tables are forgotten, so blocks that repeat them fail as well and have to be skipped too.
+    pub fn skip_corrupt_block(
+        &mut self,
+        source: &mut dyn Read,
+    ) -> Result<u64, crate::errors::FrameDecoderError> {
+        let state = match &mut self.state {
+            None => return Err(crate::errors::FrameDecoderError::NotYetInitialized),
+            Some(s) => s,
+        };
+        let corrupt = match state.corrupt_block {
+            Some(c) if c.skippable => c,
+            _ => return Err(crate::errors::FrameDecoderError::CanNotSkipBlock),
+        };
+        let header = corrupt.header.expect("skippable blocks have a header");
+
+        let placeholder = corrupt.declared_size.unwrap_or(0);
+        let limit = state.limit_block_output(&self.output_limits, self.stream_output, &header);
+        if let Err(e) = state
+            .decoder_scratch
+            .buffer
+            .check_output_limit(placeholder as usize)
+        {
+            // the block stays corrupt, decoding fails with the same error until the decoder is reset
+            return Err(state.block_body_error(limit, e.into()));
+        }
+        state.corrupt_block = None;
+        state.decoder_scratch.buffer.stopped_at_corrupt_block = false;
+        state
+            .decoder_scratch
+            .buffer
+            .push_rle(0, placeholder as usize);
+        state.decoder_scratch.reset_tables();
+        state.block_decoder.skip_failed_block();
+        state.bytes_read_counter += u64::from(header.content_size);
+        state.block_counter += 1;
+
+        if header.last_block {
+            state.finish_frame(source)?;
+        }
+        self.count_buffer_reallocations();
+        Ok(placeholder)
+    }
+
+    /// Saves the state of the frame that is being decoded, so decoding can be continued later with restore(), also in another process.
+    /// Decoding continues with the byte at bytes_read_from_source() counted from the start of the frame.
+    /// Bytes that have not been collected yet are part of the snapshot and can be collected after restoring it.
+    ///
+    /// The dictionary of the frame is not part of the snapshot, only its id. A prefix passed to reset_with_prefix() is part of it
+    pub fn snapshot(&self) -> Result<Vec<u8>, SnapshotError> {
+        let state = match &self.state {
+            None => return Err(SnapshotError::NotYetInitialized),
+            Some(s) => s,
+        };
+        if state.corrupt_block.is_some() {
+            return Err(SnapshotError::CorruptBlock);
+        }
+
+        let mut w = SnapshotWriter::new();
+        let mut header = Vec::new();
+        state.frame.write_header(&mut header);
+        w.bytes(&header);
+        w.bool(state.frame_finished);
+        w.u64(state.block_counter as u64);
+        w.u64(state.bytes_read_counter);
+        w.option_u32(state.check_sum);
+        w.option_u32(state.using_dict);
+        w.bool(state.using_prefix);
+        if state.using_prefix {
+            let prefix = state.decoder_scratch.buffer.dict.as_ref();
+            let prefix = prefix.expect("set by reset_with_prefix");
+            w.bytes(&prefix.dict_content);
+        }
+        w.u64(self.stream_output);
+        w.u64(self.frame_counter);
+        snapshot::write_scratch(&mut w, &state.decoder_scratch);
+        Ok(w.finish())
+    }
+
+    /// Continues decoding a frame from a snapshot() of it. The source for decode_blocks() has to be positioned bytes_read_from_source()
+    /// bytes after the start of the frame. If the frame uses a dictionary, it has to be added to this decoder or provided by the dict_loader.
+    /// Like the frame, how many frames and bytes this decoder decoded before is restored from the snapshot.
+    ///
+    /// If restoring fails the decoder is left uninitialized
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        let result = self.restore_state(snapshot);
+        if result.is_err() {
+            self.state = None;
+        }
+        self.count_buffer_reallocations();
+        result
+    }
+
+    fn restore_state(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        let mut r = SnapshotReader::new(snapshot)?;
+        let mut header = r.bytes()?;
+        self.reset_state(&mut header)
+            .map_err(SnapshotError::FailedToRestoreFrameHeader)?;
+
+        let state = self.state.as_mut().expect("initialized by reset_state");
+        state.frame_finished = r.bool()?;
+        state.block_counter = r.u64()? as usize;
+        state.bytes_read_counter = r.u64()?;
+        state.check_sum = r.option_u32()?;
+        state.using_dict = r.option_u32()?;
+        state.using_prefix = r.bool()?;
+        let prefix = if state.using_prefix {
+            Some(r.bytes()?)
+        } else {
+            None
+        };
+        self.stream_output = r.u64()?;
+        self.frame_counter = r.u64()?;
+        snapshot::read_scratch(&mut r, &mut state.decoder_scratch)?;
+        r.finish()?;
+
+        // only the content is needed, the tables are part of the snapshot
+        let dict = match (prefix, state.using_dict) {
+            (Some(prefix), _) => Some(self.prefix_dict(prefix)),
+            (None, Some(id)) => {
+                if !ensure_dict(&mut self.dicts, &mut self.dict_loader, id) {
+                    return Err(SnapshotError::DictNotProvided { dict_id: id });
+                }
+                self.dicts.get(id).cloned()
+            }
+            (None, None) => None,
+        };
+        if let Some(state) = &mut self.state {
+            state.decoder_scratch.buffer.dict = dict;
+        }
+        Ok(())
+    }
+
+    /// How often this decoder had to allocate or grow one of its internal buffers, plus the Vecs returned by collect().
+    /// The buffers are kept between frames, so once the decoder has seen frames of a certain size, decoding more of them
+    /// with reset(), decode_blocks() and read() or collect_to_writer() does not allocate and this counter stays the same.
+    ///
+    /// This is not a count of all heap allocations. The buffers are only compared between calls, so a buffer that is
+    /// grown several times within one call counts once. Dictionaries parsed by reset_with_dict() and add_dict() are not
+    /// counted either. Add dictionaries once with add_dict() instead of passing them to reset_with_dict() for every frame.
+    /// reset_with_prefix() only copies a prefix that differs from the previous one, that copy is not counted either.
+    pub fn buffer_reallocations(&self) -> u64 {
+        self.buffer_reallocations
+    }
+
+    /// Counts the buffers that have been reallocated since the last call
+    fn count_buffer_reallocations(&mut self) {
+        let buffers = match &self.state {
+            Some(s) => s.decoder_scratch.buffers(),
+            None => [(0, 0); NUM_BUFFERS],
+        };
+        let changed = buffers
+            .iter()
+            .zip(self.last_buffers.iter())
+            .filter(|(now, before)| now != before)
+            .count();
+        self.buffer_reallocations += changed as u64;
+        self.last_buffers = buffers;
+    }
 }
 
 /// Read bytes from the decode_buffer that are no longer needed. While the frame is not yet finished
-/// this will retain window_size bytes, else it will drain it completely
+/// this will retain window_size bytes, else it will drain it completely. After a corrupt block all bytes are read, but the window is still retained
 impl Read for FrameDecoder {
     fn read(&mut self, target: &mut [u8]) -> Result<usize, Error> {
         let state = match &mut self.state {
@@ -1,40 +1,70 @@
 use crate::decoding::bit_reader::BitReader;
 use crate::decoding::bit_reader_reverse::BitReaderReversed;
-use core::mem;
-#[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
-#[cfg(feature = "alloc")]
-use alloc::string::String;
+use crate::errors::{FSEDecoderError, FSETableError, GetBitsError};
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+use core::mem;
 
+/// Owns the decoding table and the probabilities it was built from.
+/// The table building itself works on slices, see build_decoding_table() and read_probabilities()
+#[cfg(feature = "alloc")]
 #[derive(Clone)]
 pub struct FSETable {
     pub decode: Vec<Entry>, //used to decode symbols, and calculate the next state
 
     pub accuracy_log: u8,
     pub symbol_probablilities: Vec<i32>, //used while building the decode Vector
-    symbol_counter: Vec<u32>,
 }
 
+#[cfg(feature = "alloc")]
 impl Default for FSETable {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Where an FSE table is stored. Implemented by FSETable and by the tables of the WorkspaceDecoder,
+/// so both update their sequence tables with the same code
+pub trait FSETableStorage {
+    /// Reads a table description from the start of source and builds the table from it. Returns how many bytes were read
+    fn build_decoder(&mut self, source: &[u8], max_log: u8) -> Result<usize, FSETableError>;
+
+    /// Builds the table from a predefined distribution
+    fn build_from_probabilities(&mut self, acc_log: u8, probs: &[i32])
+        -> Result<(), FSETableError>;
+
+    /// 0 if no table has been built yet
+    fn accuracy_log(&self) -> u8;
+
+    fn decoder(&self) -> FSEDecoder<'_>;
+}
+
 pub struct FSEDecoder<'table> {
     pub state: usize,
-    table: &'table FSETable,
+    decode: &'table [Entry],
+    accuracy_log: u8,
 }
 
+/// Only integers and no padding, so tables of entries can be kept in a byte workspace, see WorkspaceDecoder
 #[derive(Copy, Clone)]
+#[repr(C)]
 pub struct Entry {
-    pub base_line: usize,
+    pub base_line: u16,
     pub num_bits: u8,
     pub symbol: u8,
 }
 
+impl Entry {
+    pub const EMPTY: Entry = Entry {
+        base_line: 0,
+        num_bits: 0,
+        symbol: 0,
+    };
+}
+
+/// No FSE distribution can have more symbols than this
+pub const MAX_SYMBOLS: usize = 256;
+
 const ACC_LOG_OFFSET: u8 = 5;
 
 const fn num_bits<T>() -> usize {
@@ -47,224 +77,353 @@ fn highest_bit_set(x: u32) -> u32 {
 }
 
 impl<'t> FSEDecoder<'t> {
-    pub fn new(table: &'t FSETable) -> FSEDecoder<'_> {
-        FSEDecoder { state: 0, table }
+    #[cfg(feature = "alloc")]
+    pub fn new(table: &'t FSETable) -> FSEDecoder<'t> {
+        FSEDecoder::from_decode_table(&table.decode, table.accuracy_log)
+    }
+
+    /// Uses a table built by build_decoding_table(). An accuracy_log of 0 marks the table as uninitialized
+    pub fn from_decode_table(decode: &'t [Entry], accuracy_log: u8) -> FSEDecoder<'t> {
+        FSEDecoder {
+            state: 0,
+            decode,
+            accuracy_log,
+        }
     }
 
     pub fn decode_symbol(&self) -> u8 {
-        self.table.decode[self.state].symbol
+        self.decode[self.state].symbol
     }
 
-    pub fn init_state(&mut self, bits: &mut BitReaderReversed<'_>) -> Result<(), String> {
-        if self.table.accuracy_log == 0 {
-            return Err("Tried to use an unitizialized table!".to_owned());
+    pub fn init_state(&mut self, bits: &mut BitReaderReversed<'_>) -> Result<(), FSEDecoderError> {
+        if self.accuracy_log == 0 {
+            return Err(FSEDecoderError::TableIsUninitialized);
         }
-        self.state = bits.get_bits(self.table.accuracy_log as usize)? as usize;
+        self.state = bits.get_bits(self.accuracy_log as usize)? as usize;
 
         Ok(())
     }
 
-    pub fn update_state(&mut self, bits: &mut BitReaderReversed<'_>) -> Result<(), String> {
-        let num_bits = self.table.decode[self.state].num_bits as usize;
+    pub fn update_state(&mut self, bits: &mut BitReaderReversed<'_>) -> Result<(), GetBitsError> {
+        let num_bits = self.decode[self.state].num_bits as usize;
         let add = bits.get_bits(num_bits)?;
-        let base_line = self.table.decode[self.state].base_line;
-        let new_state = base_line + add as usize;
-        assert!(new_state < self.table.decode.len());
+        let base_line = self.decode[self.state].base_line;
+        let new_state = base_line as usize + add as usize;
+        assert!(new_state < self.decode.len());
         self.state = new_state;
 
         Ok(())
     }
+
+    /// Like update_state but without any checks. The caller needs to refill the bitreader before,
+    /// a refill provides enough bits for three updates
+    #[inline(always)]
+    pub fn update_state_fast(&mut self, bits: &mut BitReaderReversed<'_>) {
+        let entry = self.decode[self.state];
+        let add = bits.get_bits_unchecked(entry.num_bits);
+        let new_state = entry.base_line as usize + add as usize;
+        debug_assert!(new_state < self.decode.len());
+        self.state = new_state;
+    }
 }
 
+#[cfg(feature = "alloc")]
 impl FSETable {
     pub fn new() -> FSETable {
         FSETable {
-            symbol_probablilities: Vec::with_capacity(256), //will never be more than 256 symbols because u8
-            symbol_counter: Vec::with_capacity(256), //will never be more than 256 symbols because u8
-            decode: Vec::new(),                      //depending on acc_log.
+            symbol_probablilities: Vec::with_capacity(MAX_SYMBOLS), //will never be more than 256 symbols because u8
+            decode: Vec::new(), //depending on acc_log.
             accuracy_log: 0,
         }
     }
 
     pub fn reset(&mut self) {
-        self.symbol_counter.clear();
         self.symbol_probablilities.clear();
         self.decode.clear();
         self.accuracy_log = 0;
     }
 
+    /// Like clone() but reuses the allocations of self
+    pub fn copy_from(&mut self, other: &FSETable) {
+        self.decode.clear();
+        self.decode.extend_from_slice(&other.decode);
+        self.symbol_probablilities.clear();
+        self.symbol_probablilities
+            .extend_from_slice(&other.symbol_probablilities);
+        self.accuracy_log = other.accuracy_log;
+    }
+
+    /// Address and capacity of the buffers this table owns, to check if they were reallocated
+    pub fn buffers(&self) -> [(usize, usize); 2] {
+        [
+            (self.decode.as_ptr() as usize, self.decode.capacity()),
+            (
+                self.symbol_probablilities.as_ptr() as usize,
+                self.symbol_probablilities.capacity(),
+            ),
+        ]
+    }
+
     //returns how many BYTEs (not bits) were read while building the decoder
-    pub fn build_decoder(&mut self, source: &[u8], max_log: u8) -> Result<usize, String> {
+    pub fn build_decoder(&mut self, source: &[u8], max_log: u8) -> Result<usize, FSETableError> {
         self.accuracy_log = 0;
 
-        let bytes_read = self.read_probabilities(source, max_log)?;
+        let mut probs = [0; MAX_SYMBOLS];
+        let (accuracy_log, num_symbols, bytes_read) =
+            read_probabilities(source, max_log, &mut probs)?;
+        self.symbol_probablilities.clear();
+        self.symbol_probablilities
+            .extend_from_slice(&probs[..num_symbols]);
+        self.accuracy_log = accuracy_log;
         self.build_decoding_table();
 
         Ok(bytes_read)
     }
 
-    pub fn build_from_probabilities(&mut self, acc_log: u8, probs: &[i32]) -> Result<(), String> {
+    pub fn build_from_probabilities(
+        &mut self,
+        acc_log: u8,
+        probs: &[i32],
+    ) -> Result<(), FSETableError> {
         if acc_log == 0 {
-            return Err("Acclog must be at least 1".to_owned());
+            return Err(FSETableError::AccLogIsZero);
         }
-        self.symbol_probablilities = probs.to_vec();
+        self.symbol_probablilities.clear();
+        self.symbol_probablilities.extend_from_slice(probs);
         self.accuracy_log = acc_log;
         self.build_decoding_table();
         Ok(())
     }
 
+    /// Builds the table again from the accuracy log and probabilities it was built from, e.g. when a snapshot of the decoder is restored.
+    /// An accuracy log of 0 leaves the table uninitialized. Unlike build_from_probabilities() the probabilities are checked
+    pub fn restore(
+        &mut self,
+        acc_log: u8,
+        max_log: u8,
+        probs: &[i32],
+    ) -> Result<(), FSETableError> {
+        if acc_log == 0 {
+            self.reset();
+            return Ok(());
+        }
+        if acc_log > max_log {
+            return Err(FSETableError::AccLogTooBig {
+                got: acc_log,
+                max: max_log,
+            });
+        }
+        if probs.len() > MAX_SYMBOLS {
+            return Err(FSETableError::TooManySymbols { got: probs.len() });
+        }
+        let expected_sum = 1 << acc_log;
+        let mut sum: u32 = 0;
+        for prob in probs {
+            if *prob < -1 {
+                return Err(FSETableError::ProbabilityCounterMismatch {
+                    got: sum,
+                    expected_sum,
+                });
+            }
+            sum = sum.saturating_add(if *prob == -1 { 1 } else { *prob as u32 });
+        }
+        if sum != expected_sum {
+            return Err(FSETableError::ProbabilityCounterMismatch {
+                got: sum,
+                expected_sum,
+            });
+        }
+        self.build_from_probabilities(acc_log, probs)
+    }
+
     fn build_decoding_table(&mut self) {
         self.decode.clear();
-
-        let table_size = 1 << self.accuracy_log;
-        if self.decode.len() < table_size {
-            self.decode.reserve(table_size - self.decode.len());
-        }
-        //fill with dummy entries
-        self.decode.resize(
-            table_size,
-            Entry {
-                base_line: 0,
-                num_bits: 0,
-                symbol: 0,
-            },
+        self.decode.resize(1 << self.accuracy_log, Entry::EMPTY);
+        build_decoding_table(
+            self.accuracy_log,
+            &self.symbol_probablilities,
+            &mut self.decode,
         );
+    }
+}
 
-        let mut negative_idx = table_size; //will point to the highest index with is already occupied by a negative-probability-symbol
+#[cfg(feature = "alloc")]
+impl FSETableStorage for FSETable {
+    fn build_decoder(&mut self, source: &[u8], max_log: u8) -> Result<usize, FSETableError> {
+        FSETable::build_decoder(self, source, max_log)
+    }
 
-        //first scan for all -1 probabilities and place them at the top of the table
-        for symbol in 0..self.symbol_probablilities.len() {
-            if self.symbol_probablilities[symbol] == -1 {
-                negative_idx -= 1;
-                let entry = &mut self.decode[negative_idx];
-                entry.symbol = symbol as u8;
-                entry.base_line = 0;
-                entry.num_bits = self.accuracy_log;
-            }
+    fn build_from_probabilities(
+        &mut self,
+        acc_log: u8,
+        probs: &[i32],
+    ) -> Result<(), FSETableError> {
+        FSETable::build_from_probabilities(self, acc_log, probs)
+    }
+
+    fn accuracy_log(&self) -> u8 {
+        self.accuracy_log
+    }
+
+    fn decoder(&self) -> FSEDecoder<'_> {
+        FSEDecoder::new(self)
+    }
+}
+
+/// Spreads the symbols over decode, which needs to have exactly 1 << accuracy_log entries.
+/// probs must not have more than MAX_SYMBOLS entries and must sum up to 1 << accuracy_log, like read_probabilities() makes sure
+pub fn build_decoding_table(accuracy_log: u8, probs: &[i32], decode: &mut [Entry]) {
+    let table_size = 1 << accuracy_log;
+    assert!(decode.len() == table_size);
+    // the baselines are smaller than the table size, so they fit into the u16 of the entries
+    assert!(accuracy_log <= 16);
+    assert!(probs.len() <= MAX_SYMBOLS);
+
+    let mut negative_idx = table_size; //will point to the highest index with is already occupied by a negative-probability-symbol
+
+    //first scan for all -1 probabilities and place them at the top of the table
+    for (symbol, prob) in probs.iter().enumerate() {
+        if *prob == -1 {
+            negative_idx -= 1;
+            let entry = &mut decode[negative_idx];
+            entry.symbol = symbol as u8;
+            entry.base_line = 0;
+            entry.num_bits = accuracy_log;
         }
+    }
 
-        //then place in a semi-random order all of the other symbols
-        let mut position = 0;
-        for idx in 0..self.symbol_probablilities.len() {
-            let symbol = idx as u8;
-            if self.symbol_probablilities[idx] <= 0 {
-                continue;
-            }
+    //then place in a semi-random order all of the other symbols
+    let mut position = 0;
+    for (idx, prob) in probs.iter().enumerate() {
+        let symbol = idx as u8;
+        if *prob <= 0 {
+            continue;
+        }
 
-            //for each probability point the symbol gets on slot
-            let prob = self.symbol_probablilities[idx];
-            for _ in 0..prob {
-                let entry = &mut self.decode[position];
-                entry.symbol = symbol;
+        //for each probability point the symbol gets on slot
+        for _ in 0..*prob {
+            let entry = &mut decode[position];
+            entry.symbol = symbol;
 
+            position = next_position(position, table_size);
+            while position >= negative_idx {
                 position = next_position(position, table_size);
-                while position >= negative_idx {
-                    position = next_position(position, table_size);
-                    //everything above negative_idx is already taken
-                }
+                //everything above negative_idx is already taken
             }
         }
+    }
 
-        // baselines and num_bits can only be caluclated when all symbols have been spread
-        self.symbol_counter.clear();
-        self.symbol_counter
-            .resize(self.symbol_probablilities.len(), 0);
-        for idx in 0..negative_idx {
-            let entry = &mut self.decode[idx];
-            let symbol = entry.symbol;
-            let prob = self.symbol_probablilities[symbol as usize];
+    // baselines and num_bits can only be caluclated when all symbols have been spread
+    let mut symbol_counter = [0u32; MAX_SYMBOLS];
+    for entry in &mut decode[..negative_idx] {
+        let symbol = entry.symbol;
+        let prob = probs[symbol as usize];
 
-            let symbol_count = self.symbol_counter[symbol as usize];
-            let (bl, nb) = calc_baseline_and_numbits(table_size as u32, prob as u32, symbol_count);
+        let symbol_count = symbol_counter[symbol as usize];
+        let (bl, nb) = calc_baseline_and_numbits(table_size as u32, prob as u32, symbol_count);
 
+        assert!(nb <= accuracy_log);
+        symbol_counter[symbol as usize] += 1;
 
-            assert!(nb <= self.accuracy_log);
-            self.symbol_counter[symbol as usize] += 1;
+        entry.base_line = bl as u16;
+        entry.num_bits = nb;
+    }
+}
 
-            entry.base_line = bl;
-            entry.num_bits = nb;
-        }
+/// Reads the probabilities of an FSE table description from the start of source into probs.
+/// Returns the accuracy log, the number of symbols with a probability and how many BYTEs (not bits) were read
+pub fn read_probabilities(
+    source: &[u8],
+    max_log: u8,
+    probs: &mut [i32; MAX_SYMBOLS],
+) -> Result<(u8, usize, usize), FSETableError> {
+    let mut num_symbols = 0;
+
+    let mut br = BitReader::new(source);
+    let accuracy_log = ACC_LOG_OFFSET + (br.get_bits(4)? as u8);
+    if accuracy_log > max_log {
+        return Err(FSETableError::AccLogTooBig {
+            got: accuracy_log,
+            max: max_log,
+        });
+    }
+    if accuracy_log == 0 {
+        return Err(FSETableError::AccLogIsZero);
     }
 
-    fn read_probabilities(&mut self, source: &[u8], max_log: u8) -> Result<usize, String> {
-        self.symbol_probablilities.clear(); //just clear, we will fill a probability for each entry anyways. No need to force new allocs here
+    let probablility_sum = 1 << accuracy_log;
+    let mut probability_counter = 0;
 
-        let mut br = BitReader::new(source);
-        self.accuracy_log = ACC_LOG_OFFSET + (br.get_bits(4)? as u8);
-        if self.accuracy_log > max_log {
-            return Err(format!(
-                "Found FSE acc_log: {} bigger than allowed maximum in this case: {}",
-                self.accuracy_log, max_log
-            ));
-        }
-        if self.accuracy_log == 0 {
-            return Err("Acclog must be at least 1".to_owned());
-        }
+    while probability_counter < probablility_sum {
+        let max_remaining_value = probablility_sum - probability_counter + 1;
+        let bits_to_read = highest_bit_set(max_remaining_value);
 
-        let probablility_sum = 1 << self.accuracy_log;
-        let mut probability_counter = 0;
+        let unchecked_value = br.get_bits(bits_to_read as usize)? as u32;
 
-        while probability_counter < probablility_sum {
-            let max_remaining_value = probablility_sum - probability_counter + 1;
-            let bits_to_read = highest_bit_set(max_remaining_value);
+        let low_threshold = ((1 << bits_to_read) - 1) - (max_remaining_value);
+        let mask = (1 << (bits_to_read - 1)) - 1;
+        let small_value = unchecked_value & mask;
 
-            let unchecked_value = br.get_bits(bits_to_read as usize)? as u32;
+        let value = if small_value < low_threshold {
+            br.return_bits(1);
+            small_value
+        } else if unchecked_value > mask {
+            unchecked_value - low_threshold
+        } else {
+            unchecked_value
+        };
 
-            let low_threshold = ((1 << bits_to_read) - 1) - (max_remaining_value);
-            let mask = (1 << (bits_to_read - 1)) - 1;
-            let small_value = unchecked_value & mask;
+        let prob = (value as i32) - 1;
 
-            let value = if small_value < low_threshold {
-                br.return_bits(1);
-                small_value
-            } else if unchecked_value > mask {
-                unchecked_value - low_threshold
+        if num_symbols >= MAX_SYMBOLS {
+            return Err(FSETableError::TooManySymbols {
+                got: num_symbols + 1,
+            });
+        }
+        probs[num_symbols] = prob;
+        num_symbols += 1;
+        if prob != 0 {
+            if prob > 0 {
+                probability_counter += prob as u32;
             } else {
-                unchecked_value
-            };
-
-            let prob = (value as i32) - 1;
-
-            self.symbol_probablilities.push(prob);
-            if prob != 0 {
-                if prob > 0 {
-                    probability_counter += prob as u32;
-                } else {
-                    // probability -1 counts as 1
-                    assert!(prob == -1);
-                    probability_counter += 1;
+                // probability -1 counts as 1
+                assert!(prob == -1);
+                probability_counter += 1;
+            }
+        } else {
+            //fast skip further zero probabilities
+            loop {
+                let skip_amount = br.get_bits(2)? as usize;
+
+                if num_symbols + skip_amount > MAX_SYMBOLS {
+                    return Err(FSETableError::TooManySymbols {
+                        got: num_symbols + skip_amount,
+                    });
                 }
-            } else {
-                //fast skip further zero probabilities
-                loop {
-                    let skip_amount = br.get_bits(2)? as usize;
-
-                    self.symbol_probablilities
-                        .resize(self.symbol_probablilities.len() + skip_amount, 0);
-                    if skip_amount != 3 {
-                        break;
-                    }
+                for prob in &mut probs[num_symbols..num_symbols + skip_amount] {
+                    *prob = 0;
+                }
+                num_symbols += skip_amount;
+                if skip_amount != 3 {
+                    break;
                 }
             }
         }
+    }
 
-        if probability_counter != probablility_sum {
-            return Err(format!("The counter: {} exceeded the expected sum: {}. This means an error or corrupted data \n {:?}", probability_counter, probablility_sum, self.symbol_probablilities));
-        }
-        if self.symbol_probablilities.len() > 256 {
-            return Err(format!(
-                "There are too many symbols in this distribution: {}. Max: 256",
-                self.symbol_probablilities.len()
-            ));
-        }
-
-        let bytes_read = if br.bits_read() % 8 == 0 {
-            br.bits_read() / 8
-        } else {
-            (br.bits_read() / 8) + 1
-        };
-        Ok(bytes_read)
+    if probability_counter != probablility_sum {
+        return Err(FSETableError::ProbabilityCounterMismatch {
+            got: probability_counter,
+            expected_sum: probablility_sum,
+        });
     }
+
+    let bytes_read = if br.bits_read() % 8 == 0 {
+        br.bits_read() / 8
+    } else {
+        (br.bits_read() / 8) + 1
+    };
+    Ok((accuracy_log, num_symbols, bytes_read))
 }
 
 //utility functions for building the decoding table from probabilities
@@ -1,39 +1,83 @@
 use crate::decoding::bit_reader_reverse::BitReaderReversed;
+use crate::errors::{GetBitsError, HuffmanTableError};
+use crate::fse;
 use crate::fse::FSEDecoder;
-use crate::fse::FSETable;
-use core::mem;
-#[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
-#[cfg(feature = "alloc")]
-use alloc::string::String;
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+use core::mem;
 
+/// Owns the decoding tables. The table building itself works on slices, see read_weights() and build_decoding_table()
+#[cfg(feature = "alloc")]
 #[derive(Clone)]
 pub struct HuffmanTable {
-    decode: Vec<Entry>,
+    pub decode: Vec<Entry>,
+    /// Indexed by the next DOUBLE_TABLE_LOG bits, entries can hold two symbols.
+    /// Empty if no two codes fit into DOUBLE_TABLE_LOG bits or build_double_table() has not been called for this table
+    pub decode_double: Vec<DoubleEntry>,
 
-    weights: Vec<u8>,
     pub max_num_bits: u8,
-    bits: Vec<u8>,
-    bit_ranks: Vec<u32>,
-    rank_indexes: Vec<usize>,
 
-    fse_table: FSETable,
+    /// Used to decode the FSE compressed weights
+    fse_decode: Vec<fse::Entry>,
+}
+
+/// The tables needed to decode huffman coded literals, borrowed from a HuffmanTable or from a workspace
+#[derive(Copy, Clone)]
+pub struct HuffmanDecodeTables<'table> {
+    pub decode: &'table [Entry],
+    /// Either empty or 1 << DOUBLE_TABLE_LOG entries
+    pub decode_double: &'table [DoubleEntry],
+    pub max_num_bits: u8,
+}
+
+/// Where a huffman table is stored. Implemented by HuffmanTable and by the tables of the WorkspaceDecoder,
+/// so both decode their literals with decode_literals_into()
+pub trait HuffmanTableStorage {
+    /// Reads a table description from the start of source and builds the table from it. Returns how many bytes were read
+    fn build_decoder(&mut self, source: &[u8]) -> Result<u32, HuffmanTableError>;
+
+    /// The tables to decode num_literals literals with. None if no table has been built yet
+    fn decode_tables(&mut self, num_literals: usize) -> Option<HuffmanDecodeTables<'_>>;
 }
 
 pub struct HuffmanDecoder<'table> {
-    table: &'table HuffmanTable,
+    decode: &'table [Entry],
+    max_num_bits: u8,
     pub state: u64,
 }
 
+/// Only integers and no padding, so tables of entries can be kept in a byte workspace, see WorkspaceDecoder
 #[derive(Copy, Clone)]
+#[repr(C)]
 pub struct Entry {
-    symbol: u8,
-    num_bits: u8,
+    pub symbol: u8,
+    pub num_bits: u8,
 }
 
-const MAX_MAX_NUM_BITS: u8 = 11;
+impl Entry {
+    pub const EMPTY: Entry = Entry {
+        symbol: 0,
+        num_bits: 0,
+    };
+}
+
+#[derive(Copy, Clone)]
+pub struct DoubleEntry {
+    pub symbols: [u8; 2],
+    pub num_bits: u8,
+    pub has_second_symbol: bool,
+}
+
+pub const MAX_MAX_NUM_BITS: u8 = 11;
+/// The double table is always indexed with this many bits, independent of max_num_bits
+pub const DOUBLE_TABLE_LOG: u8 = 11;
+/// Building the double table fills all 1 << DOUBLE_TABLE_LOG entries. For fewer literals than this
+/// that costs more than decoding them with the double table saves
+pub const DOUBLE_TABLE_MIN_LITERALS: usize = 2 << DOUBLE_TABLE_LOG;
+/// The FSE table used to decode the weights never has a bigger accuracy log than this
+pub const WEIGHTS_MAX_ACC_LOG: u8 = 6;
+/// Weights are only transmitted for up to 255 symbols, the weight of the last symbol is implied
+pub const MAX_WEIGHTS: usize = 255;
 
 const fn num_bits<T>() -> usize {
     mem::size_of::<T>() * 8
@@ -45,304 +89,449 @@ fn highest_bit_set(x: u32) -> u32 {
 }
 
 impl<'t> HuffmanDecoder<'t> {
+    #[cfg(feature = "alloc")]
     pub fn new(table: &'t HuffmanTable) -> HuffmanDecoder<'t> {
-        HuffmanDecoder { table, state: 0 }
+        HuffmanDecoder::from_decode_table(&table.decode, table.max_num_bits)
     }
 
+    /// Uses a table built by build_decoding_table(). decode needs to have exactly 1 << max_num_bits entries
+    pub fn from_decode_table(decode: &'t [Entry], max_num_bits: u8) -> HuffmanDecoder<'t> {
+        debug_assert!(decode.len() == 1 << max_num_bits);
+        HuffmanDecoder {
+            decode,
+            max_num_bits,
+            state: 0,
+        }
+    }
+
+    #[cfg(feature = "alloc")]
     pub fn reset(mut self, new_table: Option<&'t HuffmanTable>) {
         self.state = 0;
         if let Some(next_table) = new_table {
-            self.table = next_table;
+            self.decode = &next_table.decode;
+            self.max_num_bits = next_table.max_num_bits;
         }
     }
 
     pub fn decode_symbol(&mut self) -> u8 {
-        self.table.decode[self.state as usize].symbol
+        self.decode[self.state as usize].symbol
     }
 
-    pub fn init_state(&mut self, br: &mut BitReaderReversed<'_>) -> Result<u8, String> {
-        let num_bits = self.table.max_num_bits;
+    pub fn init_state(&mut self, br: &mut BitReaderReversed<'_>) -> Result<u8, GetBitsError> {
+        let num_bits = self.max_num_bits;
         let new_bits = br.get_bits(num_bits as usize)?;
         self.state = new_bits;
         Ok(num_bits)
     }
 
-    pub fn next_state(&mut self, br: &mut BitReaderReversed<'_>) -> Result<u8, String> {
-        let num_bits = self.table.decode[self.state as usize].num_bits;
+    pub fn next_state(&mut self, br: &mut BitReaderReversed<'_>) -> Result<u8, GetBitsError> {
+        let num_bits = self.decode[self.state as usize].num_bits;
         let new_bits = br.get_bits(num_bits as usize)?;
         self.state <<= num_bits;
-        self.state &= self.table.decode.len() as u64 - 1;
+        self.state &= self.decode.len() as u64 - 1;
         self.state |= new_bits;
         Ok(num_bits)
     }
+
+    /// Like next_state but without any checks. The caller needs to refill the bitreader before,
+    /// a refill provides enough bits for four symbols
+    #[inline(always)]
+    pub fn next_state_fast(&mut self, br: &mut BitReaderReversed<'_>) -> u8 {
+        let num_bits = self.decode[self.state as usize].num_bits;
+        let new_bits = br.get_bits_unchecked(num_bits);
+        self.state <<= num_bits;
+        self.state &= self.decode.len() as u64 - 1;
+        self.state |= new_bits;
+        num_bits
+    }
 }
 
+#[cfg(feature = "alloc")]
 impl Default for HuffmanTable {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(feature = "alloc")]
 impl HuffmanTable {
     pub fn new() -> HuffmanTable {
         HuffmanTable {
             decode: Vec::new(),
+            decode_double: Vec::new(),
 
-            weights: Vec::with_capacity(256),
             max_num_bits: 0,
-            bits: Vec::with_capacity(256),
-            bit_ranks: Vec::with_capacity(11),
-            rank_indexes: Vec::with_capacity(11),
-            fse_table: FSETable::new(),
+            fse_decode: Vec::new(),
         }
     }
 
     pub fn reset(&mut self) {
         self.decode.clear();
-        self.weights.clear();
+        self.decode_double.clear();
         self.max_num_bits = 0;
-        self.bits.clear();
-        self.bit_ranks.clear();
-        self.rank_indexes.clear();
-        self.fse_table.reset();
+        self.fse_decode.clear();
     }
 
-    pub fn build_decoder(&mut self, source: &[u8]) -> Result<u32, String> {
+    /// Like clone() but reuses the allocations of self
+    pub fn copy_from(&mut self, other: &HuffmanTable) {
         self.decode.clear();
+        self.decode.extend_from_slice(&other.decode);
+        self.decode_double.clear();
+        self.decode_double.extend_from_slice(&other.decode_double);
+        self.max_num_bits = other.max_num_bits;
+        // fse_decode is only needed while building the table
+    }
+
+    /// Address and capacity of the buffers this table owns, to check if they were reallocated
+    pub fn buffers(&self) -> [(usize, usize); 3] {
+        [
+            (self.decode.as_ptr() as usize, self.decode.capacity()),
+            (
+                self.decode_double.as_ptr() as usize,
+                self.decode_double.capacity(),
+            ),
+            (
+                self.fse_decode.as_ptr() as usize,
+                self.fse_decode.capacity(),
+            ),
+        ]
+    }
 
-        let bytes_used = self.read_weights(source)?;
-        self.build_table_from_weights()?;
+    /// Whether decode_double can be used with this table
+    pub fn has_double_symbols(&self) -> bool {
+        !self.decode_double.is_empty()
+    }
+
+    pub fn build_decoder(&mut self, source: &[u8]) -> Result<u32, HuffmanTableError> {
+        self.decode.clear();
+        self.decode_double.clear();
+        self.max_num_bits = 0;
+
+        self.fse_decode
+            .resize(1 << WEIGHTS_MAX_ACC_LOG, fse::Entry::EMPTY);
+        let mut weights = [0; MAX_WEIGHTS];
+        let (num_weights, bytes_used) = read_weights(source, &mut self.fse_decode, &mut weights)?;
+
+        self.build_from_weights(&weights[..num_weights])?;
         Ok(bytes_used)
     }
 
-    fn read_weights(&mut self, source: &[u8]) -> Result<u32, String> {
-        if source.is_empty() {
-            return Err("Source needs to have at least one byte".to_owned());
-        }
-        let header = source[0];
-        let mut bits_read = 8;
-
-        match header {
-            0..=127 => {
-                let fse_stream = &source[1..];
-                if header as usize > fse_stream.len() {
-                    return Err(format!("Header says there should be {} bytes for the weights but there are only {} bytes in the stream", header, fse_stream.len()));
-                }
-                //fse decompress weights
-                let bytes_used_by_fse_header = self
-                    .fse_table
-                    .build_decoder(fse_stream, /*TODO find actual max*/ 100)?;
+    /// Builds the table from the weights as they are read by read_weights(), the weight of the last symbol is implied
+    pub fn build_from_weights(&mut self, weights: &[u8]) -> Result<(), HuffmanTableError> {
+        self.decode.clear();
+        self.decode_double.clear();
+        self.max_num_bits = 0;
 
-                if bytes_used_by_fse_header > header as usize {
-                    return Err(format!("FSE table used more bytes: {} than were meant to be used for the whole stream of huffman weights", bytes_used_by_fse_header));
-                }
+        self.decode.resize(1 << MAX_MAX_NUM_BITS, Entry::EMPTY);
+        let max_num_bits = build_decoding_table(weights, &mut self.decode)?;
+        self.max_num_bits = max_num_bits;
+        self.decode.truncate(1 << self.max_num_bits);
+        Ok(())
+    }
+
+    /// Writes the weights build_from_weights() needs to build this table again and returns how many there are.
+    /// Returns 0 if the table has not been built
+    pub fn weights(&self, weights: &mut [u8; MAX_WEIGHTS + 1]) -> usize {
+        *weights = [0; MAX_WEIGHTS + 1];
+        if self.max_num_bits == 0 {
+            return 0;
+        }
+        let mut num_symbols = 0;
+        for entry in &self.decode {
+            weights[entry.symbol as usize] = self.max_num_bits + 1 - entry.num_bits;
+            num_symbols = core::cmp::max(num_symbols, entry.symbol as usize + 1);
+        }
+        // the weight of the last symbol is implied
+        num_symbols - 1
+    }
 
-                let mut dec1 = FSEDecoder::new(&self.fse_table);
-                let mut dec2 = FSEDecoder::new(&self.fse_table);
+    /// The double table is indexed with DOUBLE_TABLE_LOG bits which may be more than max_num_bits, so two short codes can fit
+    /// into one entry. An entry holds a second symbol if its code fits into the bits the first code leaves over.
+    /// If not even the two shortest codes fit the table stays empty.
+    /// The table is not built by build_decoder() because it only pays off for enough literals, see DOUBLE_TABLE_MIN_LITERALS
+    pub fn build_double_table(&mut self) {
+        if self.has_double_symbols() || self.max_num_bits == 0 {
+            return;
+        }
 
-                let compressed_start = bytes_used_by_fse_header;
-                let compressed_length = header as usize - bytes_used_by_fse_header;
+        // every entry of the table is used, so this finds the shortest code
+        let min_bits = self.decode.iter().map(|e| e.num_bits).min();
+        match min_bits {
+            Some(min_bits) if 2 * min_bits <= DOUBLE_TABLE_LOG => {}
+            _ => return,
+        }
 
-                let compressed_weights = &fse_stream[compressed_start..];
-                if compressed_weights.len() < compressed_length {
-                    return Err(format!(
-                        "Not enough bytes in stream to decompress weights. Is: {}, Should be: {}",
-                        compressed_weights.len(),
-                        compressed_length
-                    ));
-                }
-                let compressed_weights = &compressed_weights[..compressed_length];
-                let mut br = BitReaderReversed::new(compressed_weights);
-
-                bits_read += (bytes_used_by_fse_header + compressed_length) * 8;
-
-                //skip the 0 padding at the end of the last byte of the bit stream and throw away the first 1 found
-                let mut skipped_bits = 0;
-                loop {
-                    let val = br.get_bits(1)?;
-                    skipped_bits += 1;
-                    if val == 1 || skipped_bits > 8 {
-                        break;
-                    }
+        let mask = (1 << DOUBLE_TABLE_LOG) - 1;
+        // the single table only looks at the highest max_num_bits bits
+        let single_shift = DOUBLE_TABLE_LOG - self.max_num_bits;
+        for idx in 0..(1usize << DOUBLE_TABLE_LOG) {
+            let first = self.decode[idx >> single_shift];
+            // the bits after the first code are known for the highest DOUBLE_TABLE_LOG - first.num_bits bits of this index
+            let second = self.decode[((idx << first.num_bits) & mask) >> single_shift];
+            let entry = if first.num_bits + second.num_bits <= DOUBLE_TABLE_LOG {
+                DoubleEntry {
+                    symbols: [first.symbol, second.symbol],
+                    num_bits: first.num_bits + second.num_bits,
+                    has_second_symbol: true,
                 }
-                if skipped_bits > 8 {
-                    //if more than 7 bits are 0, this is not the correct end of the bitstream. Either a bug or corrupted data
-                    return Err(format!("Padding at the end of the sequence_section was more than a byte long: {}. Probably cause by data corruption", skipped_bits));
+            } else {
+                DoubleEntry {
+                    symbols: [first.symbol, 0],
+                    num_bits: first.num_bits,
+                    has_second_symbol: false,
                 }
+            };
+            self.decode_double.push(entry);
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl HuffmanTableStorage for HuffmanTable {
+    fn build_decoder(&mut self, source: &[u8]) -> Result<u32, HuffmanTableError> {
+        HuffmanTable::build_decoder(self, source)
+    }
 
-                dec1.init_state(&mut br)?;
-                dec2.init_state(&mut br)?;
-
-                self.weights.clear();
-
-                loop {
-                    let w = dec1.decode_symbol();
-                    self.weights.push(w);
-                    dec1.update_state(&mut br)?;
-
-                    if br.bits_remaining() <= -1 {
-                        //collect final states
-                        self.weights.push(dec2.decode_symbol());
-                        break;
-                    }
-
-                    let w = dec2.decode_symbol();
-                    self.weights.push(w);
-                    dec2.update_state(&mut br)?;
-
-                    if br.bits_remaining() <= -1 {
-                        //collect final states
-                        self.weights.push(dec1.decode_symbol());
-                        break;
-                    }
-                    //maximum number of weights is 255 because we use u8 symbols and the last weight is infered from the sum of all others
-                    if self.weights.len() > 255 {
-                        return Err(
-                            "More than 255 weights decoded. Stream is probably corrupted"
-                                .to_owned(),
-                        );
-                    }
+    fn decode_tables(&mut self, num_literals: usize) -> Option<HuffmanDecodeTables<'_>> {
+        if self.max_num_bits == 0 {
+            return None;
+        }
+        if num_literals >= DOUBLE_TABLE_MIN_LITERALS {
+            self.build_double_table();
+        }
+        Some(HuffmanDecodeTables {
+            decode: &self.decode,
+            decode_double: &self.decode_double,
+            max_num_bits: self.max_num_bits,
+        })
+    }
+}
+
+/// Reads the weights of a huffman table description from the start of source into weights.
+/// fse_decode is used to decode FSE compressed weights and needs at least 1 << WEIGHTS_MAX_ACC_LOG entries.
+/// Returns the number of weights and how many BYTEs (not bits) were read
+pub fn read_weights(
+    source: &[u8],
+    fse_decode: &mut [fse::Entry],
+    weights: &mut [u8; MAX_WEIGHTS],
+) -> Result<(usize, u32), HuffmanTableError> {
+    if source.is_empty() {
+        return Err(HuffmanTableError::SourceIsEmpty);
+    }
+    let header = source[0];
+    let mut bits_read = 8;
+    let mut num_weights = 0;
+
+    match header {
+        0..=127 => {
+            let fse_stream = &source[1..];
+            if header as usize > fse_stream.len() {
+                return Err(HuffmanTableError::NotEnoughBytesForWeights {
+                    got_bytes: fse_stream.len(),
+                    expected_bytes: header,
+                });
+            }
+            //fse decompress weights
+            let mut probs = [0; fse::MAX_SYMBOLS];
+            let (acc_log, num_symbols, bytes_used_by_fse_header) =
+                fse::read_probabilities(fse_stream, WEIGHTS_MAX_ACC_LOG, &mut probs)?;
+
+            if bytes_used_by_fse_header > header as usize {
+                return Err(HuffmanTableError::FSETableUsedTooManyBytes {
+                    used: bytes_used_by_fse_header,
+                    available_bytes: header,
+                });
+            }
+
+            let fse_decode = &mut fse_decode[..1 << acc_log];
+            fse::build_decoding_table(acc_log, &probs[..num_symbols], fse_decode);
+
+            let mut dec1 = FSEDecoder::from_decode_table(fse_decode, acc_log);
+            let mut dec2 = FSEDecoder::from_decode_table(fse_decode, acc_log);
+
+            let compressed_start = bytes_used_by_fse_header;
+            let compressed_length = header as usize - bytes_used_by_fse_header;
+
+            let compressed_weights = &fse_stream[compressed_start..];
+            if compressed_weights.len() < compressed_length {
+                return Err(HuffmanTableError::NotEnoughBytesToDecompressWeights {
+                    have: compressed_weights.len(),
+                    need: compressed_length,
+                });
+            }
+            let compressed_weights = &compressed_weights[..compressed_length];
+            let mut br = BitReaderReversed::new(compressed_weights);
+
+            bits_read += (bytes_used_by_fse_header + compressed_length) * 8;
+
+            //skip the 0 padding at the end of the last byte of the bit stream and throw away the first 1 found
+            let mut skipped_bits = 0;
+            loop {
+                let val = br.get_bits(1)?;
+                skipped_bits += 1;
+                if val == 1 || skipped_bits > 8 {
+                    break;
                 }
             }
-            _ => {
-                // weights are directly encoded
-                let weights_raw = &source[1..];
-                let num_weights = header - 127;
-                self.weights.resize(num_weights as usize, 0);
-
-                let bytes_needed = if num_weights % 2 == 0 {
-                    num_weights as usize / 2
-                } else {
-                    (num_weights as usize / 2) + 1
-                };
-
-                if weights_raw.len() < bytes_needed {
-                    return Err(format!(
-                        "Source needs to have at least {} bytes",
-                        bytes_needed
-                    ));
+            if skipped_bits > 8 {
+                //if more than 7 bits are 0, this is not the correct end of the bitstream. Either a bug or corrupted data
+                return Err(HuffmanTableError::ExtraPadding { skipped_bits });
+            }
+
+            dec1.init_state(&mut br)?;
+            dec2.init_state(&mut br)?;
+
+            //maximum number of weights is 255 because we use u8 symbols and the last weight is infered from the sum of all others
+            let mut push_weight = |w: u8| {
+                if num_weights >= MAX_WEIGHTS {
+                    return Err(HuffmanTableError::TooManyWeights {
+                        got: num_weights + 1,
+                    });
                 }
+                weights[num_weights] = w;
+                num_weights += 1;
+                Ok(())
+            };
+
+            loop {
+                push_weight(dec1.decode_symbol())?;
+                dec1.update_state(&mut br)?;
+
+                if br.bits_remaining() <= -1 {
+                    //collect final states
+                    push_weight(dec2.decode_symbol())?;
+                    break;
+                }
+
+                push_weight(dec2.decode_symbol())?;
+                dec2.update_state(&mut br)?;
 
-                for idx in 0..num_weights {
-                    if idx % 2 == 0 {
-                        self.weights[idx as usize] = weights_raw[idx as usize / 2] >> 4;
-                    } else {
-                        self.weights[idx as usize] = weights_raw[idx as usize / 2] & 0xF;
-                    }
-                    bits_read += 4;
+                if br.bits_remaining() <= -1 {
+                    //collect final states
+                    push_weight(dec1.decode_symbol())?;
+                    break;
                 }
             }
         }
+        _ => {
+            // weights are directly encoded
+            let weights_raw = &source[1..];
+            num_weights = header as usize - 127;
 
-        let bytes_read = if bits_read % 8 == 0 {
-            bits_read / 8
-        } else {
-            (bits_read / 8) + 1
-        };
-        Ok(bytes_read as u32)
-    }
+            let bytes_needed = if num_weights % 2 == 0 {
+                num_weights / 2
+            } else {
+                (num_weights / 2) + 1
+            };
 
-    fn build_table_from_weights(&mut self) -> Result<(), String> {
-        self.bits.clear();
-        self.bits.resize(self.weights.len() + 1, 0);
-
-        let mut weight_sum: u32 = 0;
-        for w in &self.weights {
-            if *w > MAX_MAX_NUM_BITS {
-                return Err(format!(
-                    "Cant have weight: {} bigger than max_num_bits: {}",
-                    *w, MAX_MAX_NUM_BITS
-                ));
+            if weights_raw.len() < bytes_needed {
+                return Err(HuffmanTableError::NotEnoughBytesInSource {
+                    got: weights_raw.len(),
+                    need: bytes_needed,
+                });
             }
-            weight_sum += if *w > 0 { 1_u32 << (*w - 1) } else { 0 };
-        }
 
-        if weight_sum == 0 {
-            return Err("Cant build huffman table without any weights".to_owned());
+            for idx in 0..num_weights {
+                if idx % 2 == 0 {
+                    weights[idx] = weights_raw[idx / 2] >> 4;
+                } else {
+                    weights[idx] = weights_raw[idx / 2] & 0xF;
+                }
+                bits_read += 4;
+            }
         }
+    }
 
-        let max_bits = highest_bit_set(weight_sum) as u8;
-        let left_over = (1 << max_bits) - weight_sum;
+    let bytes_read = if bits_read % 8 == 0 {
+        bits_read / 8
+    } else {
+        (bits_read / 8) + 1
+    };
+    Ok((num_weights, bytes_read as u32))
+}
 
-        //left_over must be power of two
-        if left_over & (left_over - 1) != 0 {
-            return Err(format!(
-                "Leftover must be power of two but is: {}",
-                left_over
-            ));
+/// Builds the decoding table for the weights read by read_weights() and returns max_num_bits.
+/// decode needs at least 1 << MAX_MAX_NUM_BITS entries, only the first 1 << max_num_bits are used
+pub fn build_decoding_table(weights: &[u8], decode: &mut [Entry]) -> Result<u8, HuffmanTableError> {
+    assert!(weights.len() <= MAX_WEIGHTS);
+    let mut bits = [0u8; MAX_WEIGHTS + 1];
+    let bits = &mut bits[..weights.len() + 1];
+
+    let mut weight_sum: u32 = 0;
+    for w in weights {
+        if *w > MAX_MAX_NUM_BITS {
+            return Err(HuffmanTableError::WeightBiggerThanMaxNumBits { got: *w });
         }
+        weight_sum += if *w > 0 { 1_u32 << (*w - 1) } else { 0 };
+    }
 
-        let last_weight = highest_bit_set(left_over) as u8;
+    if weight_sum == 0 {
+        return Err(HuffmanTableError::MissingWeights);
+    }
 
-        for symbol in 0..self.weights.len() {
-            let bits = if self.weights[symbol] > 0 {
-                max_bits + 1 - self.weights[symbol]
-            } else {
-                0
-            };
-            self.bits[symbol] = bits;
-        }
+    let max_bits = highest_bit_set(weight_sum) as u8;
+    let left_over = (1 << max_bits) - weight_sum;
 
-        self.bits[self.weights.len()] = max_bits + 1 - last_weight;
-        self.max_num_bits = max_bits;
+    //left_over must be power of two
+    if left_over & (left_over - 1) != 0 {
+        return Err(HuffmanTableError::LeftoverIsNotAPowerOf2 { got: left_over });
+    }
 
-        if max_bits > MAX_MAX_NUM_BITS {
-            return Err(format!(
-                "max_bits derived from weights is: {} should be lower than: {} ",
-                max_bits, MAX_MAX_NUM_BITS
-            ));
-        }
+    let last_weight = highest_bit_set(left_over) as u8;
 
-        self.bit_ranks.clear();
-        self.bit_ranks.resize((max_bits + 1) as usize, 0);
-        for num_bits in &self.bits {
-            self.bit_ranks[(*num_bits) as usize] += 1;
-        }
+    for symbol in 0..weights.len() {
+        let bits_for_symbol = if weights[symbol] > 0 {
+            max_bits + 1 - weights[symbol]
+        } else {
+            0
+        };
+        bits[symbol] = bits_for_symbol;
+    }
 
-        //fill with dummy symbols
-        self.decode.resize(
-            1 << self.max_num_bits,
-            Entry {
-                symbol: 0,
-                num_bits: 0,
-            },
-        );
-
-        //starting codes for each rank
-        self.rank_indexes.clear();
-        self.rank_indexes.resize((max_bits + 1) as usize, 0);
-
-        self.rank_indexes[max_bits as usize] = 0;
-        for bits in (1..self.rank_indexes.len() as u8).rev() {
-            self.rank_indexes[bits as usize - 1] = self.rank_indexes[bits as usize]
-                + self.bit_ranks[bits as usize] as usize * (1 << (max_bits - bits));
-        }
+    bits[weights.len()] = max_bits + 1 - last_weight;
 
-        assert!(
-            self.rank_indexes[0] == self.decode.len(),
-            format!(
-                "rank_idx[0]: {} should be: {}",
-                self.rank_indexes[0],
-                self.decode.len()
-            )
-        );
-
-        for symbol in 0..self.bits.len() {
-            let bits_for_symbol = self.bits[symbol];
-            if bits_for_symbol != 0 {
-                // allocate code for the symbol and set in the table
-                // a code ignores all max_bits - bits[symbol] bits, so it gets
-                // a range that spans all of those in the decoding table
-                let base_idx = self.rank_indexes[bits_for_symbol as usize];
-                let len = 1 << (max_bits - bits_for_symbol);
-                self.rank_indexes[bits_for_symbol as usize] += len;
-                for idx in 0..len {
-                    self.decode[base_idx + idx].symbol = symbol as u8;
-                    self.decode[base_idx + idx].num_bits = bits_for_symbol;
-                }
+    if max_bits > MAX_MAX_NUM_BITS {
+        return Err(HuffmanTableError::MaxBitsTooHigh { got: max_bits });
+    }
+
+    let mut bit_ranks = [0u32; MAX_MAX_NUM_BITS as usize + 1];
+    for num_bits in bits.iter() {
+        bit_ranks[(*num_bits) as usize] += 1;
+    }
+
+    let decode = &mut decode[..1 << max_bits];
+
+    //starting codes for each rank
+    let mut rank_indexes = [0usize; MAX_MAX_NUM_BITS as usize + 1];
+    let rank_indexes = &mut rank_indexes[..(max_bits + 1) as usize];
+
+    rank_indexes[max_bits as usize] = 0;
+    for bits in (1..rank_indexes.len() as u8).rev() {
+        rank_indexes[bits as usize - 1] = rank_indexes[bits as usize]
+            + bit_ranks[bits as usize] as usize * (1 << (max_bits - bits));
+    }
+
+    assert!(
+        rank_indexes[0] == decode.len(),
+        "rank_idx[0]: {} should be: {}",
+        rank_indexes[0],
+        decode.len()
+    );
+
+    for (symbol, &bits_for_symbol) in bits.iter().enumerate() {
+        if bits_for_symbol != 0 {
+            // allocate code for the symbol and set in the table
+            // a code ignores all max_bits - bits[symbol] bits, so it gets
+            // a range that spans all of those in the decoding table
+            let base_idx = rank_indexes[bits_for_symbol as usize];
+            let len = 1 << (max_bits - bits_for_symbol);
+            rank_indexes[bits_for_symbol as usize] += len;
+            for entry in &mut decode[base_idx..base_idx + len] {
+                entry.symbol = symbol as u8;
+                entry.num_bits = bits_for_symbol;
             }
         }
-
-        Ok(())
     }
+
+    Ok(max_bits)
 }
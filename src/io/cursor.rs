@@ -45,6 +45,18 @@ impl<T: AsRef<[u8]>> Read for Cursor<T> {
     }
 }
 
+impl<T: AsRef<[u8]>> BufRead for Cursor<T> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        let slice = self.inner.as_ref();
+        let start = core::cmp::min(self.pos, slice.len() as u64) as usize;
+        Ok(&slice[start..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
+}
+
 impl<T: AsRef<[u8]>> Seek for Cursor<T> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
         match pos {
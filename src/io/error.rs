@@ -10,6 +10,12 @@ impl fmt::Debug for Error {
     }
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.repr, f)
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum Repr {
     Simple(ErrorKind),
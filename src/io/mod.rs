@@ -39,7 +39,7 @@ pub trait Read {
         ))
     }
 
-    fn bytes(&mut self) -> Bytes<Self>
+    fn bytes(&mut self) -> Bytes<'_, Self>
     where
         Self: Sized,
     {
@@ -47,6 +47,57 @@ pub trait Read {
     }
 }
 
+#[cfg(not(feature = "std"))]
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let amount = core::cmp::min(buf.len(), self.len());
+        let (a, b) = self.split_at(amount);
+        buf[..amount].copy_from_slice(a);
+        *self = b;
+        Ok(amount)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<R: Read + ?Sized> Read for &mut R {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+pub use std::io::BufRead;
+
+/// A simplified version of [std::io::BufRead](std::io::BufRead) for use in no_std environments
+#[cfg(not(feature = "std"))]
+pub trait BufRead: Read {
+    fn fill_buf(&mut self) -> Result<&[u8]>;
+
+    fn consume(&mut self, amt: usize);
+}
+
+#[cfg(not(feature = "std"))]
+impl BufRead for &[u8] {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(*self)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        *self = &self[amt..];
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<B: BufRead + ?Sized> BufRead for &mut B {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        (**self).fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        (**self).consume(amt)
+    }
+}
+
 pub struct Bytes<'a, R: Read> {
     inner: &'a mut R,
 }
@@ -1,2 +1,3 @@
+pub use super::BufRead;
 pub use super::Read;
 pub use super::Seek;
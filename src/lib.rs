@@ -1,27 +1,29 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-#[allow(unused_imports)]
-#[cfg(feature = "std")]
-use std as alloc;
-
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
-#[cfg(feature = "alloc")]
-use alloc::{boxed::Box, string::String, vec::Vec};
-
 pub mod blocks;
 pub mod decoding;
 pub mod errors;
 pub mod frame;
+#[cfg(feature = "alloc")]
 pub mod frame_decoder;
 pub mod fse;
 pub mod huff0;
 pub mod io;
+#[cfg(feature = "std")]
+pub mod parallel_decoder;
+#[cfg(feature = "alloc")]
 pub mod streaming_decoder;
+#[cfg(feature = "std")]
 mod tests;
+pub mod workspace_decoder;
 
-pub const VERBOSE: bool = false;
+#[cfg(feature = "alloc")]
 pub use frame_decoder::BlockDecodingStrategy;
+#[cfg(feature = "alloc")]
 pub use frame_decoder::FrameDecoder;
-pub use streaming_decoder::StreamingDecoder;
+#[cfg(feature = "alloc")]
+pub use streaming_decoder::{BufStreamingDecoder, StreamingDecoder};
+pub use workspace_decoder::WorkspaceDecoder;
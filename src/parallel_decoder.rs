@@ -0,0 +1,471 @@
+//! Decoding of inputs that consist of many independent frames (like the output of pzstd) on multiple threads.
+//!
+//! The frame boundaries are found with find_frame_compressed_size() without decoding anything. Each frame is then
+//! decoded by its own FrameDecoder on a worker thread and the output is handed out in the original order.
+//! At most max_frames_in_flight frames are buffered at any time, counting both frames that are still being decoded
+//! and frames that are decoded but not yet handed out. Inputs with only one frame are decoded on the calling thread,
+//! the worker threads are only started once a second frame is found.
+
+use crate::decoding::dictionary::DictionaryRegistry;
+use crate::errors::{FrameDecoderError, FrameSizeError, ParallelDecodeError};
+use crate::frame;
+use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// Where the frames get decoded
+#[derive(Clone)]
+pub enum ThreadPool {
+    /// Spawn this many std threads (at least one). They live as long as the ParallelReader
+    Std(usize),
+    /// Use the global rayon thread pool
+    #[cfg(feature = "rayon")]
+    RayonGlobal,
+    /// Use this rayon thread pool
+    #[cfg(feature = "rayon")]
+    Rayon(Arc<rayon::ThreadPool>),
+}
+
+impl ThreadPool {
+    fn num_threads(&self) -> usize {
+        match self {
+            ThreadPool::Std(n) => (*n).max(1),
+            #[cfg(feature = "rayon")]
+            ThreadPool::RayonGlobal => rayon::current_num_threads(),
+            #[cfg(feature = "rayon")]
+            ThreadPool::Rayon(pool) => pool.current_num_threads(),
+        }
+    }
+}
+
+/// Decodes multi-frame inputs on multiple threads. Create readers with reader() or decode whole buffers with decode_all()
+#[derive(Clone)]
+pub struct ParallelDecoder {
+    pool: ThreadPool,
+    dicts: DictionaryRegistry,
+    max_frames_in_flight: usize,
+}
+
+impl ParallelDecoder {
+    /// Allows twice as many frames in flight as there are threads, so the threads do not run out of work while
+    /// the oldest frame is being read from
+    pub fn new(pool: ThreadPool) -> ParallelDecoder {
+        let max_frames_in_flight = 2 * pool.num_threads();
+        ParallelDecoder {
+            pool,
+            dicts: DictionaryRegistry::new(),
+            max_frames_in_flight,
+        }
+    }
+
+    /// Like new() but the FrameDecoders use the provided dictionaries
+    pub fn with_dicts(pool: ThreadPool, dicts: DictionaryRegistry) -> ParallelDecoder {
+        let mut decoder = Self::new(pool);
+        decoder.dicts = dicts;
+        decoder
+    }
+
+    /// Limits how many frames are buffered at once. This is what bounds the memory usage: roughly this many
+    /// times the size of the biggest frame, compressed and decoded. Values below 1 are treated as 1
+    pub fn set_max_frames_in_flight(&mut self, max_frames_in_flight: usize) {
+        self.max_frames_in_flight = max_frames_in_flight.max(1);
+    }
+
+    pub fn max_frames_in_flight(&self) -> usize {
+        self.max_frames_in_flight
+    }
+
+    /// Returns an io::Read that yields the decoded content of all frames in source, in order
+    pub fn reader<R: Read>(&self, source: R) -> ParallelReader<R> {
+        ParallelReader {
+            frames: OrderedFrames::new(self, source),
+            current: Vec::new(),
+            current_pos: 0,
+        }
+    }
+
+    /// Decodes all frames in src and returns the concatenated content
+    pub fn decode_all(&self, src: &[u8]) -> Result<Vec<u8>, ParallelDecodeError> {
+        let mut frames = OrderedFrames::new(self, src);
+        let mut result = Vec::new();
+        while let Some(content) = frames.next_frame_content()? {
+            if result.is_empty() {
+                result = content;
+            } else {
+                result.extend_from_slice(&content);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Decodes all frames in src with the given amount of std threads and returns the concatenated content
+pub fn decode_all_parallel(src: &[u8], threads: usize) -> Result<Vec<u8>, ParallelDecodeError> {
+    ParallelDecoder::new(ThreadPool::Std(threads)).decode_all(src)
+}
+
+/// io::Read adapter returned by ParallelDecoder::reader(). Frames are decoded in the background as far ahead
+/// as max_frames_in_flight allows, the content is returned in order
+pub struct ParallelReader<R: Read> {
+    frames: OrderedFrames<R>,
+    current: Vec<u8>,
+    current_pos: usize,
+}
+
+impl<R: Read> ParallelReader<R> {
+    /// Returns the source. Bytes that were already read from it to find the next frames are not included
+    pub fn into_inner(self) -> R {
+        self.frames.source
+    }
+}
+
+impl<R: Read> Read for ParallelReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        while self.current_pos == self.current.len() {
+            match self.frames.next_frame_content() {
+                Ok(Some(content)) => {
+                    self.current = content;
+                    self.current_pos = 0;
+                }
+                Ok(None) => return Ok(0),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+            }
+        }
+
+        let available = &self.current[self.current_pos..];
+        let amount = available.len().min(buf.len());
+        buf[..amount].copy_from_slice(&available[..amount]);
+        self.current_pos += amount;
+        Ok(amount)
+    }
+}
+
+type FrameResult = Result<Vec<u8>, ParallelDecodeError>;
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Splits the source into frames, hands them to the workers and puts the results back into order
+struct OrderedFrames<R: Read> {
+    source: R,
+    /// Bytes read from the source that do not belong to a submitted frame yet
+    input: Vec<u8>,
+    source_finished: bool,
+    failed: bool,
+    /// Reading from the source failed while checking whether the first frame is the only one.
+    /// Returned by the next read_frame() so the first frame is still returned before it
+    read_error: Option<ParallelDecodeError>,
+
+    workers: Workers,
+    dicts: DictionaryRegistry,
+    max_frames_in_flight: usize,
+
+    result_sender: mpsc::Sender<(usize, FrameResult)>,
+    result_receiver: mpsc::Receiver<(usize, FrameResult)>,
+    /// Results that arrived before the results of the frames in front of them
+    finished: BTreeMap<usize, FrameResult>,
+    next_to_submit: usize,
+    next_to_return: usize,
+}
+
+/// Read at least this many bytes at a time while searching for the end of a frame
+const MIN_READ_SIZE: usize = 128 * 1024;
+
+impl<R: Read> OrderedFrames<R> {
+    fn new(decoder: &ParallelDecoder, source: R) -> OrderedFrames<R> {
+        let (result_sender, result_receiver) = mpsc::channel();
+        OrderedFrames {
+            source,
+            input: Vec::new(),
+            source_finished: false,
+            failed: false,
+            read_error: None,
+
+            workers: Workers::new(&decoder.pool),
+            dicts: decoder.dicts.clone(),
+            max_frames_in_flight: decoder.max_frames_in_flight,
+
+            result_sender,
+            result_receiver,
+            finished: BTreeMap::new(),
+            next_to_submit: 0,
+            next_to_return: 0,
+        }
+    }
+
+    /// Returns the content of the next frame, None if all frames have been returned.
+    /// After an error no more frames are returned
+    fn next_frame_content(&mut self) -> Result<Option<Vec<u8>>, ParallelDecodeError> {
+        if self.failed {
+            return Ok(None);
+        }
+        loop {
+            self.submit_frames();
+
+            if let Some(result) = self.finished.remove(&self.next_to_return) {
+                self.next_to_return += 1;
+                if result.is_err() {
+                    self.failed = true;
+                }
+                return result.map(Some);
+            }
+            if self.next_to_return == self.next_to_submit {
+                return Ok(None);
+            }
+
+            // the sender is also held by self so this can only fail if something went very wrong
+            let (idx, result) = self
+                .result_receiver
+                .recv()
+                .expect("Result channel closed while frames were in flight");
+            self.finished.insert(idx, result);
+        }
+    }
+
+    /// Reads and submits frames until max_frames_in_flight are in flight or the source is exhausted.
+    /// An error is put in line with the frames so the frames in front of it are still returned first
+    fn submit_frames(&mut self) {
+        while !self.source_finished
+            && self.next_to_submit - self.next_to_return < self.max_frames_in_flight
+        {
+            let idx = self.next_to_submit;
+            match self.read_frame() {
+                Ok(Some(raw_frame)) => {
+                    let dicts = self.dicts.clone();
+                    if idx == 0 && self.source_ends_here() {
+                        // nothing to do in parallel, so no threads are needed
+                        self.finished
+                            .insert(idx, decode_frame_caught(idx, &raw_frame, dicts));
+                        self.source_finished = true;
+                    } else {
+                        let sender = self.result_sender.clone();
+                        self.workers.run(Box::new(move || {
+                            let result = decode_frame_caught(idx, &raw_frame, dicts);
+                            // the receiver is gone if the reader was dropped, nobody needs the result then
+                            let _ = sender.send((idx, result));
+                        }));
+                    }
+                }
+                Ok(None) => {
+                    self.source_finished = true;
+                    return;
+                }
+                Err(e) => {
+                    self.finished.insert(idx, Err(e));
+                    self.source_finished = true;
+                }
+            }
+            self.next_to_submit += 1;
+        }
+    }
+
+    /// Whether the source ends with the frame that was just read. Read errors are kept for the next read_frame()
+    fn source_ends_here(&mut self) -> bool {
+        if !self.input.is_empty() {
+            return false;
+        }
+        match self.fill_input(MIN_READ_SIZE) {
+            Ok(read) => read == 0,
+            Err(e) => {
+                self.read_error = Some(e);
+                false
+            }
+        }
+    }
+
+    /// Returns the bytes of the next frame, None if the source ended exactly after the last frame
+    fn read_frame(&mut self) -> Result<Option<Vec<u8>>, ParallelDecodeError> {
+        if let Some(e) = self.read_error.take() {
+            return Err(e);
+        }
+        let mut source_empty = false;
+        loop {
+            if self.input.is_empty() && source_empty {
+                return Ok(None);
+            }
+            match frame::find_frame_compressed_size(&self.input) {
+                Ok(size) => {
+                    let rest = self.input.split_off(size);
+                    return Ok(Some(core::mem::replace(&mut self.input, rest)));
+                }
+                Err(FrameSizeError::NotEnoughBytes { have, need }) if !source_empty => {
+                    // grow at least geometrically, find_frame_compressed_size starts over at the frame header every time
+                    let amount = (need - have).max(self.input.len()).max(MIN_READ_SIZE);
+                    source_empty = self.fill_input(amount)? == 0;
+                }
+                Err(err) => {
+                    return Err(ParallelDecodeError::FailedToFindFrame {
+                        frame: self.next_to_submit,
+                        err,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Reads up to amount bytes from the source into self.input
+    fn fill_input(&mut self, amount: usize) -> Result<usize, ParallelDecodeError> {
+        let old_len = self.input.len();
+        self.input.resize(old_len + amount, 0);
+        let mut read = 0;
+        while read < amount {
+            match self.source.read(&mut self.input[old_len + read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.input.truncate(old_len + read);
+                    return Err(ParallelDecodeError::FailedToReadSource(e));
+                }
+            }
+        }
+        self.input.truncate(old_len + read);
+        Ok(read)
+    }
+}
+
+/// Like decode_frame() but a panic is reported as an error of this frame
+fn decode_frame_caught(idx: usize, raw_frame: &[u8], dicts: DictionaryRegistry) -> FrameResult {
+    panic::catch_unwind(AssertUnwindSafe(|| decode_frame(idx, raw_frame, dicts)))
+        .unwrap_or(Err(ParallelDecodeError::WorkerPanicked { frame: idx }))
+}
+
+/// Decodes one complete frame. Skippable frames decode to nothing
+fn decode_frame(idx: usize, raw_frame: &[u8], dicts: DictionaryRegistry) -> FrameResult {
+    let magic_num = u32::from_le_bytes(raw_frame[..4].try_into().expect("optimized away"));
+    if (frame::SKIPPABLE_MAGIC_NUM_MIN..=frame::SKIPPABLE_MAGIC_NUM_MAX).contains(&magic_num) {
+        return Ok(Vec::new());
+    }
+
+    let to_error =
+        |err: FrameDecoderError| ParallelDecodeError::FailedToDecodeFrame { frame: idx, err };
+
+    let mut source = raw_frame;
+    let mut decoder = FrameDecoder::with_dicts(dicts);
+    decoder.reset(&mut source).map_err(to_error)?;
+    decoder
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .map_err(to_error)?;
+    let content = decoder
+        .collect()
+        .ok_or_else(|| to_error(FrameDecoderError::NotYetInitialized))?;
+
+    if let Some(from_data) = decoder.get_checksum_from_data() {
+        let calculated = decoder.get_calculated_checksum().unwrap_or(0);
+        if from_data != calculated {
+            return Err(ParallelDecodeError::ChecksumMismatch {
+                frame: idx,
+                from_data,
+                calculated,
+            });
+        }
+    }
+    Ok(content)
+}
+
+struct Workers {
+    pool: WorkerPool,
+    /// Set on drop, the jobs that were not started yet are skipped then
+    cancelled: Arc<AtomicBool>,
+}
+
+enum WorkerPool {
+    Std {
+        num_threads: usize,
+        /// Taken on drop so the threads see the channel closing
+        jobs: Option<mpsc::Sender<Job>>,
+        /// Started by the first run()
+        threads: Vec<thread::JoinHandle<()>>,
+    },
+    #[cfg(feature = "rayon")]
+    RayonGlobal,
+    #[cfg(feature = "rayon")]
+    Rayon(Arc<rayon::ThreadPool>),
+}
+
+impl Workers {
+    fn new(pool: &ThreadPool) -> Workers {
+        let worker_pool = match pool {
+            ThreadPool::Std(_) => WorkerPool::Std {
+                num_threads: pool.num_threads(),
+                jobs: None,
+                threads: Vec::new(),
+            },
+            #[cfg(feature = "rayon")]
+            ThreadPool::RayonGlobal => WorkerPool::RayonGlobal,
+            #[cfg(feature = "rayon")]
+            ThreadPool::Rayon(pool) => WorkerPool::Rayon(Arc::clone(pool)),
+        };
+        Workers {
+            pool: worker_pool,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn run(&mut self, job: Job) {
+        let cancelled = Arc::clone(&self.cancelled);
+        let job: Job = Box::new(move || {
+            if !cancelled.load(Ordering::Relaxed) {
+                job()
+            }
+        });
+        match &mut self.pool {
+            WorkerPool::Std {
+                num_threads,
+                jobs,
+                threads,
+            } => {
+                if jobs.is_none() {
+                    let (sender, receiver) = mpsc::channel::<Job>();
+                    let receiver = Arc::new(Mutex::new(receiver));
+                    *threads = (0..*num_threads)
+                        .map(|_| {
+                            let receiver = Arc::clone(&receiver);
+                            thread::spawn(move || loop {
+                                // the lock is only held while waiting for a job, not while running it
+                                let job = match receiver.lock() {
+                                    Ok(receiver) => receiver.recv(),
+                                    Err(_) => return,
+                                };
+                                match job {
+                                    Ok(job) => job(),
+                                    Err(_) => return,
+                                }
+                            })
+                        })
+                        .collect();
+                    *jobs = Some(sender);
+                }
+                // the threads only stop when the sender is dropped, so they are still there to take the job
+                jobs.as_ref()
+                    .expect("The threads were started above")
+                    .send(job)
+                    .expect("Worker threads stopped early");
+            }
+            #[cfg(feature = "rayon")]
+            WorkerPool::RayonGlobal => rayon::spawn(job),
+            #[cfg(feature = "rayon")]
+            WorkerPool::Rayon(pool) => pool.spawn(job),
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        // the frames that are being decoded right now are finished, the queued ones are skipped
+        self.cancelled.store(true, Ordering::Relaxed);
+        match &mut self.pool {
+            WorkerPool::Std { jobs, threads, .. } => {
+                jobs.take();
+                for thread in threads.drain(..) {
+                    let _ = thread.join();
+                }
+            }
+            #[cfg(feature = "rayon")]
+            _ => { /* the rayon pools are not owned by the workers */ }
+        }
+    }
+}
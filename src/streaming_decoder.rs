@@ -1,68 +1,175 @@
+use crate::errors::FrameDecoderError;
 use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
-use crate::io::{Error, ErrorKind, Read};
-#[cfg(feature = "alloc")]
-use alloc::string::String;
+use crate::io::{BufRead, Error, ErrorKind, Read};
 
 /// High level decoder that implements a io::Read that can be used with
 /// io::Read::read_to_end / io::Read::read_exact or passing this to another library / module as a source for the decoded content
 ///
+/// The source is owned by the decoder. Pass `&mut source` to only borrow it, see BorrowedStreamingDecoder.
+/// The decoder is Send if the source is Send.
+///
 /// The lower level FrameDecoder by comparison allows for finer grained control but need sto have it's decode_blocks method called continously
 /// to decode the zstd-frame.
-pub struct StreamingDecoder<'a> {
+pub struct StreamingDecoder<READ: Read> {
     pub decoder: FrameDecoder,
-    source: &'a mut dyn Read,
+    source: READ,
+    /// An error that happened after some bytes were decoded. It is returned once these bytes have been read
+    pending_error: Option<FrameDecoderError>,
 }
 
-impl<'a> StreamingDecoder<'a> {
-    pub fn new(source: &'a mut dyn Read) -> Result<StreamingDecoder<'_>, String> {
-        let mut decoder = FrameDecoder::new();
-        decoder.init(source)?;
-        Ok(StreamingDecoder { decoder, source })
+/// The StreamingDecoder as it was before it owned its source. Any `&mut impl Read` can be used as the source as well
+pub type BorrowedStreamingDecoder<'a> = StreamingDecoder<&'a mut dyn Read>;
+
+impl<READ: Read> StreamingDecoder<READ> {
+    /// Reads the frame header from source. Fails with FrameDecoderError::FailedToInitialize holding the FrameHeaderError if it is not a valid zstd frame header
+    pub fn new(source: READ) -> Result<StreamingDecoder<READ>, FrameDecoderError> {
+        Self::new_with_decoder(source, FrameDecoder::new())
     }
 
     pub fn new_with_decoder(
-        source: &'a mut dyn Read,
+        mut source: READ,
         mut decoder: FrameDecoder,
-    ) -> Result<StreamingDecoder<'_>, String> {
-        decoder.init(source)?;
-        Ok(StreamingDecoder { decoder, source })
+    ) -> Result<StreamingDecoder<READ>, FrameDecoderError> {
+        decoder.init(&mut source)?;
+        Ok(StreamingDecoder {
+            decoder,
+            source,
+            pending_error: None,
+        })
     }
 
+    /// Returns the FrameDecoder, e.g. to reuse it with new_with_decoder()
     pub fn inner(self) -> FrameDecoder {
         self.decoder
     }
+
+    /// Gets a reference to the source
+    pub fn get_ref(&self) -> &READ {
+        &self.source
+    }
+
+    /// Gets a mutable reference to the source. Reading from it while the frame is not finished will corrupt the decoding
+    pub fn get_mut(&mut self) -> &mut READ {
+        &mut self.source
+    }
+
+    /// Returns the source. After all decoded bytes have been read, it is positioned right after the frame
+    pub fn into_inner(self) -> READ {
+        self.source
+    }
 }
 
-impl<'a> Read for StreamingDecoder<'a> {
+impl<READ: Read> Read for StreamingDecoder<READ> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        if self.decoder.is_finished() && self.decoder.can_collect() == 0 {
-            //No more bytes can ever be decoded
-            return Ok(0);
-        }
+        let source = &mut self.source;
+        read_decoded(
+            &mut self.decoder,
+            &mut self.pending_error,
+            buf,
+            |decoder, strat| decoder.decode_blocks(source, strat),
+        )
+    }
+}
+
+/// Like StreamingDecoder but for sources that are a io::BufRead, like a BufReader or a slice.
+/// Compressed blocks are decoded directly from the buffer of the source instead of being copied into the decoder first.
+///
+/// Only the bytes of the frame are read from the source. After all decoded bytes have been read, the source is positioned right after the frame
+/// and can be used for whatever comes next.
+pub struct BufStreamingDecoder<READ: BufRead> {
+    pub decoder: FrameDecoder,
+    source: READ,
+    /// An error that happened after some bytes were decoded. It is returned once these bytes have been read
+    pending_error: Option<FrameDecoderError>,
+}
+
+impl<READ: BufRead> BufStreamingDecoder<READ> {
+    /// Reads the frame header from source. Fails with FrameDecoderError::FailedToInitialize holding the FrameHeaderError if it is not a valid zstd frame header
+    pub fn new(source: READ) -> Result<BufStreamingDecoder<READ>, FrameDecoderError> {
+        Self::new_with_decoder(source, FrameDecoder::new())
+    }
+
+    pub fn new_with_decoder(
+        mut source: READ,
+        mut decoder: FrameDecoder,
+    ) -> Result<BufStreamingDecoder<READ>, FrameDecoderError> {
+        decoder.init(&mut source)?;
+        Ok(BufStreamingDecoder {
+            decoder,
+            source,
+            pending_error: None,
+        })
+    }
 
-        // need to loop. The UpToBytes strategy doesnt take any effort to actually reach that limit.
-        // The first few calls can result in just filling the decode buffer but these bytes can not be collected.
-        // So we need to call this until we can actually collect enough bytes
-
-        // TODO add BlockDecodingStrategy::UntilCollectable(usize) that pushes this logic into the decode_blocks function
-        while self.decoder.can_collect() < buf.len() && !self.decoder.is_finished() {
-            //More bytes can be decoded
-            let additional_bytes_needed = buf.len() - self.decoder.can_collect();
-            match self.decoder.decode_blocks(
-                self.source,
-                BlockDecodingStrategy::UptoBytes(additional_bytes_needed),
-            ) {
-                Ok(_) => { /*Nothing to do*/ }
-                Err(e) => {
-                    let err = Error::new(
-                        ErrorKind::Other,
-                        format!("Error in the zstd decoder: {:?}", e),
-                    );
-                    return Err(err);
-                }
-            }
+    /// Returns the FrameDecoder, e.g. to reuse it with new_with_decoder()
+    pub fn inner(self) -> FrameDecoder {
+        self.decoder
+    }
+
+    /// Gets a reference to the source
+    pub fn get_ref(&self) -> &READ {
+        &self.source
+    }
+
+    /// Gets a mutable reference to the source. Reading from it while the frame is not finished will corrupt the decoding
+    pub fn get_mut(&mut self) -> &mut READ {
+        &mut self.source
+    }
+
+    /// Returns the source. After all decoded bytes have been read, it is positioned right after the frame
+    pub fn into_inner(self) -> READ {
+        self.source
+    }
+}
+
+impl<READ: BufRead> Read for BufStreamingDecoder<READ> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let source = &mut self.source;
+        read_decoded(
+            &mut self.decoder,
+            &mut self.pending_error,
+            buf,
+            |decoder, strat| decoder.decode_blocks_from_buf(source, strat),
+        )
+    }
+}
+
+/// Decodes blocks with decode_blocks until buf can be filled or the frame is finished, then reads from the decoder
+fn read_decoded(
+    decoder: &mut FrameDecoder,
+    pending_error: &mut Option<FrameDecoderError>,
+    buf: &mut [u8],
+    decode_blocks: impl FnOnce(
+        &mut FrameDecoder,
+        BlockDecodingStrategy,
+    ) -> Result<bool, FrameDecoderError>,
+) -> Result<usize, Error> {
+    if pending_error.is_some() {
+        // no more blocks are decoded before the error has been returned
+        if decoder.can_collect() == 0 {
+            let e = pending_error.take().expect("checked above");
+            return Err(Error::new(ErrorKind::Other, e));
         }
+        return decoder.read(buf);
+    }
 
-        self.decoder.read(buf)
+    if decoder.is_finished() && decoder.can_collect() == 0 {
+        //No more bytes can ever be decoded
+        return Ok(0);
     }
+
+    // with eager output the bytes of each block are handed out right away instead of waiting for more blocks to fill buf
+    let wanted = if decoder.eager_output() { 1 } else { buf.len() };
+    if decoder.can_collect() < wanted && !decoder.is_finished() {
+        //More bytes can be decoded
+        match decode_blocks(decoder, BlockDecodingStrategy::UntilCollectable(wanted)) {
+            Ok(_) => { /*Nothing to do*/ }
+            // hand out the bytes decoded before the error first, the error is returned once they have been read
+            Err(e) if decoder.can_collect() > 0 => *pending_error = Some(e),
+            // the FrameDecoderError is kept in the io::Error, so callers can tell e.g. a cancellation from corrupted data
+            Err(e) => return Err(Error::new(ErrorKind::Other, e)),
+        }
+    }
+
+    decoder.read(buf)
 }
@@ -38,6 +38,38 @@ fn test_bitreader_reversed() {
     }
 }
 
+#[test]
+fn test_bitreader_reversed_refill() {
+    use crate::decoding::bit_reader_reverse::{BitReaderReversed, MIN_BITS_AFTER_REFILL};
+
+    let encoded: Vec<u8> = (0..37u32).map(|x| (x * 97 + 13) as u8).collect();
+
+    //the unchecked path must return the same bits as the checked one, also past the start of the source
+    let mut checked = BitReaderReversed::new(&encoded[..]);
+    let mut unchecked = BitReaderReversed::new(&encoded[..]);
+    let mut x = 0;
+    while checked.bits_remaining() > -64 {
+        unchecked.refill();
+        let mut refilled_bits_used = 0;
+        loop {
+            x += 5;
+            let num_bits = (x % 17) as u8;
+            if refilled_bits_used + num_bits > MIN_BITS_AFTER_REFILL {
+                break;
+            }
+            refilled_bits_used += num_bits;
+
+            let expected = checked.get_bits(num_bits as usize).unwrap();
+            assert_eq!(unchecked.peek_bits(num_bits), expected);
+            unchecked.consume(num_bits);
+            assert_eq!(checked.bits_remaining(), unchecked.bits_remaining());
+        }
+    }
+
+    //reading past the start yields zeros
+    assert_eq!(checked.get_bits(64).unwrap(), 0);
+}
+
 #[test]
 fn test_bitreader_normal() {
     use crate::decoding::bit_reader::BitReader;
@@ -0,0 +1,80 @@
+#[cfg(test)]
+use crate::decoding::block_observer::{BlockInfo, BlockObserver};
+
+#[cfg(test)]
+#[derive(Default)]
+struct Recorder {
+    with_sequences: bool,
+    blocks: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    decompressed: std::sync::Arc<std::sync::Mutex<u64>>,
+}
+
+#[cfg(test)]
+impl BlockObserver for Recorder {
+    fn on_block(&mut self, block: &BlockInfo<'_>) {
+        *self.decompressed.lock().unwrap() += u64::from(block.decompressed_size);
+
+        if let Some(sequences) = &block.sequences {
+            if let Some(decoded) = sequences.sequences {
+                assert_eq!(decoded.len(), sequences.num_sequences as usize);
+                // all bytes of the block are produced by the sequences and the literals after the last sequence
+                let from_sequences: u32 = decoded.iter().map(|seq| seq.ll + seq.ml).sum();
+                let literals = block.literals.unwrap().section.regenerated_size;
+                let literals_in_sequences: u32 = decoded.iter().map(|seq| seq.ll).sum();
+                assert_eq!(
+                    from_sequences + literals - literals_in_sequences,
+                    block.decompressed_size
+                );
+            } else {
+                assert!(!self.with_sequences);
+            }
+        }
+        self.blocks.lock().unwrap().push(format!("{:?}", block));
+    }
+
+    fn wants_sequences(&self) -> bool {
+        self.with_sequences
+    }
+}
+
+#[test]
+fn test_block_observer() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let mut files: Vec<_> = fs::read_dir("./decodecorpus_files")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .take(30)
+        .collect();
+    files.sort();
+
+    for &with_sequences in &[false, true] {
+        let recorder = Recorder {
+            with_sequences,
+            ..Recorder::default()
+        };
+        let blocks = std::sync::Arc::clone(&recorder.blocks);
+        let decompressed = std::sync::Arc::clone(&recorder.decompressed);
+
+        let mut frame_dec = FrameDecoder::new();
+        frame_dec.set_block_observer(Box::new(recorder));
+
+        let mut total = 0;
+        let mut total_blocks = 0;
+        for path in &files {
+            let mut content = fs::File::open(path).unwrap();
+            frame_dec.reset(&mut content).unwrap();
+            frame_dec
+                .decode_blocks(&mut content, BlockDecodingStrategy::All)
+                .unwrap();
+            total += frame_dec.collect().unwrap().len() as u64;
+            total_blocks += frame_dec.blocks_decoded();
+        }
+
+        assert_eq!(*decompressed.lock().unwrap(), total);
+        assert_eq!(blocks.lock().unwrap().len(), total_blocks);
+        assert!(frame_dec.take_block_observer().is_some());
+    }
+}
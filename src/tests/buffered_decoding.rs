@@ -0,0 +1,66 @@
+#[test]
+fn test_buf_streaming_decoder_leaves_rest_of_source() {
+    use crate::streaming_decoder::BufStreamingDecoder;
+    use std::io::{BufReader, Read};
+
+    let first = std::fs::read("./decodecorpus_files/z000088.zst").unwrap();
+    let second = std::fs::read("./decodecorpus_files/z000068.zst").unwrap();
+    let mut compressed = first.clone();
+    compressed.extend(&second);
+
+    let expected_first = std::fs::read("./decodecorpus_files/z000088").unwrap();
+    let expected_second = std::fs::read("./decodecorpus_files/z000068").unwrap();
+
+    // a slice buffers everything, the small BufReader can not hold most blocks and falls back to copying them
+    let mut slice = &compressed[..];
+    let mut small_reader = BufReader::with_capacity(100, &compressed[..]);
+    let mut big_reader = BufReader::with_capacity(1 << 20, &compressed[..]);
+    let sources: Vec<&mut dyn std::io::BufRead> =
+        vec![&mut slice, &mut small_reader, &mut big_reader];
+
+    for source in sources {
+        for expected in &[&expected_first, &expected_second] {
+            let mut decoder = BufStreamingDecoder::new(&mut *source).unwrap();
+            let mut result = Vec::new();
+            decoder.read_to_end(&mut result).unwrap();
+            assert!(&result == *expected);
+        }
+
+        let mut rest = Vec::new();
+        source.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+}
+
+#[test]
+fn test_decode_blocks_from_buf() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let mut files: Vec<_> = fs::read_dir("./decodecorpus_files")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .collect();
+    files.sort();
+
+    let mut frame_dec = FrameDecoder::new();
+    for path in files {
+        let mut compressed = fs::read(&path).unwrap();
+        let expected = fs::read(path.with_extension("")).unwrap();
+        let frame_len = compressed.len();
+        compressed.extend(&[1, 2, 3]);
+
+        let mut source = &compressed[..];
+        frame_dec.reset(&mut source).unwrap();
+        while !frame_dec.is_finished() {
+            frame_dec
+                .decode_blocks_from_buf(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+                .unwrap();
+        }
+
+        assert!(frame_dec.collect().unwrap() == expected);
+        assert_eq!(frame_dec.bytes_read_from_source(), frame_len as u64);
+        assert_eq!(source, &[1, 2, 3]);
+    }
+}
@@ -0,0 +1,262 @@
+#[cfg(test)]
+const RAW_HELLO: &[u8] = &[5 << 3, 0, 0, b'h', b'e', b'l', b'l', b'o'];
+
+/// A compressed block that wants to repeat the huffman table, which no block before it has provided
+#[cfg(test)]
+fn treeless_block(last: bool) -> Vec<u8> {
+    let content = [0x03u8, 0, 0, 0, 0, 0, 0, 0];
+    let header = (content.len() as u32) << 3 | 2 << 1 | last as u32;
+    let mut block = header.to_le_bytes()[..3].to_vec();
+    block.extend_from_slice(&content);
+    block
+}
+
+/// Single segment frame without checksum, so the window is the content size
+#[cfg(test)]
+fn frame(content_size: u8, blocks: &[&[u8]]) -> Vec<u8> {
+    let mut frame = vec![0x28, 0xB5, 0x2F, 0xFD, 0x20, content_size];
+    for block in blocks {
+        frame.extend_from_slice(block);
+    }
+    frame
+}
+
+#[test]
+fn test_skip_corrupt_block() {
+    use crate::errors::FrameDecoderError;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+
+    // RLE block of 4 'x' as the last block
+    let rle_last = [4 << 3 | 1 << 1 | 1, 0, 0, b'x'];
+    let compressed = frame(9, &[RAW_HELLO, &treeless_block(false), &rle_last]);
+
+    let mut source = &compressed[..];
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.reset(&mut source).unwrap();
+    assert!(frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .is_err());
+
+    let corrupt = *frame_dec.corrupt_block().unwrap();
+    assert_eq!(corrupt.block_index, 1);
+    assert_eq!(corrupt.compressed_offset, 6 + RAW_HELLO.len() as u64);
+    assert_eq!(corrupt.decompressed_offset, 5);
+    assert_eq!(corrupt.declared_size, None);
+    assert!(corrupt.skippable);
+
+    // everything before the corrupt block can be collected although the frame is not finished
+    assert_eq!(frame_dec.can_collect(), 5);
+    assert_eq!(frame_dec.collect().unwrap(), b"hello");
+
+    match frame_dec.decode_blocks(&mut source, BlockDecodingStrategy::All) {
+        Err(FrameDecoderError::CorruptBlockNotSkipped) => {}
+        _ => panic!("decoding must not continue before the block is skipped"),
+    }
+
+    assert_eq!(frame_dec.skip_corrupt_block(&mut source).unwrap(), 0);
+    assert!(frame_dec.corrupt_block().is_none());
+    assert!(frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .unwrap());
+    assert_eq!(frame_dec.collect().unwrap(), b"xxxx");
+    assert_eq!(frame_dec.blocks_decoded(), 3);
+    assert_eq!(frame_dec.bytes_read_from_source(), compressed.len() as u64);
+    assert!(source.is_empty());
+}
+
+#[test]
+fn test_skip_corrupt_last_block() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+
+    // the frame content size declares the size of the corrupt last block
+    let compressed = frame(12, &[RAW_HELLO, &treeless_block(true)]);
+
+    let mut source = &compressed[..];
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.reset(&mut source).unwrap();
+    assert!(frame_dec
+        .decode_blocks_from_buf(&mut source, BlockDecodingStrategy::All)
+        .is_err());
+    assert!(source.is_empty());
+    assert_eq!(frame_dec.corrupt_block().unwrap().declared_size, Some(7));
+
+    assert_eq!(frame_dec.skip_corrupt_block(&mut source).unwrap(), 7);
+    assert!(frame_dec.is_finished());
+    assert_eq!(frame_dec.collect().unwrap(), b"hello\0\0\0\0\0\0\0");
+}
+
+#[test]
+fn test_skip_corrupt_block_keeps_window() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use crate::io::Read;
+
+    // no literals and one sequence with all codes in RLE mode: offset code 3 with extra bits 0 (offset 8 - 3 = 5), match length 5
+    let repeat_hello = [7 << 3 | 2 << 1 | 1, 0, 0, 0, 1, 0x54, 0, 3, 2, 0x08];
+    let compressed = frame(10, &[RAW_HELLO, &treeless_block(false), &repeat_hello]);
+
+    for &use_read in &[false, true] {
+        let mut source = &compressed[..];
+        let mut frame_dec = FrameDecoder::new();
+        frame_dec.reset(&mut source).unwrap();
+        assert!(frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .is_err());
+
+        // the bytes before the corrupt block are handed out, but kept for the blocks after it
+        let mut before = vec![0u8; 10];
+        if use_read {
+            assert_eq!(frame_dec.read(&mut before).unwrap(), 5);
+            before.truncate(5);
+        } else {
+            before = frame_dec.collect().unwrap();
+        }
+        assert_eq!(before, b"hello");
+        assert_eq!(frame_dec.can_collect(), 0);
+
+        assert_eq!(frame_dec.skip_corrupt_block(&mut source).unwrap(), 0);
+        assert!(frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap());
+        assert_eq!(frame_dec.collect().unwrap(), b"hello");
+    }
+}
+
+#[test]
+fn test_skip_corrupt_block_checks_output_limit() {
+    use crate::decoding::limits::{OutputLimit, OutputLimits};
+    use crate::errors::FrameDecoderError;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+
+    // the 7 zeros that replace the corrupt last block would exceed the limit
+    let compressed = frame(12, &[RAW_HELLO, &treeless_block(true)]);
+
+    let mut source = &compressed[..];
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_output_limits(OutputLimits {
+        max_frame_output: Some(10),
+        ..OutputLimits::default()
+    });
+    frame_dec.reset(&mut source).unwrap();
+    assert!(frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .is_err());
+
+    match frame_dec.skip_corrupt_block(&mut source) {
+        Err(FrameDecoderError::OutputLimitExceeded(OutputLimit::FrameOutput(10))) => {}
+        _ => panic!("the placeholder must not exceed the output limit"),
+    }
+    assert_eq!(
+        frame_dec.corrupt_block().unwrap().exceeded_limit,
+        Some(OutputLimit::FrameOutput(10))
+    );
+    match frame_dec.decode_blocks(&mut source, BlockDecodingStrategy::All) {
+        Err(FrameDecoderError::OutputLimitExceeded(_)) => {}
+        _ => panic!("decoding must fail with the same error"),
+    }
+    assert_eq!(frame_dec.collect().unwrap(), b"hello");
+}
+
+#[test]
+fn test_continue_with_next_frame() {
+    use crate::errors::FrameDecoderError;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+
+    // a reserved block type, where the block ends is unknown
+    let reserved = [3 << 1, 0, 0];
+    let mut compressed = frame(5, &[RAW_HELLO, &reserved]);
+    let first_frame_len = compressed.len();
+    let raw_last = [3 << 3 | 1, 0, 0, b'a', b'b', b'c'];
+    compressed.extend_from_slice(&frame(3, &[&raw_last]));
+
+    let mut source = &compressed[..];
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.reset(&mut source).unwrap();
+    assert!(frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .is_err());
+
+    let corrupt = *frame_dec.corrupt_block().unwrap();
+    assert!(corrupt.header.is_none());
+    assert!(!corrupt.skippable);
+    match frame_dec.skip_corrupt_block(&mut source) {
+        Err(FrameDecoderError::CanNotSkipBlock) => {}
+        _ => panic!("a block without header can not be skipped"),
+    }
+    assert_eq!(frame_dec.collect().unwrap(), b"hello");
+
+    // the caller knows where the next frame starts
+    let mut source = &compressed[first_frame_len..];
+    frame_dec.reset(&mut source).unwrap();
+    assert!(frame_dec.corrupt_block().is_none());
+    frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .unwrap();
+    assert_eq!(frame_dec.collect().unwrap(), b"abc");
+}
+
+#[test]
+fn test_streaming_returns_bytes_before_corrupt_block() {
+    use crate::errors::FrameDecoderError;
+    use crate::io::Read;
+    use crate::streaming_decoder::StreamingDecoder;
+
+    let compressed = frame(12, &[RAW_HELLO, &treeless_block(true)]);
+    let mut stream = StreamingDecoder::new(&compressed[..]).unwrap();
+    let mut result = Vec::new();
+    let err = stream.read_to_end(&mut result).unwrap_err();
+    assert_eq!(result, b"hello");
+    // the error of the corrupt block, not that decoding can not continue before it is skipped
+    match err.get_ref().unwrap().downcast_ref::<FrameDecoderError>() {
+        Some(FrameDecoderError::FailedToReadBlockBody(_)) => {}
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    assert_eq!(stream.decoder.corrupt_block().unwrap().block_index, 1);
+}
+
+#[test]
+fn test_errors_keep_their_cause() {
+    use crate::blocks::block::BlockType;
+    use crate::errors::{
+        DecodeBlockContentError, DecompressBlockError, DecompressLiteralsError, FrameDecoderError,
+        FrameHeaderError,
+    };
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::error::Error;
+
+    let mut frame_dec = FrameDecoder::new();
+    let mut source: &[u8] = &[0x27, 0xB5, 0x2F, 0xFD, 0x20, 0];
+    match frame_dec.reset(&mut source) {
+        Err(FrameDecoderError::FailedToInitialize(FrameHeaderError::BadMagicNumber(_))) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let compressed = frame(12, &[RAW_HELLO, &treeless_block(true)]);
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    let err = frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .unwrap_err();
+    match &err {
+        FrameDecoderError::FailedToReadBlockBody(
+            DecodeBlockContentError::DecompressBlockError(
+                DecompressBlockError::FailedToDecodeLiterals(
+                    DecompressLiteralsError::UninitializedHuffmanTable,
+                ),
+            ),
+        ) => {}
+        other => panic!("Unexpected error: {:?}", other),
+    }
+    assert!(err.source().unwrap().source().unwrap().source().is_some());
+
+    // the raw block is cut off
+    let compressed = frame(5, &[&RAW_HELLO[..6]]);
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    match frame_dec.decode_blocks(&mut source, BlockDecodingStrategy::All) {
+        Err(FrameDecoderError::FailedToReadBlockBody(DecodeBlockContentError::ReadError {
+            step: BlockType::Raw,
+            ..
+        })) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
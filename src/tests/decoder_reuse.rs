@@ -0,0 +1,90 @@
+#[cfg(test)]
+fn decode_all(
+    frame_dec: &mut crate::frame_decoder::FrameDecoder,
+    frames: &[(Vec<u8>, Vec<u8>)],
+    buf: &mut [u8],
+) {
+    use crate::frame_decoder::BlockDecodingStrategy;
+    use crate::io::Read;
+
+    for (compressed, original) in frames {
+        let mut source = &compressed[..];
+        frame_dec.reset(&mut source).unwrap();
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+
+        let mut decoded = 0;
+        loop {
+            let read = frame_dec.read(buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            assert!(buf[..read] == original[decoded..decoded + read]);
+            decoded += read;
+        }
+        assert_eq!(decoded, original.len());
+    }
+}
+
+#[test]
+fn test_no_allocations_when_reused() {
+    use crate::frame_decoder::FrameDecoder;
+    use std::fs;
+
+    // frames with and without dictionary, so the dictionary tables are switched in and out
+    let mut files: Vec<_> = fs::read_dir("./dict_tests/files")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .take(50)
+        .collect();
+    files.extend(
+        fs::read_dir("./decodecorpus_files")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+            .take(20),
+    );
+    files.sort();
+    let frames: Vec<_> = files
+        .iter()
+        .map(|path| {
+            let compressed = fs::read(path).unwrap();
+            let original = fs::read(path.with_extension("")).unwrap();
+            (compressed, original)
+        })
+        .collect();
+
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec
+        .add_dict(&fs::read("./dict_tests/dictionary").unwrap())
+        .unwrap();
+    let mut buf = vec![0u8; 4096];
+
+    decode_all(&mut frame_dec, &frames, &mut buf);
+    let after_warmup = frame_dec.buffer_reallocations();
+    assert!(after_warmup > 0);
+
+    decode_all(&mut frame_dec, &frames, &mut buf);
+    assert_eq!(frame_dec.buffer_reallocations(), after_warmup);
+}
+
+#[test]
+fn test_collect_counts_buffer_reallocations() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+
+    let compressed = std::fs::read("./decodecorpus_files/z000088.zst").unwrap();
+    let mut frame_dec = FrameDecoder::new();
+    for _ in 0..2 {
+        let mut source = &compressed[..];
+        frame_dec.reset(&mut source).unwrap();
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+    }
+
+    let before = frame_dec.buffer_reallocations();
+    frame_dec.collect().unwrap();
+    assert_eq!(frame_dec.buffer_reallocations(), before + 1);
+}
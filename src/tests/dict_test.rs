@@ -1,6 +1,7 @@
 #[test]
 fn test_dict_parsing() {
     use crate::decoding::dictionary::Dictionary;
+    use crate::errors::DictionaryDecodeError;
     let mut raw = vec![0u8; 8];
 
     // correct magic num
@@ -27,10 +28,11 @@ fn test_dict_parsing() {
     ];
     raw.extend(&raw_tables[..]);
 
-    //offset history 3,10,0x00ABCDEF
+    //offset history 3,10,20
+    let offsets_start = raw.len();
     raw.extend(vec![3, 0, 0, 0]);
     raw.extend(vec![10, 0, 0, 0]);
-    raw.extend(vec![0xEF, 0xCD, 0xAB, 0]);
+    raw.extend(vec![20, 0, 0, 0]);
 
     //just some random bytes
     let raw_content = vec![
@@ -54,14 +56,39 @@ fn test_dict_parsing() {
         );
     }
 
-    if !dict.offset_hist.eq(&[3, 10, 0x00ABCDEF]) {
+    if !dict.offset_hist.eq(&[3, 10, 20]) {
         panic!(
             "offset history did not get parsed correctly. Is: {:?}, Should be: {:?}",
             dict.offset_hist,
-            [3, 10, 0x00ABCDEF]
+            [3, 10, 20]
         );
     }
 
+    // test offset checking, offsets must not be zero and must point into the content
+    let mut bad_offsets = raw.clone();
+    bad_offsets[offsets_start + 4] = 0;
+    match Dictionary::decode_dict(&bad_offsets) {
+        Err(DictionaryDecodeError::ZeroRepeatOffset { index: 1 }) => { /* This is what should happen*/
+        }
+        Err(e) => panic!("Wrong error for a zero offset: {}", e),
+        Ok(_) => panic!("The dict got decoded but an offset was zero!"),
+    }
+    let mut bad_offsets = raw.clone();
+    bad_offsets[offsets_start + 8..offsets_start + 12].copy_from_slice(&[0xEF, 0xCD, 0xAB, 0]);
+    match Dictionary::decode_dict(&bad_offsets) {
+        Err(DictionaryDecodeError::RepeatOffsetTooBig { index: 2, .. }) => { /* This is what should happen*/
+        }
+        Err(e) => panic!("Wrong error for a too big offset: {}", e),
+        Ok(_) => panic!("The dict got decoded but an offset was bigger than the content!"),
+    }
+
+    // test that truncated dicts are rejected without panicking
+    for len in 0..offsets_start + 12 {
+        if Dictionary::decode_dict(&raw[..len]).is_ok() {
+            panic!("The dict got decoded but was truncated to {} bytes", len);
+        }
+    }
+
     // test magic num checking
     raw[0] = 1;
     raw[1] = 1;
@@ -90,8 +117,9 @@ fn test_dict_decoding() {
     let mut speeds_read = Vec::new();
 
     let mut files: Vec<_> = fs::read_dir("./dict_tests/files").unwrap().collect();
-    let dict = fs::File::open("./dict_tests/dictionary").unwrap();
-    let dict: Vec<u8> = dict.bytes().map(|x| x.unwrap()).collect();
+    let mut dict_file = fs::File::open("./dict_tests/dictionary").unwrap();
+    let mut dict: Vec<u8> = Vec::new();
+    dict_file.read_to_end(&mut dict).unwrap();
 
     files.sort_by_key(|x| match x {
         Err(_) => "".to_owned(),
@@ -123,28 +151,26 @@ fn test_dict_decoding() {
         let result = frame_dec.collect().unwrap();
         let end_time = start_time.elapsed();
 
-        match frame_dec.get_checksum_from_data() {
-            Some(chksum) => {
-                if frame_dec.get_calculated_checksum().unwrap() != chksum {
+        if let Some(chksum) = frame_dec.get_checksum_from_data() {
+            if frame_dec.get_calculated_checksum().unwrap() != chksum {
                 panic!(
-                        "Checksum did not match! From data: {}, calculated while decoding: {}\n",
-                        chksum,
-                        frame_dec.get_calculated_checksum().unwrap()
-                    );
-                } else {
-                }
+                    "Checksum did not match! From data: {}, calculated while decoding: {}\n",
+                    chksum,
+                    frame_dec.get_calculated_checksum().unwrap()
+                );
             }
         }
 
         let mut original_p = p.clone();
         original_p.truncate(original_p.len() - 4);
-        let original_f = fs::File::open(original_p).unwrap();
-        let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+        let mut original_f = fs::File::open(original_p).unwrap();
+        let mut original: Vec<u8> = Vec::new();
+        original_f.read_to_end(&mut original).unwrap();
 
         let mut success = true;
 
         if original.len() != result.len() {
-            panic!(
+            println!(
                 "Result has wrong length: {}, should be: {}",
                 result.len(),
                 original.len()
@@ -206,6 +232,7 @@ fn test_dict_decoding() {
         fail_counter_diff
     );
     for f in &failed {
+        println!("{}", f);
     }
 
     let speed_len = speeds.len();
@@ -213,8 +240,11 @@ fn test_dict_decoding() {
     let avg_speed = sum_speed / speed_len;
     let avg_speed_bps = avg_speed * 1_000_000;
     if avg_speed_bps < 1000 {
+        println!("Average speed: {} B/s", avg_speed_bps);
     } else if avg_speed_bps < 1_000_000 {
+        println!("Average speed: {} KB/s", avg_speed_bps / 1000);
     } else {
+        println!("Average speed: {} MB/s", avg_speed_bps / 1_000_000);
     }
 
     let speed_read_len = speeds_read.len();
@@ -222,7 +252,9 @@ fn test_dict_decoding() {
     let avg_speed_read = sum_speed_read / speed_read_len;
     let avg_speed_read_bps = avg_speed_read * 1_000_000;
     if avg_speed_read_bps < 1000 {
-    } else if avg_speed_bps < 1_000_000 {
+        println!("Average speed reading: {} B/s", avg_speed_read_bps);
+    } else if avg_speed_read_bps < 1_000_000 {
+        println!("Average speed reading: {} KB/s", avg_speed_read_bps / 1000);
     } else {
         println!(
             "Average speed reading: {} MB/s",
@@ -232,3 +264,162 @@ fn test_dict_decoding() {
 
     assert!(failed.is_empty());
 }
+
+#[test]
+fn test_dict_registry() {
+    use crate::decoding::dictionary::{Dictionary, DictionaryRegistry};
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+    use std::io::Read;
+    use std::sync::Arc;
+
+    let mut dict_file = fs::File::open("./dict_tests/dictionary").unwrap();
+    let mut raw_dict: Vec<u8> = Vec::new();
+    dict_file.read_to_end(&mut raw_dict).unwrap();
+
+    let mut registry = DictionaryRegistry::new();
+    let id = registry.add_dict(&raw_dict).unwrap();
+    assert_eq!(registry.ids().collect::<Vec<_>>(), vec![id]);
+
+    let mut files: Vec<_> = fs::read_dir("./dict_tests/files")
+        .unwrap()
+        .map(|f| f.unwrap().path())
+        .filter(|p| p.to_str().unwrap().ends_with(".zst"))
+        .collect();
+    files.sort();
+    let files = Arc::new(files);
+
+    // all decoders share the same dictionary content
+    let handles: Vec<_> = (0..4)
+        .map(|thread_idx| {
+            let registry = registry.clone();
+            let files = Arc::clone(&files);
+            std::thread::spawn(move || {
+                let mut frame_dec = FrameDecoder::with_dicts(registry);
+                for p in files.iter().skip(thread_idx).step_by(4) {
+                    let mut content = fs::File::open(p).unwrap();
+                    frame_dec.reset(&mut content).unwrap();
+                    frame_dec
+                        .decode_blocks(&mut content, BlockDecodingStrategy::All)
+                        .unwrap();
+                    let result = frame_dec.collect().unwrap();
+
+                    let mut original = Vec::new();
+                    let original_p = p.with_extension("");
+                    fs::File::open(original_p)
+                        .unwrap()
+                        .read_to_end(&mut original)
+                        .unwrap();
+                    assert!(result == original, "Wrong result for: {:?}", p);
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    // without the dictionary decoding must fail
+    let mut frame_dec = FrameDecoder::with_dicts(registry.clone());
+    assert!(frame_dec.remove_dict(id).is_some());
+    assert!(frame_dec.dicts().is_empty());
+    assert!(registry.contains(id));
+    let mut content = fs::File::open(&files[0]).unwrap();
+    frame_dec.reset(&mut content).unwrap();
+    match frame_dec.decode_blocks(&mut content, BlockDecodingStrategy::All) {
+        Err(crate::errors::FrameDecoderError::DictNotProvided) => { /* expected */ }
+        _ => panic!("Decoding without the dictionary did not fail"),
+    }
+
+    // the loader is asked for the missing dictionary and it is kept for the following frames
+    let shared = Arc::new(Dictionary::decode_dict(&raw_dict).unwrap());
+    let loader_calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let calls = Arc::clone(&loader_calls);
+    frame_dec.set_dict_loader(Box::new(move |requested| {
+        calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        if requested == shared.id {
+            Some(Arc::clone(&shared))
+        } else {
+            None
+        }
+    }));
+    for p in files.iter().take(3) {
+        let mut content = fs::File::open(p).unwrap();
+        frame_dec.reset(&mut content).unwrap();
+        frame_dec
+            .decode_blocks(&mut content, BlockDecodingStrategy::All)
+            .unwrap();
+    }
+    assert_eq!(loader_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert!(frame_dec.dicts().contains(id));
+}
+
+#[test]
+fn test_prefix_and_raw_content_dict() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let prefix = fs::read("./dict_tests/prefix/prefix").unwrap();
+    let original = fs::read("./dict_tests/prefix/data").unwrap();
+
+    // the prefix is only used for the one frame it was provided for
+    let mut frame_dec = FrameDecoder::new();
+    for _ in 0..2 {
+        let mut content = fs::File::open("./dict_tests/prefix/data_prefix.zst").unwrap();
+        frame_dec.reset_with_prefix(&mut content, &prefix).unwrap();
+        frame_dec
+            .decode_blocks(&mut content, BlockDecodingStrategy::All)
+            .unwrap();
+        let result = frame_dec.collect().unwrap();
+        assert!(result == original);
+        assert_eq!(
+            frame_dec.get_checksum_from_data(),
+            frame_dec.get_calculated_checksum()
+        );
+    }
+    let mut content = fs::File::open("./dict_tests/prefix/data_prefix.zst").unwrap();
+    frame_dec.reset(&mut content).unwrap();
+    assert!(frame_dec
+        .decode_blocks(&mut content, BlockDecodingStrategy::All)
+        .is_err());
+
+    // a raw content dictionary with id 0 is used for frames without a dictionary id
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.add_raw_content_dict(0, &prefix);
+    let mut content = fs::File::open("./dict_tests/prefix/data_raw_dict.zst").unwrap();
+    frame_dec.reset(&mut content).unwrap();
+    frame_dec
+        .decode_blocks(&mut content, BlockDecodingStrategy::All)
+        .unwrap();
+    let result = frame_dec.collect().unwrap();
+    assert!(result == original);
+    assert_eq!(
+        frame_dec.get_checksum_from_data(),
+        frame_dec.get_calculated_checksum()
+    );
+}
+
+#[test]
+fn test_prefix_changes_between_frames() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let prefix = fs::read("./dict_tests/prefix/prefix").unwrap();
+    let mut other_prefix = prefix.clone();
+    for x in other_prefix.iter_mut() {
+        *x = x.wrapping_add(1);
+    }
+    let compressed = fs::read("./dict_tests/prefix/data_prefix.zst").unwrap();
+    let original = fs::read("./dict_tests/prefix/data").unwrap();
+
+    // the copy of the previous prefix must not be used for a prefix with other content
+    let mut frame_dec = FrameDecoder::new();
+    for (prefix, matches) in &[(&prefix, true), (&other_prefix, false), (&prefix, true)] {
+        let mut source = &compressed[..];
+        frame_dec.reset_with_prefix(&mut source, prefix).unwrap();
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+        assert_eq!(frame_dec.collect().unwrap() == original, *matches);
+    }
+}
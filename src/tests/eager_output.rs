@@ -0,0 +1,83 @@
+/// Serves the bytes up to a flush point. Reading past it would block until the peer sends more
+#[cfg(test)]
+struct FlushedPeer<'a> {
+    data: &'a [u8],
+}
+
+#[cfg(test)]
+impl std::io::Read for FlushedPeer<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        assert!(
+            !self.data.is_empty(),
+            "decoder waits for bytes after the flush"
+        );
+        self.data.read(buf)
+    }
+}
+
+#[test]
+fn test_eager_output_frame_decoder() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_eager_output(true);
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    let mut result = Vec::new();
+    while !frame_dec.is_finished() {
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+            .unwrap();
+        // everything decoded so far can be collected right away
+        result.extend(frame_dec.collect().unwrap_or_default());
+        assert_eq!(frame_dec.can_collect(), 0);
+        assert!(result[..] == original[..result.len()]);
+    }
+    assert!(result == original);
+    assert_eq!(
+        frame_dec.get_checksum_from_data(),
+        frame_dec.get_calculated_checksum()
+    );
+}
+
+#[test]
+fn test_eager_output_streaming() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use crate::streaming_decoder::StreamingDecoder;
+    use std::fs;
+    use std::io::Read;
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+
+    // the sender flushed after the second block
+    let mut reference = FrameDecoder::new();
+    reference.set_eager_output(true);
+    let mut source = &compressed[..];
+    reference.reset(&mut source).unwrap();
+    reference
+        .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(2))
+        .unwrap();
+    let flush_point = reference.bytes_read_from_source() as usize;
+    let flushed_len = reference.collect().unwrap().len();
+    assert!(flushed_len < original.len());
+
+    let mut peer = FlushedPeer {
+        data: &compressed[..flush_point],
+    };
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_eager_output(true);
+    let mut stream = StreamingDecoder::new_with_decoder(&mut peer, frame_dec).unwrap();
+    let mut result = Vec::new();
+    let mut buf = vec![0; 1024 * 1024];
+    while result.len() < flushed_len {
+        let read = stream.read(&mut buf).unwrap();
+        assert!(read > 0);
+        result.extend_from_slice(&buf[..read]);
+    }
+    assert!(result[..] == original[..flushed_len]);
+}
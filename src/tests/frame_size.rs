@@ -0,0 +1,108 @@
+#[cfg(test)]
+fn read_file(path: &str) -> Vec<u8> {
+    use std::io::Read;
+
+    let mut f = std::fs::File::open(path).unwrap();
+    let mut content = Vec::new();
+    f.read_to_end(&mut content).unwrap();
+    content
+}
+
+#[test]
+fn test_decompressed_size_multiple_frames() {
+    use crate::frame;
+
+    // both frames declare their content size
+    let mut src = read_file("./decodecorpus_files/z000004.zst");
+    // skippable frame with 5 bytes of user data
+    src.extend(&[0x50, 0x2A, 0x4D, 0x18, 5, 0, 0, 0, 1, 2, 3, 4, 5]);
+    src.extend(read_file("./decodecorpus_files/z000005.zst"));
+
+    let expected = read_file("./decodecorpus_files/z000004").len()
+        + read_file("./decodecorpus_files/z000005").len();
+
+    let bound = frame::decompress_bound(&src).unwrap();
+    assert!(bound >= expected as u64);
+
+    assert_eq!(
+        frame::find_decompressed_size(&src).unwrap(),
+        Some(expected as u64)
+    );
+
+    // truncating the last frame must be reported instead of producing a wrong size
+    match frame::decompress_bound(&src[..src.len() - 1]) {
+        Err(crate::errors::FrameSizeError::NotEnoughBytes { .. }) => { /* expected */ }
+        _ => panic!("Truncated input was not detected"),
+    }
+}
+
+#[test]
+fn test_decompress_bound_corpus() {
+    use crate::frame;
+    use std::fs;
+
+    for file in fs::read_dir("./decodecorpus_files").unwrap() {
+        let p = file.unwrap().path();
+        let p = p.to_str().unwrap();
+        if !p.ends_with(".zst") {
+            continue;
+        }
+
+        let src = read_file(p);
+        let original = read_file(&p[..p.len() - 4]);
+
+        let bound = frame::decompress_bound(&src).unwrap();
+        assert!(
+            bound >= original.len() as u64,
+            "Bound: {} smaller than decompressed size: {} for file: {}",
+            bound,
+            original.len(),
+            p
+        );
+        if let Some(size) = frame::find_decompressed_size(&src).unwrap() {
+            assert_eq!(size, original.len() as u64, "Wrong size for file: {}", p);
+        }
+    }
+}
+
+#[test]
+fn test_frame_compressed_size() {
+    use crate::errors::FrameSizeError;
+    use crate::frame;
+    use std::fs;
+
+    for file in fs::read_dir("./decodecorpus_files").unwrap() {
+        let p = file.unwrap().path();
+        let p = p.to_str().unwrap();
+        if !p.ends_with(".zst") {
+            continue;
+        }
+
+        let src = read_file(p);
+        assert_eq!(frame::find_frame_compressed_size(&src).unwrap(), src.len());
+
+        // trailing bytes of a following frame must not be counted
+        let mut with_trailer = src.clone();
+        with_trailer.extend(&[0x28, 0xB5, 0x2F, 0xFD]);
+        assert_eq!(
+            frame::find_frame_compressed_size(&with_trailer).unwrap(),
+            src.len()
+        );
+
+        match frame::find_frame_compressed_size(&src[..src.len() - 1]) {
+            Err(FrameSizeError::NotEnoughBytes { have, need }) => {
+                assert_eq!(have, src.len() - 1);
+                assert!(need > have);
+            }
+            Ok(size) => panic!("Truncated frame reported size: {}", size),
+            Err(e) => panic!("Unexpected error for truncated frame: {}", e),
+        }
+    }
+
+    let skippable = [0x5F, 0x2A, 0x4D, 0x18, 2, 0, 0, 0, 0xAB, 0xCD, 0xEF];
+    assert_eq!(frame::find_frame_compressed_size(&skippable).unwrap(), 10);
+    match frame::find_frame_compressed_size(&skippable[..9]) {
+        Err(FrameSizeError::NotEnoughBytes { have: 9, need: 10 }) => { /* expected */ }
+        _ => panic!("Truncated skippable frame was not detected"),
+    }
+}
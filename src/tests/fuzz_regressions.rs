@@ -15,12 +15,9 @@ fn test_all_artifacts() {
         }
 
         let mut f = File::open(file_name.clone()).unwrap();
-        match frame_dec.reset(&mut f) {
-            Ok(_) => {
-                let _ = frame_dec.decode_blocks(&mut f, frame_decoder::BlockDecodingStrategy::All);
-                /* ignore errors. It just should never panic on invalid input */
-            }
-            Err(_) => {} /* ignore errors. It just should never panic on invalid input */
+        if frame_dec.reset(&mut f).is_ok() {
+            let _ = frame_dec.decode_blocks(&mut f, frame_decoder::BlockDecodingStrategy::All);
+            /* ignore errors. It just should never panic on invalid input */
         }
     }
 }
@@ -0,0 +1,176 @@
+#[cfg(test)]
+use crate::errors::DecompressLiteralsError;
+#[cfg(test)]
+use crate::huff0::HuffmanTable;
+
+/// Short codes (1 to 6 bits), two of them fit into one entry of the double table
+#[cfg(test)]
+const SHORT_CODES: &[u8] = &[5, 0, 4, 3, 0, 2, 1, 1];
+/// 64 symbols with 6 bit codes, two of them do not fit into the 11 bits of the double table
+#[cfg(test)]
+const LONG_CODES: &[u8] = &[1; 63];
+
+#[cfg(test)]
+fn build_table(weights: &[u8]) -> HuffmanTable {
+    let mut table = HuffmanTable::new();
+    table.build_from_weights(weights).unwrap();
+    table
+}
+
+/// The code of a symbol is the top num_bits of the indexes its entries occupy in the decoding table
+#[cfg(test)]
+fn codes(table: &HuffmanTable) -> Vec<Option<(u32, u8)>> {
+    let mut codes = vec![None; 256];
+    for (idx, entry) in table.decode.iter().enumerate() {
+        let code = (idx >> (table.max_num_bits - entry.num_bits)) as u32;
+        codes[entry.symbol as usize] = Some((code, entry.num_bits));
+    }
+    codes
+}
+
+/// Semi random symbols of the table, short codes are more likely like in real data
+#[cfg(test)]
+fn symbols(table: &HuffmanTable, len: usize, seed: u64) -> Vec<u8> {
+    let choices: Vec<u8> = table.decode.iter().map(|e| e.symbol).collect();
+    let mut x = seed;
+    (0..len)
+        .map(|_| {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            choices[(x >> 33) as usize % choices.len()]
+        })
+        .collect()
+}
+
+/// Writes the symbols as a stream that is read backwards, like the literal streams of zstd
+#[cfg(test)]
+fn encode_stream(table: &HuffmanTable, symbols: &[u8]) -> Vec<u8> {
+    let codes = codes(table);
+
+    // bits in the order the decoder reads them: the 1 that ends the padding, then the codes
+    let mut bits = vec![true];
+    for &symbol in symbols {
+        let (code, num_bits) = codes[symbol as usize].unwrap();
+        for bit in (0..num_bits).rev() {
+            bits.push((code >> bit) & 1 == 1);
+        }
+    }
+
+    // the decoder starts at the highest bit of the last byte, the zeros of the padding are read first
+    let num_bytes = (bits.len() + 7) / 8;
+    let padding = num_bytes * 8 - bits.len();
+    let mut stream = vec![0u8; num_bytes];
+    for (idx, bit) in bits.iter().enumerate() {
+        let pos = num_bytes * 8 - 1 - (padding + idx);
+        if *bit {
+            stream[pos / 8] |= 1 << (pos % 8);
+        }
+    }
+    stream
+}
+
+/// Encodes the literals in one or four streams and decodes them again with the given table
+#[cfg(test)]
+fn decode(
+    table: &HuffmanTable,
+    literals: &[u8],
+    num_streams: u8,
+) -> Result<Vec<u8>, DecompressLiteralsError> {
+    use crate::blocks::literals_section::{LiteralsSection, LiteralsSectionType};
+    use crate::decoding::literals_section_decoder::decode_literals;
+    use crate::decoding::scratch::HuffmanScratch;
+
+    let mut source = Vec::new();
+    if num_streams == 4 {
+        let segment_size = (literals.len() + 3) / 4;
+        let streams: Vec<Vec<u8>> = literals
+            .chunks(segment_size)
+            .map(|segment| encode_stream(table, segment))
+            .collect();
+        assert_eq!(streams.len(), 4);
+        for stream in &streams[..3] {
+            source.extend_from_slice(&(stream.len() as u16).to_le_bytes());
+        }
+        for stream in &streams {
+            source.extend_from_slice(stream);
+        }
+    } else {
+        source = encode_stream(table, literals);
+    }
+
+    let section = LiteralsSection {
+        regenerated_size: literals.len() as u32,
+        compressed_size: Some(source.len() as u32),
+        num_streams: Some(num_streams),
+        ls_type: LiteralsSectionType::Treeless,
+    };
+    let mut scratch = HuffmanScratch::new();
+    scratch.table.copy_from(table);
+
+    let mut target = Vec::new();
+    decode_literals(&section, &mut scratch, &source, &mut target)?;
+    Ok(target)
+}
+
+#[test]
+fn test_double_table_matches_single_table() {
+    use crate::huff0::DOUBLE_TABLE_LOG;
+
+    let mut table = build_table(SHORT_CODES);
+    assert!(!table.has_double_symbols());
+    table.build_double_table();
+    assert!(table.has_double_symbols());
+
+    let max_num_bits = table.max_num_bits;
+    let single = |bits: usize| table.decode[bits >> (DOUBLE_TABLE_LOG - max_num_bits)];
+    let mask = (1 << DOUBLE_TABLE_LOG) - 1;
+    for (idx, entry) in table.decode_double.iter().enumerate() {
+        let first = single(idx);
+        assert_eq!(entry.symbols[0], first.symbol);
+        if entry.has_second_symbol {
+            let second = single((idx << first.num_bits) & mask);
+            assert_eq!(entry.symbols[1], second.symbol);
+            assert_eq!(entry.num_bits, first.num_bits + second.num_bits);
+        } else {
+            assert_eq!(entry.num_bits, first.num_bits);
+        }
+    }
+
+    // two codes of 6 bits do not fit
+    let mut table = build_table(LONG_CODES);
+    table.build_double_table();
+    assert!(!table.has_double_symbols());
+}
+
+#[test]
+fn test_single_and_double_symbols_decode_the_same() {
+    let single = build_table(SHORT_CODES);
+    let mut double = build_table(SHORT_CODES);
+    double.build_double_table();
+
+    // every length around the 8 symbols of a fast round, so streams and segments end in the middle of a pair
+    for len in 16..300 {
+        let literals = symbols(&single, len, len as u64);
+        for &num_streams in &[1, 4] {
+            assert_eq!(decode(&single, &literals, num_streams).unwrap(), literals);
+            assert_eq!(decode(&double, &literals, num_streams).unwrap(), literals);
+        }
+    }
+}
+
+#[test]
+fn test_decode_long_literal_sections() {
+    use crate::huff0::DOUBLE_TABLE_MIN_LITERALS;
+
+    // from DOUBLE_TABLE_MIN_LITERALS on the decoder builds the double table itself
+    for &weights in &[SHORT_CODES, LONG_CODES] {
+        let table = build_table(weights);
+        for len in DOUBLE_TABLE_MIN_LITERALS - 5..DOUBLE_TABLE_MIN_LITERALS + 5 {
+            let literals = symbols(&table, len, len as u64);
+            for &num_streams in &[1, 4] {
+                assert_eq!(decode(&table, &literals, num_streams).unwrap(), literals);
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+/// Frame with a 128KiB window and no content size, made of RLE blocks that each decode to 128KiB from a single byte
+#[cfg(test)]
+fn rle_bomb(num_blocks: usize) -> Vec<u8> {
+    let mut frame = vec![0x28, 0xB5, 0x2F, 0xFD, 0x00, 7 << 3];
+    for idx in 0..num_blocks {
+        let last = idx + 1 == num_blocks;
+        let header = (128 * 1024) << 3 | 1 << 1 | last as u32;
+        frame.extend_from_slice(&header.to_le_bytes()[..3]);
+        frame.push(b'b');
+    }
+    frame
+}
+
+#[test]
+fn test_rle_bomb_limits() {
+    use crate::decoding::limits::{OutputLimit, OutputLimits};
+    use crate::errors::FrameDecoderError;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+
+    let compressed = rle_bomb(100);
+
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_output_limits(OutputLimits {
+        max_frame_output: Some(1024 * 1024),
+        ..OutputLimits::default()
+    });
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    match frame_dec.decode_blocks(&mut source, BlockDecodingStrategy::All) {
+        Err(FrameDecoderError::OutputLimitExceeded(OutputLimit::FrameOutput(1048576))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // the blocks up to the limit can still be collected
+    assert_eq!(frame_dec.blocks_decoded(), 8);
+    assert_eq!(frame_dec.corrupt_block().unwrap().block_index, 8);
+    assert_eq!(frame_dec.can_collect(), 1024 * 1024);
+
+    // after the header, every block reads 4 bytes. With a ratio of 1000 only the allowance of one block is left
+    frame_dec.set_output_limits(OutputLimits {
+        max_expansion_ratio: Some(1000),
+        ..OutputLimits::default()
+    });
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    match frame_dec.decode_blocks(&mut source, BlockDecodingStrategy::All) {
+        Err(FrameDecoderError::OutputLimitExceeded(OutputLimit::ExpansionRatio(1000))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(frame_dec.blocks_decoded(), 1);
+
+    let mut target = vec![0; 256 * 1024];
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_output_limits(OutputLimits {
+        max_expansion_ratio: Some(1000),
+        ..OutputLimits::default()
+    });
+    match frame_dec.decode_from_to(&compressed, &mut target) {
+        Err(FrameDecoderError::OutputLimitExceeded(OutputLimit::ExpansionRatio(1000))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_frame_output_limit_sequences() {
+    use crate::decoding::limits::{OutputLimit, OutputLimits};
+    use crate::errors::FrameDecoderError;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+    let half = original.len() as u64 / 2;
+
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_output_limits(OutputLimits {
+        max_frame_output: Some(half),
+        ..OutputLimits::default()
+    });
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    match frame_dec.decode_blocks(&mut source, BlockDecodingStrategy::All) {
+        Err(FrameDecoderError::OutputLimitExceeded(OutputLimit::FrameOutput(max)))
+            if max == half => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let decoded = frame_dec.collect().unwrap();
+    assert!(decoded.len() as u64 <= half);
+    assert!(decoded[..] == original[..decoded.len()]);
+
+    // exactly the size of the frame is allowed
+    frame_dec.set_output_limits(OutputLimits {
+        max_frame_output: Some(original.len() as u64),
+        ..OutputLimits::default()
+    });
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .unwrap();
+    assert!(frame_dec.collect().unwrap() == original);
+}
+
+#[test]
+fn test_stream_output_limit() {
+    use crate::decoding::limits::{OutputLimit, OutputLimits};
+    use crate::errors::FrameDecoderError;
+    use crate::streaming_decoder::StreamingDecoder;
+    use std::fs;
+    use std::io::Read;
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+    let mut two_frames = compressed.clone();
+    two_frames.extend_from_slice(&compressed);
+
+    let max = original.len() as u64 * 3 / 2;
+    let mut source = &two_frames[..];
+    let mut stream = StreamingDecoder::new(&mut source).unwrap();
+    stream.decoder.set_output_limits(OutputLimits {
+        max_stream_output: Some(max),
+        ..OutputLimits::default()
+    });
+    let mut result = Vec::new();
+    stream.read_to_end(&mut result).unwrap();
+    assert!(result == original);
+
+    let decoder = stream.inner();
+    let mut stream = StreamingDecoder::new_with_decoder(&mut source, decoder).unwrap();
+    let err = stream.read_to_end(&mut Vec::new()).unwrap_err();
+    match err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<FrameDecoderError>())
+    {
+        Some(FrameDecoderError::OutputLimitExceeded(OutputLimit::StreamOutput(limit)))
+            if *limit == max => {}
+        _ => panic!("unexpected error: {:?}", err),
+    }
+}
@@ -0,0 +1,105 @@
+#[cfg(test)]
+fn supported_strategies() -> Vec<crate::decoding::match_copy::CopyStrategy> {
+    use crate::decoding::match_copy::CopyStrategy;
+
+    let mut strategies = vec![CopyStrategy::Portable];
+    if CopyStrategy::detect() != CopyStrategy::Portable {
+        strategies.push(CopyStrategy::detect());
+    }
+    strategies
+}
+
+#[test]
+fn test_repeat_against_bytewise_copy() {
+    use crate::decoding::decodebuffer::Decodebuffer;
+
+    for strategy in supported_strategies() {
+        let mut buf = Decodebuffer::new(1 << 20);
+        buf.set_copy_strategy(strategy).unwrap();
+        let mut expected: Vec<u8> = Vec::new();
+
+        let start: Vec<u8> = (0..300u32).map(|x| (x * 7 + x / 13) as u8).collect();
+        buf.push(&start);
+        expected.extend_from_slice(&start);
+
+        //semi random offsets and lengths, covering all small offsets and matches overlapping their source
+        let mut x: u64 = 0;
+        for round in 0..5000 {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let offset = if round < 600 {
+                round % 40 + 1
+            } else {
+                (x >> 33) as usize % expected.len() + 1
+            };
+            let match_length = (x >> 20) as usize % 100 + 1;
+
+            buf.repeat(offset, match_length).unwrap();
+            let start_idx = expected.len() - offset;
+            for idx in 0..match_length {
+                let byte = expected[start_idx + idx];
+                expected.push(byte);
+            }
+
+            // some literals in between, they overwrite the slack of the last copy
+            if round % 3 == 0 {
+                let literals = [round as u8, (round >> 8) as u8, 0xAA];
+                buf.push(&literals);
+                expected.extend_from_slice(&literals);
+            }
+        }
+
+        assert!(
+            buf.buffer == expected,
+            "Strategy {:?} produced a different result",
+            strategy
+        );
+    }
+}
+
+#[test]
+fn test_copy_strategies_decode_corpus() {
+    use crate::decoding::block_decoder;
+    use crate::decoding::scratch::DecoderScratch;
+    use crate::frame;
+    use std::fs;
+
+    let mut files: Vec<_> = fs::read_dir("./decodecorpus_files")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .collect();
+    files.sort();
+
+    for strategy in supported_strategies() {
+        for path in &files {
+            let content = fs::read(path).unwrap();
+            let mut source = content.as_slice();
+
+            let (frame, _) = frame::read_frame_header(&mut source).unwrap();
+            let window_size = frame.header.window_size().unwrap();
+            let mut scratch = DecoderScratch::new(window_size as usize);
+            scratch.buffer.set_copy_strategy(strategy).unwrap();
+
+            let mut block_dec = block_decoder::new();
+            loop {
+                let (header, _) = block_dec.read_block_header(&mut source).unwrap();
+                block_dec
+                    .decode_block_content(&header, &mut scratch, &mut source)
+                    .unwrap();
+                if header.last_block {
+                    break;
+                }
+            }
+
+            let original = fs::read(path.with_extension("")).unwrap();
+            assert!(
+                scratch.buffer.buffer == original,
+                "Strategy {:?} decoded {:?} wrong",
+                strategy,
+                path
+            );
+        }
+    }
+}
@@ -54,13 +54,13 @@ fn test_decode_from_to() {
     use crate::frame_decoder;
     use std::fs::File;
     use std::io::Read;
-    let f = File::open("./decodecorpus_files/z000088.zst").unwrap();
+    let mut f = File::open("./decodecorpus_files/z000088.zst").unwrap();
     let mut frame_dec = frame_decoder::FrameDecoder::new();
 
-    let content: Vec<u8> = f.bytes().map(|x| x.unwrap()).collect();
+    let mut content: Vec<u8> = Vec::new();
+    f.read_to_end(&mut content).unwrap();
 
-    let mut target = Vec::with_capacity(1024 * 1024);
-    target.resize(1024 * 1024, 0u8);
+    let mut target = vec![0u8; 1024 * 1024];
 
     // first part
     let source1 = &content[..50 * 1024];
@@ -93,28 +93,26 @@ fn test_decode_from_to() {
     let result = &target.as_slice()[..written];
 
     if read != content.len() {
-        panic!(format!(
+        panic!(
             "Byte counter: {} was wrong. Should be: {}",
             read,
             content.len()
-        ));
+        );
     }
 
-    match frame_dec.get_checksum_from_data() {
-        Some(chksum) => {
-            if frame_dec.get_calculated_checksum().unwrap() != chksum {
-                panic!(
-                    "Checksum did not match! From data: {}, calculated while decoding: {}\n",
-                    chksum,
-                    frame_dec.get_calculated_checksum().unwrap()
-                );
-            } else {
-            }
+    if let Some(chksum) = frame_dec.get_checksum_from_data() {
+        if frame_dec.get_calculated_checksum().unwrap() != chksum {
+            panic!(
+                "Checksum did not match! From data: {}, calculated while decoding: {}\n",
+                chksum,
+                frame_dec.get_calculated_checksum().unwrap()
+            );
         }
     }
 
-    let original_f = File::open("./decodecorpus_files/z000088").unwrap();
-    let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+    let mut original_f = File::open("./decodecorpus_files/z000088").unwrap();
+    let mut original: Vec<u8> = Vec::new();
+    original_f.read_to_end(&mut original).unwrap();
 
     if original.len() != result.len() {
         panic!(
@@ -170,8 +168,9 @@ fn test_specific_file() {
         .unwrap();
     let result = frame_dec.collect().unwrap();
 
-    let original_f = fs::File::open("./decodecorpus_files/z000088").unwrap();
-    let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+    let mut original_f = fs::File::open("./decodecorpus_files/z000068").unwrap();
+    let mut original: Vec<u8> = Vec::new();
+    original_f.read_to_end(&mut original).unwrap();
 
 
     if original.len() != result.len() {
@@ -197,6 +196,7 @@ fn test_specific_file() {
         }
     }
     if counter > 0 {
+        panic!("Result differs in at least {} bytes from original", counter);
     }
 }
 
@@ -211,8 +211,9 @@ fn test_streaming() {
     let mut result = Vec::new();
     Read::read_to_end(&mut stream, &mut result).unwrap();
 
-    let original_f = fs::File::open("./decodecorpus_files/z000088").unwrap();
-    let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+    let mut original_f = fs::File::open("./decodecorpus_files/z000088").unwrap();
+    let mut original: Vec<u8> = Vec::new();
+    original_f.read_to_end(&mut original).unwrap();
 
     if original.len() != result.len() {
         panic!(
@@ -250,8 +251,9 @@ fn test_streaming() {
     let mut result = Vec::new();
     Read::read_to_end(&mut stream, &mut result).unwrap();
 
-    let original_f = fs::File::open("./decodecorpus_files/z000068").unwrap();
-    let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+    let mut original_f = fs::File::open("./decodecorpus_files/z000068").unwrap();
+    let mut original: Vec<u8> = Vec::new();
+    original_f.read_to_end(&mut original).unwrap();
 
 
     if original.len() != result.len() {
@@ -281,7 +283,128 @@ fn test_streaming() {
     }
 }
 
+#[test]
+fn test_streaming_owned_source() {
+    use crate::streaming_decoder::{BorrowedStreamingDecoder, StreamingDecoder};
+    use std::fs;
+    use std::io::Read;
+
+    fn open(path: &str) -> StreamingDecoder<fs::File> {
+        StreamingDecoder::new(fs::File::open(path).unwrap()).unwrap()
+    }
+    fn assert_send<T: Send>(_: &T) {}
+
+    let mut compressed = fs::read("./decodecorpus_files/z000088.zst").unwrap();
+    compressed.extend(&[1, 2, 3]);
+    let original = fs::read("./decodecorpus_files/z000088").unwrap();
+
+    // the owned decoder can be moved to another thread
+    let stream = open("./decodecorpus_files/z000088.zst");
+    assert_send(&stream);
+    let result = std::thread::spawn(move || {
+        let mut stream = stream;
+        let mut result = Vec::new();
+        stream.read_to_end(&mut result).unwrap();
+        result
+    })
+    .join()
+    .unwrap();
+    assert!(result == original);
+
+    // the source is positioned after the frame
+    let mut stream = StreamingDecoder::new(&compressed[..]).unwrap();
+    // only the frame header has been read yet
+    assert!(stream.get_ref().len() < compressed.len());
+    let mut result = Vec::new();
+    stream.read_to_end(&mut result).unwrap();
+    assert!(result == original);
+    assert_eq!(stream.into_inner(), &[1, 2, 3]);
+
+    let mut source: &[u8] = &compressed;
+    let source: &mut dyn Read = &mut source;
+    let mut stream: BorrowedStreamingDecoder = StreamingDecoder::new(source).unwrap();
+    let mut result = Vec::new();
+    stream.read_to_end(&mut result).unwrap();
+    assert!(result == original);
+}
+
+#[test]
+fn test_streaming_new_header_error() {
+    use crate::errors::{FrameDecoderError, FrameHeaderError};
+    use crate::streaming_decoder::{BufStreamingDecoder, StreamingDecoder};
+
+    let not_zstd: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
+    match StreamingDecoder::new(not_zstd) {
+        Err(FrameDecoderError::FailedToInitialize(FrameHeaderError::BadMagicNumber(_))) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Decoder should not accept a bad magic number"),
+    }
+    match BufStreamingDecoder::new(not_zstd) {
+        Err(FrameDecoderError::FailedToInitialize(FrameHeaderError::BadMagicNumber(_))) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Decoder should not accept a bad magic number"),
+    }
+}
+
+#[test]
+fn test_decoding_strategies() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use crate::io::Read;
+    use std::fs;
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+
+    for strat in &[
+        BlockDecodingStrategy::UntilCollectable(1000),
+        BlockDecodingStrategy::UptoInputBytes(5000),
+    ] {
+        let mut frame_dec = FrameDecoder::new();
+        let mut source = &compressed[..];
+        frame_dec.reset(&mut source).unwrap();
+        let mut result = Vec::new();
+        let mut buf = [0u8; 1000];
+
+        while !frame_dec.is_finished() {
+            let bytes_read_before = frame_dec.bytes_read_from_source();
+            frame_dec.decode_blocks(&mut source, *strat).unwrap();
+            let bytes_read = frame_dec.bytes_read_from_source() - bytes_read_before;
+            match strat {
+                BlockDecodingStrategy::UntilCollectable(n) => {
+                    assert!(frame_dec.is_finished() || frame_dec.can_collect() >= *n);
+                }
+                BlockDecodingStrategy::UptoInputBytes(n) => {
+                    assert!(frame_dec.is_finished() || bytes_read >= *n);
+                    // only the block that crossed the limit is read after it
+                    assert!(bytes_read < n + 128 * 1024 + 3);
+                }
+                _ => unreachable!(),
+            }
+            let read = frame_dec.read(&mut buf).unwrap();
+            result.extend_from_slice(&buf[..read]);
+        }
+        while frame_dec.can_collect() > 0 {
+            let read = frame_dec.read(&mut buf).unwrap();
+            result.extend_from_slice(&buf[..read]);
+        }
+        assert!(result == original);
+    }
+}
+
 pub mod bit_reader;
+pub mod block_observer;
+pub mod buffered_decoding;
+pub mod corrupt_blocks;
 pub mod decode_corpus;
+pub mod decoder_reuse;
 pub mod dict_test;
+pub mod eager_output;
+pub mod frame_size;
 pub mod fuzz_regressions;
+pub mod huffman_decoding;
+pub mod limits;
+pub mod match_copy;
+pub mod parallel_decoder;
+pub mod progress;
+pub mod snapshot;
+pub mod workspace_decoder;
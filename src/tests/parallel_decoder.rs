@@ -0,0 +1,196 @@
+#[cfg(test)]
+/// Returns the compressed frames, their content and the length of the content of the last frame
+fn corpus_frames() -> (Vec<u8>, Vec<u8>, usize) {
+    use std::fs;
+
+    let mut files: Vec<_> = fs::read_dir("./decodecorpus_files")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .collect();
+    files.sort();
+
+    let mut compressed = Vec::new();
+    let mut expected = Vec::new();
+    let mut last_len = 0;
+    for (idx, path) in files.iter().take(60).enumerate() {
+        let original = fs::read(path.with_extension("")).unwrap();
+        last_len = original.len();
+        compressed.extend(fs::read(path).unwrap());
+        expected.extend(original);
+        if idx % 10 == 0 {
+            // skippable frame with 3 bytes of user data
+            compressed.extend(&[0x5F, 0x2A, 0x4D, 0x18, 3, 0, 0, 0, 1, 2, 3]);
+        }
+    }
+    (compressed, expected, last_len)
+}
+
+#[test]
+fn test_parallel_decode_all() {
+    use crate::parallel_decoder::{decode_all_parallel, ParallelDecoder, ThreadPool};
+
+    let (compressed, expected, _) = corpus_frames();
+
+    let result = decode_all_parallel(&compressed, 4).unwrap();
+    assert!(result == expected);
+
+    // a single frame in flight means everything is done in order
+    let mut decoder = ParallelDecoder::new(ThreadPool::Std(3));
+    decoder.set_max_frames_in_flight(1);
+    assert!(decoder.decode_all(&compressed).unwrap() == expected);
+
+    assert!(decode_all_parallel(&[], 2).unwrap().is_empty());
+}
+
+#[test]
+fn test_parallel_reader() {
+    use crate::parallel_decoder::{ParallelDecoder, ThreadPool};
+    use std::io::Read;
+
+    let (compressed, expected, _) = corpus_frames();
+
+    // a source that only hands out a few bytes per read
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let amount = buf.len().min(self.0.len()).min(777);
+            buf[..amount].copy_from_slice(&self.0[..amount]);
+            self.0 = &self.0[amount..];
+            Ok(amount)
+        }
+    }
+
+    let decoder = ParallelDecoder::new(ThreadPool::Std(4));
+    let mut reader = decoder.reader(Trickle(&compressed));
+    let mut result = Vec::new();
+    let mut buf = [0u8; 1000];
+    loop {
+        let read = reader.read(&mut buf).unwrap();
+        if read == 0 {
+            break;
+        }
+        result.extend_from_slice(&buf[..read]);
+    }
+    assert!(result == expected);
+}
+
+#[test]
+fn test_parallel_errors_are_ordered() {
+    use crate::errors::ParallelDecodeError;
+    use crate::parallel_decoder::{ParallelDecoder, ThreadPool};
+    use std::io::Read;
+
+    let (mut compressed, expected, last_frame_len) = corpus_frames();
+    // truncate the last frame
+    compressed.pop();
+
+    let decoder = ParallelDecoder::new(ThreadPool::Std(4));
+    match decoder.decode_all(&compressed) {
+        Err(ParallelDecodeError::FailedToFindFrame { .. }) => { /* expected */ }
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("Truncated input was not detected"),
+    }
+
+    // all frames before the broken one are still returned
+    let mut reader = decoder.reader(&compressed[..]);
+    let mut result = Vec::new();
+    assert!(reader.read_to_end(&mut result).is_err());
+    assert!(result[..] == expected[..expected.len() - last_frame_len]);
+}
+
+#[test]
+fn test_parallel_single_frame() {
+    use crate::parallel_decoder::{ParallelDecoder, ThreadPool};
+    use std::fs;
+    use std::io::Read;
+
+    let compressed = fs::read("./decodecorpus_files/z000088.zst").unwrap();
+    let expected = fs::read("./decodecorpus_files/z000088").unwrap();
+
+    // decoded on the calling thread
+    let decoder = ParallelDecoder::new(ThreadPool::Std(4));
+    assert!(decoder.decode_all(&compressed).unwrap() == expected);
+
+    // the source fails while checking for a second frame, the first one is still returned before the error
+    struct FailAfter<'a>(&'a [u8], bool);
+    impl<'a> Read for FailAfter<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                if self.1 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::Other, "broken"));
+                }
+                self.1 = true;
+            }
+            let amount = buf.len().min(self.0.len());
+            buf[..amount].copy_from_slice(&self.0[..amount]);
+            self.0 = &self.0[amount..];
+            Ok(amount)
+        }
+    }
+    let mut reader = decoder.reader(FailAfter(&compressed, false));
+    let mut result = Vec::new();
+    assert!(reader.read_to_end(&mut result).is_err());
+    assert!(result == expected);
+}
+
+#[test]
+fn test_parallel_reader_drop_mid_stream() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use crate::parallel_decoder::{ParallelDecoder, ThreadPool};
+    use std::fs;
+    use std::io::Read;
+    use std::time::Instant;
+
+    let frame = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+    const FRAMES: usize = 32;
+    let compressed = frame.repeat(FRAMES);
+
+    let start = Instant::now();
+    let mut frame_dec = FrameDecoder::new();
+    let mut source = &frame[..];
+    frame_dec.reset(&mut source).unwrap();
+    frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .unwrap();
+    assert_eq!(frame_dec.collect().unwrap().len(), original.len());
+    let one_frame = start.elapsed();
+
+    // one thread with all frames queued, only the frame it is decoding when the reader is dropped is finished
+    let mut decoder = ParallelDecoder::new(ThreadPool::Std(1));
+    decoder.set_max_frames_in_flight(FRAMES);
+    let mut reader = decoder.reader(&compressed[..]);
+    let mut buf = vec![0u8; original.len()];
+    reader.read_exact(&mut buf).unwrap();
+    assert!(buf == original);
+
+    let start = Instant::now();
+    drop(reader);
+    let dropping = start.elapsed();
+    assert!(
+        dropping < one_frame * (FRAMES as u32 / 4),
+        "Dropping the reader took {:?}, decoding one frame {:?}",
+        dropping,
+        one_frame
+    );
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_parallel_decode_rayon() {
+    use crate::parallel_decoder::{ParallelDecoder, ThreadPool};
+    use std::sync::Arc;
+
+    let (compressed, expected, _) = corpus_frames();
+
+    let decoder = ParallelDecoder::new(ThreadPool::RayonGlobal);
+    assert!(decoder.decode_all(&compressed).unwrap() == expected);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(3)
+        .build()
+        .unwrap();
+    let decoder = ParallelDecoder::new(ThreadPool::Rayon(Arc::new(pool)));
+    assert!(decoder.decode_all(&compressed).unwrap() == expected);
+}
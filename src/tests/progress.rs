@@ -0,0 +1,108 @@
+#[test]
+fn test_progress_hook() {
+    use crate::decoding::progress::Progress;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+
+    let reports: Arc<Mutex<Vec<Progress>>> = Arc::default();
+    let mut frame_dec = FrameDecoder::new();
+    let hook_reports = Arc::clone(&reports);
+    frame_dec.set_progress_hook(Box::new(move |progress| {
+        hook_reports.lock().unwrap().push(*progress)
+    }));
+
+    for frame_index in 0..2 {
+        reports.lock().unwrap().clear();
+        let mut source = &compressed[..];
+        frame_dec.reset(&mut source).unwrap();
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), frame_dec.blocks_decoded());
+        for (idx, pair) in reports.windows(2).enumerate() {
+            assert_eq!(pair[0].blocks_decoded, idx + 1);
+            assert!(pair[0].bytes_read < pair[1].bytes_read);
+            assert!(pair[0].bytes_decoded <= pair[1].bytes_decoded);
+        }
+        let last = reports.last().unwrap();
+        assert_eq!(last.frame_index, frame_index);
+        assert_eq!(last.bytes_read, compressed.len() as u64);
+        assert_eq!(last.bytes_decoded, original.len() as u64);
+        assert_eq!(last.content_size, frame_dec.content_size());
+    }
+    assert!(frame_dec.take_progress_hook().is_some());
+}
+
+#[test]
+fn test_cancel_decoding() {
+    use crate::decoding::progress::CancellationToken;
+    use crate::errors::FrameDecoderError;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+
+    let token = CancellationToken::new();
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_cancellation_token(token.clone());
+    let hook_token = token.clone();
+    frame_dec.set_progress_hook(Box::new(move |progress| {
+        if progress.blocks_decoded == 2 {
+            hook_token.cancel();
+        }
+    }));
+
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    match frame_dec.decode_blocks(&mut source, BlockDecodingStrategy::All) {
+        Err(FrameDecoderError::Cancelled) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // stopped at the block boundary, so decoding can continue with a new token
+    assert_eq!(frame_dec.blocks_decoded(), 2);
+    assert!(token.is_cancelled());
+    frame_dec.set_cancellation_token(CancellationToken::new());
+    frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .unwrap();
+    assert!(frame_dec.collect().unwrap() == original);
+
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_cancellation_token(token);
+    let mut target = vec![0; original.len()];
+    match frame_dec.decode_from_to(&compressed, &mut target) {
+        Err(FrameDecoderError::Cancelled) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_cancel_streaming() {
+    use crate::decoding::progress::CancellationToken;
+    use crate::errors::FrameDecoderError;
+    use crate::streaming_decoder::StreamingDecoder;
+    use std::fs;
+    use std::io::Read;
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let mut stream = StreamingDecoder::new(&compressed[..]).unwrap();
+    let token = CancellationToken::new();
+    stream.decoder.set_cancellation_token(token.clone());
+    token.cancel();
+
+    let err = stream.read_to_end(&mut Vec::new()).unwrap_err();
+    match err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<FrameDecoderError>())
+    {
+        Some(FrameDecoderError::Cancelled) => {}
+        _ => panic!("unexpected error: {:?}", err),
+    }
+}
@@ -0,0 +1,269 @@
+#[test]
+fn test_xxh64_matches_twox_hash() {
+    use crate::decoding::xxhash64::Xxh64;
+    use std::hash::Hasher;
+
+    let data: Vec<u8> = (0..1000u32).map(|x| (x * 7 + x / 13) as u8).collect();
+    for len in (0..100).chain(990..1000) {
+        for split in &[0, 1, 3, 31, 32, 33, 64] {
+            let split = std::cmp::min(*split, len);
+            let mut ours = Xxh64::new();
+            ours.write(&data[..split]);
+            ours.write(&data[split..len]);
+            let mut theirs = twox_hash::XxHash64::with_seed(0);
+            theirs.write(&data[..len]);
+            assert_eq!(
+                ours.finish(),
+                theirs.finish(),
+                "len: {} split: {}",
+                len,
+                split
+            );
+        }
+    }
+}
+
+/// Decodes the frame block by block, taking a snapshot after every few blocks that a fresh decoder continues from
+#[cfg(test)]
+fn decode_with_snapshots(
+    compressed: &[u8],
+    original: &[u8],
+    every: usize,
+    new_decoder: &dyn Fn() -> crate::frame_decoder::FrameDecoder,
+) {
+    use crate::frame_decoder::BlockDecodingStrategy;
+
+    let mut frame_dec = new_decoder();
+    let mut source = compressed;
+    frame_dec.reset(&mut source).unwrap();
+    let mut result = Vec::new();
+
+    while !frame_dec.is_finished() {
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(every))
+            .unwrap();
+        if let Some(collected) = frame_dec.collect() {
+            result.extend(collected);
+        }
+
+        let snapshot = frame_dec.snapshot().unwrap();
+        frame_dec = new_decoder();
+        frame_dec.restore(&snapshot).unwrap();
+        source = &compressed[frame_dec.bytes_read_from_source() as usize..];
+    }
+    result.extend(frame_dec.collect().unwrap());
+
+    assert!(result == original);
+    if let Some(checksum) = frame_dec.get_checksum_from_data() {
+        assert_eq!(checksum, frame_dec.get_calculated_checksum().unwrap());
+    }
+}
+
+#[test]
+fn test_restore_snapshots() {
+    use crate::frame_decoder::FrameDecoder;
+    use std::fs;
+
+    let mut files: Vec<_> = fs::read_dir("./decodecorpus_files")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .collect();
+    files.sort();
+
+    for (idx, path) in files.iter().take(60).enumerate() {
+        let compressed = fs::read(path).unwrap();
+        let original = fs::read(path.with_extension("")).unwrap();
+        decode_with_snapshots(&compressed, &original, 1 + idx % 3, &FrameDecoder::new);
+    }
+}
+
+#[test]
+fn test_restore_snapshots_eager_output() {
+    use crate::frame_decoder::FrameDecoder;
+    use std::fs;
+
+    // the bytes that were collected but are still in the window must not be handed out again after restoring
+    let eager = || {
+        let mut frame_dec = FrameDecoder::new();
+        frame_dec.set_eager_output(true);
+        frame_dec
+    };
+    for name in &["z000033", "z000088"] {
+        let path = format!("./decodecorpus_files/{}", name);
+        let compressed = fs::read(format!("{}.zst", path)).unwrap();
+        let original = fs::read(path).unwrap();
+        decode_with_snapshots(&compressed, &original, 1, &eager);
+    }
+}
+
+#[test]
+fn test_restore_snapshots_with_dict() {
+    use crate::errors::SnapshotError;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let dict = fs::read("./dict_tests/dictionary").unwrap();
+    let with_dict = || {
+        let mut frame_dec = FrameDecoder::new();
+        frame_dec.add_dict(&dict).unwrap();
+        frame_dec
+    };
+
+    let mut files: Vec<_> = fs::read_dir("./dict_tests/files")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .collect();
+    files.sort();
+
+    for path in files.iter().take(30) {
+        let compressed = fs::read(path).unwrap();
+        let original = fs::read(path.with_extension("")).unwrap();
+        decode_with_snapshots(&compressed, &original, 1, &with_dict);
+    }
+
+    // the dictionary is not part of the snapshot
+    let compressed = fs::read(&files[0]).unwrap();
+    let mut frame_dec = with_dict();
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+        .unwrap();
+    let snapshot = frame_dec.snapshot().unwrap();
+    let mut without_dict = FrameDecoder::new();
+    match without_dict.restore(&snapshot) {
+        Err(SnapshotError::DictNotProvided { .. }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(without_dict.snapshot().is_err());
+}
+
+#[test]
+fn test_restore_snapshots_with_prefix() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let prefix = fs::read("./dict_tests/prefix/prefix").unwrap();
+    let compressed = fs::read("./dict_tests/prefix/data_prefix.zst").unwrap();
+    let original = fs::read("./dict_tests/prefix/data").unwrap();
+
+    // the prefix is part of the snapshot, the raw content dictionary with id 0 must not be used instead
+    let mut frame_dec = FrameDecoder::new();
+    let mut source = &compressed[..];
+    frame_dec.reset_with_prefix(&mut source, &prefix).unwrap();
+    let mut result = Vec::new();
+    loop {
+        let snapshot = frame_dec.snapshot().unwrap();
+        frame_dec = FrameDecoder::new();
+        frame_dec.add_raw_content_dict(0, &original);
+        frame_dec.restore(&snapshot).unwrap();
+        source = &compressed[frame_dec.bytes_read_from_source() as usize..];
+        if frame_dec.is_finished() {
+            break;
+        }
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+            .unwrap();
+        if let Some(collected) = frame_dec.collect() {
+            result.extend(collected);
+        }
+    }
+    assert!(result == original);
+    assert_eq!(
+        frame_dec.get_checksum_from_data(),
+        frame_dec.get_calculated_checksum()
+    );
+}
+
+#[test]
+fn test_restore_snapshots_of_later_frames() {
+    use crate::decoding::limits::OutputLimits;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    let compressed = fs::read("./decodecorpus_files/z000033.zst").unwrap();
+    let original = fs::read("./decodecorpus_files/z000033").unwrap();
+    // enough for the first frame but not the second one
+    let limits = OutputLimits {
+        max_stream_output: Some(original.len() as u64 * 2 - 1),
+        ..OutputLimits::default()
+    };
+
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec.set_output_limits(limits);
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .unwrap();
+    frame_dec.collect();
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+        .unwrap();
+    assert!(!frame_dec.is_finished());
+    let snapshot = frame_dec.snapshot().unwrap();
+
+    // restoring counts neither as a new frame nor forgets what the frames before decoded to
+    let frame_indices = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&frame_indices);
+    let mut restored = FrameDecoder::new();
+    restored.set_output_limits(limits);
+    restored.set_progress_hook(Box::new(move |progress| {
+        seen.lock().unwrap().push(progress.frame_index)
+    }));
+    restored.restore(&snapshot).unwrap();
+    assert_eq!(restored.snapshot().unwrap(), snapshot);
+    let mut source = &compressed[restored.bytes_read_from_source() as usize..];
+    assert!(restored
+        .decode_blocks(&mut source, BlockDecodingStrategy::All)
+        .is_err());
+    let frame_indices = frame_indices.lock().unwrap();
+    assert!(!frame_indices.is_empty());
+    assert!(frame_indices.iter().all(|idx| *idx == 1));
+}
+
+#[test]
+fn test_reject_broken_snapshots() {
+    use crate::errors::SnapshotError;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use std::fs;
+
+    let compressed = fs::read("./decodecorpus_files/z000088.zst").unwrap();
+    let mut frame_dec = FrameDecoder::new();
+    assert!(frame_dec.snapshot().is_err());
+    let mut source = &compressed[..];
+    frame_dec.reset(&mut source).unwrap();
+    frame_dec
+        .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+        .unwrap();
+    let snapshot = frame_dec.snapshot().unwrap();
+
+    let mut restored = FrameDecoder::new();
+    let mut flipped = snapshot.clone();
+    flipped[20] ^= 1;
+    match restored.restore(&flipped) {
+        Err(SnapshotError::ChecksumMismatch) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match restored.restore(&snapshot[..snapshot.len() / 2]) {
+        Err(SnapshotError::ChecksumMismatch) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let mut newer = snapshot.clone();
+    newer[4] = 3;
+    match restored.restore(&newer) {
+        Err(SnapshotError::UnsupportedVersion { got: 3, .. }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match restored.restore(&compressed) {
+        Err(SnapshotError::NotASnapshot) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    restored.restore(&snapshot).unwrap();
+    assert_eq!(restored.snapshot().unwrap(), snapshot);
+}
@@ -0,0 +1,112 @@
+#[test]
+fn test_workspace_decoder_corpus() {
+    use crate::frame;
+    use crate::workspace_decoder::{workspace_size, WorkspaceDecoder};
+    use std::fs;
+
+    let mut files: Vec<_> = fs::read_dir("./decodecorpus_files")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .collect();
+    files.sort();
+
+    let mut workspace = Vec::new();
+    for path in files {
+        let compressed = fs::read(&path).unwrap();
+        let expected = fs::read(path.with_extension("")).unwrap();
+
+        let (frame, _) = frame::read_frame_header(&mut &compressed[..]).unwrap();
+        let need = workspace_size(&frame.header).unwrap();
+        if workspace.len() < need {
+            workspace.resize(need, 0);
+        }
+
+        let mut decoder = WorkspaceDecoder::new(&compressed, &mut workspace).unwrap();
+        let mut result = Vec::new();
+        // odd sized reads so the blocks are returned in pieces
+        let mut buf = [0u8; 999];
+        loop {
+            let bytes_read = decoder.read(&mut buf).unwrap();
+            if bytes_read == 0 {
+                break;
+            }
+            result.extend_from_slice(&buf[..bytes_read]);
+        }
+
+        assert!(decoder.is_finished());
+        assert!(decoder.remaining_source().is_empty());
+        assert!(result == expected, "{:?} decoded differently", path);
+    }
+}
+
+#[test]
+fn test_workspace_decoder_workspace_too_small() {
+    use crate::errors::WorkspaceDecoderError;
+    use crate::frame;
+    use crate::workspace_decoder::{workspace_size, WorkspaceDecoder};
+
+    let compressed = std::fs::read("./decodecorpus_files/z000088.zst").unwrap();
+    let (frame, _) = frame::read_frame_header(&mut &compressed[..]).unwrap();
+    let need = workspace_size(&frame.header).unwrap();
+
+    let mut workspace = vec![0u8; need - 1];
+    match WorkspaceDecoder::new(&compressed, &mut workspace) {
+        Err(WorkspaceDecoderError::WorkspaceTooSmall { have, need: n }) => {
+            assert_eq!(have, need - 1);
+            assert_eq!(n, need);
+        }
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("Workspace should be too small"),
+    }
+}
+
+#[test]
+fn test_workspace_decoder_checksum_mismatch() {
+    use crate::errors::WorkspaceDecoderError;
+    use crate::workspace_decoder::WorkspaceDecoder;
+
+    let mut compressed = std::fs::read("./decodecorpus_files/z000088.zst").unwrap();
+    let (frame, _) = crate::frame::read_frame_header(&mut &compressed[..]).unwrap();
+    assert!(frame.header.descriptor.content_checksum_flag());
+    let last = compressed.len() - 1;
+    compressed[last] ^= 0xFF;
+
+    // the workspace does not need to be aligned
+    let mut workspace = vec![0u8; 1 << 24];
+    let mut decoder = WorkspaceDecoder::new(&compressed, &mut workspace[1..]).unwrap();
+    let mut buf = vec![0u8; 1 << 20];
+    let err = loop {
+        match decoder.read(&mut buf) {
+            Ok(0) => panic!("Checksum mismatch was not detected"),
+            Ok(_) => {}
+            Err(e) => break e,
+        }
+    };
+    match err {
+        WorkspaceDecoderError::ChecksumMismatch { .. } => {}
+        e => panic!("Unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_workspace_decoder_repeat_mode_without_table() {
+    use crate::errors::{DecodeSequenceError, WorkspaceDecoderError};
+    use crate::workspace_decoder::WorkspaceDecoder;
+
+    // raw literals "hello", then one sequence whose three tables are all in repeat mode
+    let content = [5 << 3, b'h', b'e', b'l', b'l', b'o', 1, 0xFC, 0x01];
+    let mut compressed = vec![0x28, 0xB5, 0x2F, 0xFD, 0x20, 100];
+    compressed.extend_from_slice(&[(content.len() as u8) << 3 | 2 << 1 | 1, 0, 0]);
+    compressed.extend_from_slice(&content);
+
+    let mut workspace = vec![0u8; 1 << 20];
+    let mut decoder = WorkspaceDecoder::new(&compressed, &mut workspace).unwrap();
+    match decoder.read(&mut [0u8; 16]) {
+        Err(WorkspaceDecoderError::FailedToDecodeSequences(
+            DecodeSequenceError::RepeatModeWithoutTable,
+        )) => {}
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("Repeat mode without a table was not detected"),
+    }
+}
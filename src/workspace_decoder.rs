@@ -0,0 +1,556 @@
+use crate::blocks::block::BlockType;
+use crate::blocks::literals_section::LiteralsSection;
+use crate::blocks::sequence_section::{
+    do_offset_history, SequencesHeader, LL_MAX_LOG, ML_MAX_LOG, OF_MAX_LOG,
+};
+use crate::decoding::literals_section_decoder::decode_literals_into;
+use crate::decoding::sequence_section_decoder::{
+    decode_sequences_from_tables, update_sequence_tables, SequenceTables,
+};
+use crate::decoding::xxhash64::Xxh64;
+use crate::errors::{FSETableError, HuffmanTableError, WorkspaceDecoderError};
+use crate::frame::{self, FrameHeader};
+use crate::fse::{self, FSEDecoder, FSETableStorage};
+use crate::huff0::{self, HuffmanDecodeTables, HuffmanTableStorage};
+use core::convert::TryInto;
+use core::hash::Hasher;
+use core::{cmp, mem, slice};
+
+/// Decodes a single frame without allocating. All buffers and tables live in a workspace provided by the caller,
+/// so this also works without the alloc feature, e.g. on targets without a global allocator.
+///
+/// The workspace holds:
+/// * the FSE tables for the sequences and for the huffman weights
+/// * the huffman table
+/// * a buffer for the literals of one block
+/// * the window plus room for one block
+///
+/// Sequences are executed right after they are decoded, so no buffer for them is needed.
+/// workspace_size() tells how big the workspace needs to be for a frame. Dictionaries are not supported.
+///
+/// ```
+/// use ruzstd::frame;
+/// use ruzstd::workspace_decoder::{workspace_size, WorkspaceDecoder};
+///
+/// fn decode_this(compressed: &[u8], workspace: &mut [u8]) {
+///     let (frame, _) = frame::read_frame_header(&mut &compressed[..]).unwrap();
+///     let need = workspace_size(&frame.header).unwrap();
+///     if workspace.len() < need {
+///         // WorkspaceDecoder::new() would fail with WorkspaceDecoderError::WorkspaceTooSmall
+///         return;
+///     }
+///
+///     let mut decoder = WorkspaceDecoder::new(compressed, workspace).unwrap();
+///     let mut buf = [0u8; 512];
+///     loop {
+///         let bytes_read = decoder.read(&mut buf).unwrap();
+///         if bytes_read == 0 {
+///             break;
+///         }
+///         do_something(&buf[..bytes_read]);
+///     }
+/// }
+///
+/// fn do_something(_data: &[u8]) {}
+/// ```
+pub struct WorkspaceDecoder<'s, 'w> {
+    header: FrameHeader,
+    /// What is left of the source after the parts that have been decoded
+    source: &'s [u8],
+
+    tables: Tables<'w>,
+    literals: &'w mut [u8],
+    /// Holds at least the last window_size decoded bytes and the block that is currently decoded
+    window: &'w mut [u8],
+    window_size: usize,
+    block_size_max: usize,
+    /// Bytes of decoded data in the window
+    filled: usize,
+    /// Bytes of window[..filled] that have already been returned by read()
+    drained: usize,
+
+    offset_hist: [u32; 3],
+    hasher: Xxh64,
+    last_block_decoded: bool,
+    checksum_verified: bool,
+}
+
+struct Tables<'w> {
+    literal_lengths: SequenceTable<'w>,
+    ll_rle: Option<u8>,
+    offsets: SequenceTable<'w>,
+    of_rle: Option<u8>,
+    match_lengths: SequenceTable<'w>,
+    ml_rle: Option<u8>,
+    huffman: HuffmanTable<'w>,
+}
+
+struct SequenceTable<'w> {
+    decode: &'w mut [fse::Entry],
+    /// 0 as long as no table has been built in this frame
+    accuracy_log: u8,
+}
+
+/// Has no double table, building one would need more workspace
+struct HuffmanTable<'w> {
+    /// Used to decode FSE compressed huffman weights
+    weights: &'w mut [fse::Entry],
+    decode: &'w mut [huff0::Entry],
+    /// 0 as long as no huffman table has been read in this frame
+    max_num_bits: u8,
+}
+
+/// No block decodes to more than this
+const ABSOLUTE_MAXIMUM_BLOCK_SIZE: usize = 128 * 1024;
+
+const LL_TABLE_SIZE: usize = 1 << LL_MAX_LOG;
+const OF_TABLE_SIZE: usize = 1 << OF_MAX_LOG;
+const ML_TABLE_SIZE: usize = 1 << ML_MAX_LOG;
+const WEIGHTS_TABLE_SIZE: usize = 1 << huff0::WEIGHTS_MAX_ACC_LOG;
+const FSE_ENTRIES: usize = LL_TABLE_SIZE + OF_TABLE_SIZE + ML_TABLE_SIZE + WEIGHTS_TABLE_SIZE;
+const HUFFMAN_ENTRIES: usize = 1 << huff0::MAX_MAX_NUM_BITS;
+
+/// The tables need the same amount of space for every frame. This includes the padding that may be needed to align them
+const TABLES_SIZE: usize = mem::align_of::<fse::Entry>() - 1
+    + FSE_ENTRIES * mem::size_of::<fse::Entry>()
+    + HUFFMAN_ENTRIES * mem::size_of::<huff0::Entry>();
+
+/// Returns how many bytes the workspace needs to decode the frame with this header.
+/// This depends on the window size of the frame, a few hundred KiB are needed at least.
+pub fn workspace_size(header: &FrameHeader) -> Result<usize, WorkspaceDecoderError> {
+    let window_size = window_size(header)?;
+    workspace_size_for_window(window_size)
+}
+
+fn window_size(header: &FrameHeader) -> Result<usize, WorkspaceDecoderError> {
+    let window_size = header.window_size()?;
+    if window_size > usize::max_value() as u64 {
+        return Err(WorkspaceDecoderError::WindowTooBig { window_size });
+    }
+    Ok(window_size as usize)
+}
+
+fn workspace_size_for_window(window_size: usize) -> Result<usize, WorkspaceDecoderError> {
+    let block_size_max = cmp::min(window_size, ABSOLUTE_MAXIMUM_BLOCK_SIZE);
+    // tables + literals + window + one block
+    window_size
+        .checked_add(TABLES_SIZE + 2 * block_size_max)
+        .ok_or(WorkspaceDecoderError::WindowTooBig {
+            window_size: window_size as u64,
+        })
+}
+
+impl<'s, 'w> WorkspaceDecoder<'s, 'w> {
+    /// Reads the frame header from the start of source and splits the workspace into the buffers and tables.
+    /// Fails with WorkspaceDecoderError::WorkspaceTooSmall if the frame needs more than workspace.len() bytes
+    pub fn new(
+        source: &'s [u8],
+        workspace: &'w mut [u8],
+    ) -> Result<WorkspaceDecoder<'s, 'w>, WorkspaceDecoderError> {
+        let mut rest = source;
+        let (frame, _) = frame::read_frame_header(&mut rest)?;
+        frame.check_valid()?;
+        match frame.header.dictiornary_id() {
+            Some(dict_id) if dict_id != 0 => {
+                return Err(WorkspaceDecoderError::DictNotSupported { dict_id })
+            }
+            _ => {}
+        }
+
+        let window_size = window_size(&frame.header)?;
+        let need = workspace_size_for_window(window_size)?;
+        if workspace.len() < need {
+            return Err(WorkspaceDecoderError::WorkspaceTooSmall {
+                have: workspace.len(),
+                need,
+            });
+        }
+
+        let block_size_max = cmp::min(window_size, ABSOLUTE_MAXIMUM_BLOCK_SIZE);
+        let (fse_entries, huffman, rest_of_workspace) = split_tables(workspace);
+        let (literals, rest_of_workspace) = rest_of_workspace.split_at_mut(block_size_max);
+        let window = &mut rest_of_workspace[..window_size + block_size_max];
+
+        let (literal_lengths, fse_entries) = fse_entries.split_at_mut(LL_TABLE_SIZE);
+        let (offsets, fse_entries) = fse_entries.split_at_mut(OF_TABLE_SIZE);
+        let (match_lengths, weights) = fse_entries.split_at_mut(ML_TABLE_SIZE);
+
+        Ok(WorkspaceDecoder {
+            header: frame.header,
+            source: rest,
+            tables: Tables {
+                literal_lengths: SequenceTable::new(literal_lengths),
+                ll_rle: None,
+                offsets: SequenceTable::new(offsets),
+                of_rle: None,
+                match_lengths: SequenceTable::new(match_lengths),
+                ml_rle: None,
+                huffman: HuffmanTable {
+                    weights,
+                    decode: huffman,
+                    max_num_bits: 0,
+                },
+            },
+            literals,
+            window,
+            window_size,
+            block_size_max,
+            filled: 0,
+            drained: 0,
+            offset_hist: [1, 4, 8],
+            hasher: Xxh64::new(),
+            last_block_decoded: false,
+            checksum_verified: false,
+        })
+    }
+
+    pub fn frame_header(&self) -> &FrameHeader {
+        &self.header
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    /// The part of the source after everything that has been decoded so far.
+    /// After the frame is finished this is whatever followed the frame in the source
+    pub fn remaining_source(&self) -> &'s [u8] {
+        self.source
+    }
+
+    /// Whether all blocks have been decoded and read and the checksum (if the frame has one) was verified
+    pub fn is_finished(&self) -> bool {
+        self.checksum_verified
+    }
+
+    /// Fills target with decoded bytes, decoding the next block if everything decoded so far has already been read.
+    /// Returns 0 if target is empty or the frame is finished
+    pub fn read(&mut self, target: &mut [u8]) -> Result<usize, WorkspaceDecoderError> {
+        if target.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.drained < self.filled {
+                let amount = cmp::min(target.len(), self.filled - self.drained);
+                target[..amount].copy_from_slice(&self.window[self.drained..self.drained + amount]);
+                self.drained += amount;
+                return Ok(amount);
+            }
+            if self.last_block_decoded {
+                self.verify_checksum()?;
+                return Ok(0);
+            }
+            self.decode_next_block()?;
+        }
+    }
+
+    fn verify_checksum(&mut self) -> Result<(), WorkspaceDecoderError> {
+        if self.checksum_verified {
+            return Ok(());
+        }
+        if self.header.descriptor.content_checksum_flag() {
+            let raw = take_bytes(&mut self.source, 4)?;
+            let from_data = u32::from_le_bytes(raw.try_into().expect("optimized away"));
+            let calculated = self.hasher.finish() as u32;
+            if from_data != calculated {
+                return Err(WorkspaceDecoderError::ChecksumMismatch {
+                    from_data,
+                    calculated,
+                });
+            }
+        }
+        self.checksum_verified = true;
+        Ok(())
+    }
+
+    /// Decodes the next block into the window. Everything decoded before must have been read already
+    fn decode_next_block(&mut self) -> Result<(), WorkspaceDecoderError> {
+        debug_assert!(self.drained == self.filled);
+        if self.filled + self.block_size_max > self.window.len() {
+            // only the last window_size bytes can be referenced by the next blocks
+            let keep = cmp::min(self.window_size, self.filled);
+            self.window.copy_within(self.filled - keep..self.filled, 0);
+            self.filled = keep;
+            self.drained = keep;
+        }
+
+        let raw_header = take_bytes(&mut self.source, 3)?;
+        let raw_header = u32::from(raw_header[0])
+            | u32::from(raw_header[1]) << 8
+            | u32::from(raw_header[2]) << 16;
+        let last_block = raw_header & 0x1 == 1;
+        let block_size = (raw_header >> 3) as usize;
+        if block_size > self.block_size_max {
+            return Err(WorkspaceDecoderError::BlockTooBig {
+                got: block_size,
+                max: self.block_size_max,
+            });
+        }
+
+        let block_start = self.filled;
+        match block_type((raw_header >> 1) & 0x3) {
+            BlockType::Raw => {
+                let content = take_bytes(&mut self.source, block_size)?;
+                self.window[block_start..block_start + block_size].copy_from_slice(content);
+                self.filled += block_size;
+            }
+            BlockType::RLE => {
+                let byte = take_bytes(&mut self.source, 1)?[0];
+                for x in &mut self.window[block_start..block_start + block_size] {
+                    *x = byte;
+                }
+                self.filled += block_size;
+            }
+            BlockType::Compressed => {
+                let content = take_bytes(&mut self.source, block_size)?;
+                self.decode_compressed_block(content)?;
+            }
+            BlockType::Reserved => return Err(WorkspaceDecoderError::ReservedBlockType),
+        }
+
+        if self.header.descriptor.content_checksum_flag() {
+            self.hasher.write(&self.window[block_start..self.filled]);
+        }
+        self.last_block_decoded = last_block;
+        Ok(())
+    }
+
+    fn decode_compressed_block(&mut self, content: &[u8]) -> Result<(), WorkspaceDecoderError> {
+        let mut section = LiteralsSection::new();
+        let header_size = section.parse_from_header(content)?;
+        let rest = &content[header_size as usize..];
+
+        let num_literals = section.regenerated_size as usize;
+        if num_literals > self.block_size_max {
+            return Err(WorkspaceDecoderError::BlockTooBig {
+                got: num_literals,
+                max: self.block_size_max,
+            });
+        }
+        let bytes_read = decode_literals_into(
+            &section,
+            &mut self.tables.huffman,
+            rest,
+            &mut self.literals[..num_literals],
+        )?;
+        let rest = &rest[bytes_read as usize..];
+
+        let mut sequences_header = SequencesHeader::new();
+        let header_size = sequences_header.parse_from_header(rest)?;
+        let rest = &rest[header_size as usize..];
+
+        let block_end = self.filled + self.block_size_max;
+        let mut output = Output {
+            window: &mut self.window[..block_end],
+            filled: self.filled,
+            max_offset: self.window_size,
+            literals: &self.literals[..num_literals],
+            literals_used: 0,
+        };
+
+        if sequences_header.num_sequences > 0 {
+            let mut tables = self.tables.sequence_tables();
+            let bytes_read = update_sequence_tables(&sequences_header, rest, &mut tables)?;
+            let offset_hist = &mut self.offset_hist;
+            decode_sequences_from_tables(&sequences_header, &rest[bytes_read..], &tables, |seq| {
+                let offset = do_offset_history(seq.of, seq.ll, offset_hist);
+                output.push_literals(seq.ll as usize)?;
+                output.repeat(offset as usize, seq.ml as usize)
+            })?;
+        }
+
+        let rest_literals = output.literals.len() - output.literals_used;
+        output.push_literals(rest_literals)?;
+        self.filled = output.filled;
+        Ok(())
+    }
+}
+
+impl<'w> SequenceTable<'w> {
+    fn new(decode: &'w mut [fse::Entry]) -> SequenceTable<'w> {
+        SequenceTable {
+            decode,
+            accuracy_log: 0,
+        }
+    }
+}
+
+impl<'w> FSETableStorage for SequenceTable<'w> {
+    fn build_decoder(&mut self, source: &[u8], max_log: u8) -> Result<usize, FSETableError> {
+        self.accuracy_log = 0;
+        let mut probs = [0; fse::MAX_SYMBOLS];
+        let (accuracy_log, num_symbols, bytes_read) =
+            fse::read_probabilities(source, max_log, &mut probs)?;
+        self.build_from_probabilities(accuracy_log, &probs[..num_symbols])?;
+        Ok(bytes_read)
+    }
+
+    fn build_from_probabilities(
+        &mut self,
+        acc_log: u8,
+        probs: &[i32],
+    ) -> Result<(), FSETableError> {
+        if acc_log == 0 {
+            return Err(FSETableError::AccLogIsZero);
+        }
+        fse::build_decoding_table(acc_log, probs, &mut self.decode[..1 << acc_log]);
+        self.accuracy_log = acc_log;
+        Ok(())
+    }
+
+    fn accuracy_log(&self) -> u8 {
+        self.accuracy_log
+    }
+
+    fn decoder(&self) -> FSEDecoder<'_> {
+        FSEDecoder::from_decode_table(&self.decode[..1 << self.accuracy_log], self.accuracy_log)
+    }
+}
+
+impl<'w> HuffmanTableStorage for HuffmanTable<'w> {
+    fn build_decoder(&mut self, source: &[u8]) -> Result<u32, HuffmanTableError> {
+        self.max_num_bits = 0;
+        let mut weights = [0; huff0::MAX_WEIGHTS];
+        let (num_weights, bytes_read) = huff0::read_weights(source, self.weights, &mut weights)?;
+        self.max_num_bits = huff0::build_decoding_table(&weights[..num_weights], self.decode)?;
+        Ok(bytes_read)
+    }
+
+    fn decode_tables(&mut self, _num_literals: usize) -> Option<HuffmanDecodeTables<'_>> {
+        if self.max_num_bits == 0 {
+            return None;
+        }
+        Some(HuffmanDecodeTables {
+            decode: &self.decode[..1 << self.max_num_bits],
+            decode_double: &[],
+            max_num_bits: self.max_num_bits,
+        })
+    }
+}
+
+impl<'w> Tables<'w> {
+    fn sequence_tables(&mut self) -> SequenceTables<'_, SequenceTable<'w>> {
+        SequenceTables {
+            literal_lengths: &mut self.literal_lengths,
+            ll_rle: &mut self.ll_rle,
+            offsets: &mut self.offsets,
+            of_rle: &mut self.of_rle,
+            match_lengths: &mut self.match_lengths,
+            ml_rle: &mut self.ml_rle,
+        }
+    }
+}
+
+/// The part of the window the current block is decoded into and the literals of the block
+struct Output<'a> {
+    /// Ends where the block would exceed the maximum block size
+    window: &'a mut [u8],
+    filled: usize,
+    max_offset: usize,
+    literals: &'a [u8],
+    literals_used: usize,
+}
+
+impl<'a> Output<'a> {
+    fn push_literals(&mut self, amount: usize) -> Result<(), WorkspaceDecoderError> {
+        let end = self.literals_used + amount;
+        if end > self.literals.len() {
+            return Err(WorkspaceDecoderError::NotEnoughLiterals {
+                need: end,
+                have: self.literals.len(),
+            });
+        }
+        self.reserve(amount)?;
+        self.window[self.filled..self.filled + amount]
+            .copy_from_slice(&self.literals[self.literals_used..end]);
+        self.literals_used = end;
+        self.filled += amount;
+        Ok(())
+    }
+
+    fn repeat(&mut self, offset: usize, match_length: usize) -> Result<(), WorkspaceDecoderError> {
+        if offset == 0 {
+            return Err(WorkspaceDecoderError::ZeroOffset);
+        }
+        let max = cmp::min(self.filled, self.max_offset);
+        if offset > max {
+            return Err(WorkspaceDecoderError::OffsetTooBig { offset, max });
+        }
+        self.reserve(match_length)?;
+
+        let start = self.filled - offset;
+        if offset >= match_length {
+            self.window
+                .copy_within(start..start + match_length, self.filled);
+        } else {
+            // the match overlaps with its own output, so it has to be copied byte by byte
+            for idx in 0..match_length {
+                self.window[self.filled + idx] = self.window[start + idx];
+            }
+        }
+        self.filled += match_length;
+        Ok(())
+    }
+
+    fn reserve(&self, amount: usize) -> Result<(), WorkspaceDecoderError> {
+        if self.filled + amount > self.window.len() {
+            return Err(WorkspaceDecoderError::BlockTooBig {
+                got: self.filled + amount,
+                max: self.window.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn block_type(raw: u32) -> BlockType {
+    match raw {
+        0 => BlockType::Raw,
+        1 => BlockType::RLE,
+        2 => BlockType::Compressed,
+        _ => BlockType::Reserved,
+    }
+}
+
+/// Splits off the first amount bytes of source
+fn take_bytes<'s>(source: &mut &'s [u8], amount: usize) -> Result<&'s [u8], WorkspaceDecoderError> {
+    if source.len() < amount {
+        return Err(WorkspaceDecoderError::NotEnoughBytes {
+            have: source.len(),
+            need: amount,
+        });
+    }
+    let (taken, rest) = source.split_at(amount);
+    *source = rest;
+    Ok(taken)
+}
+
+// split_tables() relies on the layout of the entries: fse::Entry is a u16 and two u8 and huff0::Entry two u8, both without padding
+const _: [(); 4] = [(); mem::size_of::<fse::Entry>()];
+const _: [(); 2] = [(); mem::align_of::<fse::Entry>()];
+const _: [(); 2] = [(); mem::size_of::<huff0::Entry>()];
+const _: [(); 1] = [(); mem::align_of::<huff0::Entry>()];
+
+/// Splits the tables off the start of the workspace, the rest of the workspace is returned as is.
+/// The workspace needs to be at least TABLES_SIZE bytes long
+fn split_tables(workspace: &mut [u8]) -> (&mut [fse::Entry], &mut [huff0::Entry], &mut [u8]) {
+    assert!(workspace.len() >= TABLES_SIZE);
+    let align = mem::align_of::<fse::Entry>();
+    let padding = (align - workspace.as_ptr() as usize % align) % align;
+    let fse_bytes = FSE_ENTRIES * mem::size_of::<fse::Entry>();
+    let huffman_bytes = HUFFMAN_ENTRIES * mem::size_of::<huff0::Entry>();
+
+    let (fse_entries, workspace) = workspace[padding..].split_at_mut(fse_bytes);
+    let (huffman, rest) = workspace.split_at_mut(huffman_bytes);
+    // The entries only consist of integers, so any initialized bytes are valid entries. They have no padding either,
+    // so writing entries leaves every byte of the workspace initialized. fse_entries starts aligned for fse::Entry.
+    // huffman starts at a multiple of the size of fse::Entry after that, which is aligned for huff0::Entry.
+    // Both byte slices are borrowed for as long as the returned entries, so they can not be used in another way meanwhile
+    let fse_entries = unsafe {
+        slice::from_raw_parts_mut(fse_entries.as_mut_ptr() as *mut fse::Entry, FSE_ENTRIES)
+    };
+    let huffman = unsafe {
+        slice::from_raw_parts_mut(huffman.as_mut_ptr() as *mut huff0::Entry, HUFFMAN_ENTRIES)
+    };
+    (fse_entries, huffman, rest)
+}
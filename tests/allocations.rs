@@ -0,0 +1,145 @@
+//! Counts the heap allocations of the FrameDecoder with a counting global allocator.
+//! This is its own test crate so the allocator does not replace the one of the other tests
+
+use ruzstd::{BlockDecodingStrategy, FrameDecoder};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Counts the heap allocations of each thread, so the tests running in parallel do not disturb each other
+struct CountingAllocator;
+
+thread_local! {
+    static HEAP_ALLOCATIONS: Cell<u64> = Cell::new(0);
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // the counter may already be gone while the thread shuts down
+        let _ = HEAP_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = HEAP_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn heap_allocations() -> u64 {
+    HEAP_ALLOCATIONS.with(|count| count.get())
+}
+
+fn zst_files(dir: &str) -> Vec<PathBuf> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zst"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Decodes the compressed frames and compares them to the originals, without allocating anything itself
+fn decode_all(frame_dec: &mut FrameDecoder, frames: &[(Vec<u8>, Vec<u8>)], buf: &mut [u8]) {
+    for (compressed, original) in frames {
+        let mut source = &compressed[..];
+        frame_dec.reset(&mut source).unwrap();
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+
+        let mut decoded = 0;
+        loop {
+            let read = frame_dec.read(buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            assert!(buf[..read] == original[decoded..decoded + read]);
+            decoded += read;
+        }
+        assert_eq!(decoded, original.len());
+    }
+}
+
+#[test]
+fn test_no_heap_allocations_when_reused() {
+    // frames with and without dictionary, so the dictionary tables are switched in and out
+    let mut files: Vec<_> = zst_files("./dict_tests/files")
+        .into_iter()
+        .take(50)
+        .collect();
+    files.extend(zst_files("./decodecorpus_files").into_iter().take(20));
+    let frames: Vec<_> = files
+        .iter()
+        .map(|path| {
+            let compressed = fs::read(path).unwrap();
+            let original = fs::read(path.with_extension("")).unwrap();
+            (compressed, original)
+        })
+        .collect();
+
+    let mut frame_dec = FrameDecoder::new();
+    frame_dec
+        .add_dict(&fs::read("./dict_tests/dictionary").unwrap())
+        .unwrap();
+    let mut buf = vec![0u8; 4096];
+
+    let heap_start = heap_allocations();
+    decode_all(&mut frame_dec, &frames, &mut buf);
+    assert!(heap_allocations() > heap_start);
+
+    let heap_before = heap_allocations();
+    decode_all(&mut frame_dec, &frames, &mut buf);
+    assert_eq!(heap_allocations(), heap_before);
+}
+
+#[test]
+fn test_collect_allocates_the_returned_vec() {
+    let compressed = fs::read("./decodecorpus_files/z000088.zst").unwrap();
+    let mut frame_dec = FrameDecoder::new();
+    for _ in 0..2 {
+        let mut source = &compressed[..];
+        frame_dec.reset(&mut source).unwrap();
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+    }
+
+    let heap_before = heap_allocations();
+    frame_dec.collect().unwrap();
+    assert_eq!(heap_allocations(), heap_before + 1);
+}
+
+#[test]
+fn test_prefix_is_not_copied_for_every_frame() {
+    let prefix = fs::read("./dict_tests/prefix/prefix").unwrap();
+    let compressed = fs::read("./dict_tests/prefix/data_prefix.zst").unwrap();
+    let original = fs::read("./dict_tests/prefix/data").unwrap();
+
+    let mut frame_dec = FrameDecoder::new();
+    let mut buf = vec![0u8; original.len()];
+    let mut decode = |frame_dec: &mut FrameDecoder| {
+        let mut source = &compressed[..];
+        frame_dec.reset_with_prefix(&mut source, &prefix).unwrap();
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+        assert_eq!(frame_dec.read(&mut buf).unwrap(), original.len());
+        assert!(buf == original);
+    };
+
+    decode(&mut frame_dec);
+    let heap_before = heap_allocations();
+    decode(&mut frame_dec);
+    assert_eq!(heap_allocations(), heap_before);
+}